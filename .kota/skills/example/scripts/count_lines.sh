@@ -0,0 +1,3 @@
+#!/usr/bin/env bash
+# Count the lines of the given files
+wc -l "$@"
@@ -0,0 +1,21 @@
+kota.register_tool({
+    name = "example_word_count",
+    description = "Count the words in a text",
+    parameters = {
+        type = "object",
+        properties = {
+            text = {
+                type = "string",
+                description = "The text to count"
+            }
+        },
+        required = { "text" }
+    },
+    entry = function(args)
+        local count = 0
+        for _ in string.gmatch(args.text, "%S+") do
+            count = count + 1
+        end
+        return { words = count }
+    end
+})
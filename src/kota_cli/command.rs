@@ -1,8 +1,17 @@
-use crate::kota_code::context::{ContextManager, SerializableMessage};
+use crate::kota_code::agent::retry::RetriesExhausted;
+use crate::kota_code::agent::{TurnCancelled, Undo, UndoIncomplete};
+use crate::kota_code::context::{session_tree, SerializableMessage};
+use crate::kota_code::search::SessionFilter;
+use crate::kota_code::skills::{AutoSelect, SkillScope};
+use crate::kota_code::store::SessionConflict;
+use crate::kota_code::usage::{BudgetExceeded, BudgetStatus};
 use anyhow::Result;
 use colored::*;
+use rig::completion::Message;
+use std::io::Write;
 
 use super::command_registry::parse_command_input;
+use super::render::EventPrinter;
 use super::KotaCli;
 
 impl KotaCli {
@@ -20,6 +29,26 @@ impl KotaCli {
             "/history" => {
                 self.show_history()?;
             }
+            "/cost" => {
+                self.show_cost()?;
+            }
+            "/compact" => {
+                self.compact_history().await?;
+            }
+            "/undo" => {
+                self.undo_turn()?;
+            }
+            "/memory" => {
+                self.show_memory()?;
+            }
+            _ if input.starts_with("/memory ") => {
+                let args = input.strip_prefix("/memory ").unwrap_or("").trim();
+                match args.split_whitespace().collect::<Vec<_>>().as_slice() {
+                    ["edit"] | ["edit", "project"] => self.edit_memory(false)?,
+                    ["edit", "global"] => self.edit_memory(true)?,
+                    _ => println!("{} Usage: /memory [edit [project|global]]", "❌".red()),
+                }
+            }
             "/skills" => {
                 self.list_skills()?;
             }
@@ -28,14 +57,57 @@ impl KotaCli {
                 self.activate_skill(skill_name)?;
             }
             "/skill-off" => {
-                self.deactivate_skill()?;
+                self.deactivate_skill(None)?;
+            }
+            "/skill-auto" => {
+                self.toggle_auto_skills(None)?;
+            }
+            _ if input.starts_with("/skill-auto ") => {
+                match input.strip_prefix("/skill-auto ").unwrap_or("").trim() {
+                    "on" => self.toggle_auto_skills(Some(true))?,
+                    "off" => self.toggle_auto_skills(Some(false))?,
+                    _ => println!("{} Usage: /skill-auto [on|off]", "❌".red()),
+                }
+            }
+            _ if input.starts_with("/skill-off ") => {
+                let skill_name = input.strip_prefix("/skill-off ").unwrap_or("").trim();
+                self.deactivate_skill(Some(skill_name))?;
             }
             _ if input.starts_with("/load ") => {
-                let session_id = input.strip_prefix("/load ").unwrap_or("").trim();
-                self.load_session(session_id)?;
+                let args = input.strip_prefix("/load ").unwrap_or("").trim();
+                self.load_command(args)?;
+            }
+            "/search" => {
+                println!("{} Usage: /search <query>", "❌".red());
+            }
+            _ if input.starts_with("/search ") => {
+                let query = input.strip_prefix("/search ").unwrap_or("").trim();
+                self.search_sessions(query)?;
             }
             _ if input.starts_with("/sessions") => {
-                self.list_sessions()?;
+                let args = input.strip_prefix("/sessions").unwrap_or("").trim();
+                self.list_sessions(args)?;
+            }
+            "/fork" => {
+                self.fork_session(None)?;
+            }
+            _ if input.starts_with("/fork ") => {
+                let number = input.strip_prefix("/fork ").unwrap_or("").trim();
+                match number.parse::<usize>() {
+                    Ok(number) if number > 0 => self.fork_session(Some(number))?,
+                    _ => println!("{} Usage: /fork [n]", "❌".red()),
+                }
+            }
+            _ if input.starts_with("/tag ") => {
+                let tags = input.strip_prefix("/tag ").unwrap_or("").trim();
+                self.tag_session(tags, true)?;
+            }
+            _ if input.starts_with("/untag ") => {
+                let tags = input.strip_prefix("/untag ").unwrap_or("").trim();
+                self.tag_session(tags, false)?;
+            }
+            "/tag" | "/untag" => {
+                println!("{} Usage: {} <tag>...", "❌".red(), input);
             }
             _ if input.starts_with("/delete ") => {
                 let session_id = input.strip_prefix("/delete ").unwrap_or("").trim();
@@ -74,7 +146,9 @@ impl KotaCli {
 
     fn show_config(&self) -> Result<()> {
         println!("{}", "⚙️  Current Configuration:".bright_cyan());
-        println!("  {} {}", "API Base:".bright_white(), self.api_base);
+        if let Some(api_base) = &self.api_base {
+            println!("  {} {}", "API Base:".bright_white(), api_base);
+        }
         println!("  {} {}", "Model:".bright_white(), self.model_name);
         println!(
             "  {} {}",
@@ -97,20 +171,63 @@ impl KotaCli {
             "  {} - Show conversation history",
             "/history".bright_green()
         );
+        println!(
+            "  {} - Show token usage and cost of this session",
+            "/cost".bright_green()
+        );
+        println!(
+            "  {} - Summarize the older messages of this session",
+            "/compact".bright_green()
+        );
+        println!(
+            "  {} - Remove the last turn and roll back the file and memory changes it made",
+            "/undo".bright_green()
+        );
+        println!(
+            "  {} - Show loaded KOTA.md memory files",
+            "/memory".bright_green()
+        );
+        println!(
+            "  {} - Edit the project or global KOTA.md",
+            "/memory edit [project|global]".bright_green()
+        );
         println!("  {} - List all available skills", "/skills".bright_green());
         println!(
-            "  {} - Activate a specific skill",
+            "  {} - Activate a skill and its dependencies",
             "/skill <name>".bright_green()
         );
         println!(
-            "  {} - Deactivate current skill",
-            "/skill-off".bright_green()
+            "  {} - Deactivate one skill, or all skills without a name",
+            "/skill-off [name]".bright_green()
+        );
+        println!(
+            "  {} - Pick matching skills for each message automatically",
+            "/skill-auto [on|off]".bright_green()
         );
         println!(
             "  {} - Load specific session",
             "/load <session_id>".bright_green()
         );
-        println!("  {} - List all sessions", "/sessions".bright_green());
+        println!(
+            "  {} - Jump to a message of a session or to a /search result",
+            "/load <session_id> <n> | /load #<k>".bright_green()
+        );
+        println!(
+            "  {} - List sessions, filtered by tag, update time, model or branch",
+            "/sessions [--tag t] [--since 7d] [--model m] [--branch b]".bright_green()
+        );
+        println!(
+            "  {} - Continue in a new session with the history up to message n",
+            "/fork [n]".bright_green()
+        );
+        println!(
+            "  {} - Add or remove tags of the current session",
+            "/tag <tag>... | /untag <tag>...".bright_green()
+        );
+        println!(
+            "  {} - Search the messages of all sessions",
+            "/search <query>".bright_green()
+        );
         println!(
             "  {} - Delete a specific session",
             "/delete <session_id>".bright_green()
@@ -168,66 +285,373 @@ impl KotaCli {
             println!();
 
             for (i, message) in messages.iter().enumerate() {
-                let serializable = SerializableMessage::from(message);
-                let role_color = match serializable.role.as_str() {
-                    "user" => "👤 User".bright_cyan(),
-                    "assistant" => "🤖 Assistant".bright_green(),
-                    _ => "❓ Unknown".bright_yellow(),
-                };
+                print_message(i + 1, message);
+            }
 
-                println!("{}. {}", (i + 1).to_string().bright_white(), role_color);
+            println!("{} Total messages: {}", "📊".bright_blue(), messages.len());
+        }
+        println!();
+        Ok(())
+    }
 
-                // 限制显示长度，避免输出过长
-                let content = if serializable.content.chars().count() > 200 {
-                    format!(
-                        "{}...",
-                        serializable.content.chars().take(200).collect::<String>()
-                    )
-                } else {
-                    serializable.content
-                };
+    async fn compact_history(&mut self) -> Result<()> {
+        if self.agent_instance.context().is_none() {
+            println!("{} Conversation history is not tracked", "🗜".bright_blue());
+            return Ok(());
+        }
+        println!("{} Summarizing the older messages...", "🗜".bright_blue());
+        match self.agent_instance.compact().await {
+            Ok(Some(compaction)) => {
+                println!(
+                    "{} Compacted {} messages into a summary",
+                    "✅".bright_green(),
+                    compaction.messages.to_string().bright_yellow()
+                );
+                println!(
+                    "  {} ~{} → ~{}",
+                    "History tokens:".bright_white(),
+                    compaction.tokens_before,
+                    compaction.tokens_after.to_string().bright_yellow()
+                );
+                println!(
+                    "  {}",
+                    "The original messages stay archived in the session file".dimmed()
+                );
+            }
+            Ok(None) => println!(
+                "{} Nothing to compact, the history only holds recent messages",
+                "💡".bright_blue()
+            ),
+            Err(e) if e.is::<SessionConflict>() => {
+                println!(
+                    "{} Compacted the history, but it is not saved yet",
+                    "⚠️".yellow()
+                );
+                self.save_session()?;
+            }
+            Err(e) => println!("{} Failed to compact the history: {:#}", "❌".red(), e),
+        }
+        Ok(())
+    }
 
-                // 缩进显示内容
-                for line in content.lines() {
-                    println!("   {}", line);
+    fn undo_turn(&mut self) -> Result<()> {
+        let undo = match self.agent_instance.undo() {
+            Ok(Some(undo)) => undo,
+            Ok(None) => {
+                println!("{} Nothing to undo", "💡".bright_blue());
+                return Ok(());
+            }
+            Err(e) => match e.downcast::<UndoIncomplete>() {
+                // 文件已经还原, 先报告再处理保存失败
+                Ok(incomplete) => {
+                    Self::print_undo(&incomplete.undo);
+                    if incomplete.error.is::<SessionConflict>() {
+                        println!("{} The undo is not saved yet", "⚠️".yellow());
+                        self.save_session()?;
+                    } else {
+                        println!("{} {:#}", "⚠️".yellow(), incomplete.error);
+                    }
+                    return Ok(());
+                }
+                Err(e) => {
+                    println!("{} Failed to undo the last turn: {:#}", "❌".red(), e);
+                    return Ok(());
+                }
+            },
+        };
+        Self::print_undo(&undo);
+        Ok(())
+    }
+
+    fn print_undo(undo: &Undo) {
+        println!(
+            "{} Undid the last turn, {} messages removed",
+            "↩️".bright_green(),
+            undo.messages.to_string().bright_yellow()
+        );
+        if let Some(request) = &undo.request {
+            let request: String = request.chars().take(80).collect();
+            println!("  {} {}", "Request:".bright_white(), request.dimmed());
+        }
+        for path in &undo.files.restored {
+            println!("  {} {}", "Restored".bright_green(), path.display());
+        }
+        for path in &undo.files.removed {
+            println!("  {} {}", "Removed".bright_green(), path.display());
+        }
+        for (path, error) in &undo.files.failed {
+            println!("  {} {}: {}", "Not restored".red(), path.display(), error);
+        }
+        if undo.files.is_empty() {
+            println!(
+                "  {}",
+                "No file changes were recorded for this turn".dimmed()
+            );
+        }
+    }
+
+    fn show_cost(&self) -> Result<()> {
+        let Some(usage) = self.agent_instance.session_usage() else {
+            println!("{} Token usage is not tracked", "📊".bright_blue());
+            return Ok(());
+        };
+
+        println!("{}", "📊 Session Usage:".bright_cyan());
+        println!(
+            "  {} {}",
+            "Turns:".bright_white(),
+            usage.turns.len().to_string().bright_yellow()
+        );
+        println!(
+            "  {} {} ({} cached)",
+            "Input tokens:".bright_white(),
+            usage.total.input_tokens.to_string().bright_yellow(),
+            usage.total.cached_input_tokens
+        );
+        println!(
+            "  {} {}",
+            "Output tokens:".bright_white(),
+            usage.total.output_tokens.to_string().bright_yellow()
+        );
+        println!(
+            "  {} {}",
+            "Total tokens:".bright_white(),
+            usage.total.total_tokens().to_string().bright_yellow()
+        );
+        match self.agent_instance.session_cost() {
+            Some(cost) => println!("  {} ${:.4}", "Estimated cost:".bright_white(), cost),
+            None => println!(
+                "  {} {}",
+                "Estimated cost:".bright_white(),
+                "unknown (add the model to `prices` in kota.setup)".dimmed()
+            ),
+        }
+
+        if let Some(budget) = &self.agent_instance.budget {
+            if let Some(max_cost) = budget.max_cost {
+                println!("  {} ${:.2}", "Cost budget:".bright_white(), max_cost);
+            }
+            if let Some(max_tokens) = budget.max_tokens {
+                println!("  {} {} tokens", "Token budget:".bright_white(), max_tokens);
+            }
+            self.print_budget_status();
+        }
+        println!();
+        Ok(())
+    }
+
+    fn show_memory(&mut self) -> Result<()> {
+        // 重新读取, 包括 remember 工具在本次会话中写入的内容
+        if let Err(e) = self.agent_instance.reload_memory() {
+            println!("{} Failed to reload memory: {}", "⚠️".yellow(), e);
+        }
+        let memory = &self.agent_instance.memory;
+
+        if memory.files.is_empty() {
+            println!("{} No KOTA.md memory files loaded", "🧠".bright_blue());
+        } else {
+            println!(
+                "{} Memory files (later ones take precedence):",
+                "🧠".bright_blue()
+            );
+            println!();
+            for (i, file) in memory.files.iter().enumerate() {
+                println!(
+                    "{}. {} ({}, {} lines)",
+                    (i + 1).to_string().bright_white(),
+                    file.path.display().to_string().bright_cyan(),
+                    file.scope.label(),
+                    file.content.lines().count().to_string().bright_yellow()
+                );
+                for line in file.content.lines() {
+                    println!("   {}", line.dimmed());
                 }
                 println!();
             }
+        }
+        println!(
+            "{} Use '/memory edit' or '/memory edit global' to edit memory",
+            "💡".bright_blue()
+        );
+        Ok(())
+    }
 
-            println!("{} Total messages: {}", "📊".bright_blue(), messages.len());
+    fn edit_memory(&mut self, global: bool) -> Result<()> {
+        let path = if global {
+            self.agent_instance.memory.global_file()
+        } else {
+            Some(self.agent_instance.memory.project_file())
+        };
+        let Some(path) = path else {
+            println!("{} Home directory is unknown", "❌".red());
+            return Ok(());
+        };
+
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, "# Memory\n\n")?;
+        }
+
+        let editor = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| {
+                if cfg!(windows) {
+                    "notepad".to_string()
+                } else {
+                    "vi".to_string()
+                }
+            });
+        let mut parts = editor.split_whitespace();
+        let program = parts.next().unwrap_or("vi");
+        match std::process::Command::new(program)
+            .args(parts)
+            .arg(&path)
+            .status()
+        {
+            Ok(status) if status.success() => {
+                self.agent_instance.reload_memory()?;
+                println!(
+                    "{} Memory updated: {}",
+                    "✅".bright_green(),
+                    path.display().to_string().bright_cyan()
+                );
+            }
+            Ok(status) => {
+                println!("{} Editor exited with {}", "⚠️".yellow(), status);
+            }
+            Err(e) => {
+                println!("{} Failed to start editor '{}': {}", "❌".red(), program, e);
+                println!(
+                    "{} Set $EDITOR or edit {} directly",
+                    "💡".bright_blue(),
+                    path.display()
+                );
+            }
         }
-        println!();
         Ok(())
     }
 
-    fn list_sessions(&self) -> Result<()> {
+    /// Warn when the session gets close to or over its budget
+    fn print_budget_status(&self) {
+        match self.agent_instance.budget_status() {
+            BudgetStatus::Ok => {}
+            BudgetStatus::Warning { used } => println!(
+                "{} {:.0}% of the session budget used",
+                "⚠️".yellow(),
+                used * 100.0
+            ),
+            BudgetStatus::Exceeded { used } => println!(
+                "{} Session budget exceeded ({:.0}% used), new turns will be refused",
+                "⛔".red(),
+                used * 100.0
+            ),
+        }
+    }
+
+    /// `/sessions [filters]`, see [`SessionFilter::parse`]
+    fn list_sessions(&self, args: &str) -> Result<()> {
+        let filter = match SessionFilter::parse(args) {
+            Ok(filter) => filter,
+            Err(e) => {
+                println!("{} {}", "❌".red(), e);
+                println!(
+                    "{} Usage: /sessions [--tag <tag>] [--since <2024-05-01|12h|7d|2w>] [--model <name>] [--branch <name>]",
+                    "💡".bright_blue()
+                );
+                return Ok(());
+            }
+        };
         let context = self
             .agent_instance
             .context()
             .expect("Context manager not initialized");
-        match context.list_sessions() {
+        match context.find_sessions(&filter) {
             Ok(sessions) => {
                 if sessions.is_empty() {
-                    println!("{} No saved sessions found", "📁".bright_blue());
+                    if filter.is_empty() {
+                        println!("{} No saved sessions found", "📁".bright_blue());
+                    } else {
+                        println!("{} No sessions match the filter", "📁".bright_blue());
+                    }
                 } else {
                     println!("{} Available Sessions:", "📁".bright_blue());
                     println!();
 
-                    for (i, session) in sessions.iter().enumerate() {
+                    // 分叉和子代理会话缩进显示在父会话下面
+                    for (i, (depth, session)) in session_tree(sessions).iter().enumerate() {
                         let current_marker = if session.session_id == context.session_id() {
                             " (current)".bright_green()
                         } else {
                             "".normal()
                         };
+                        let indent = "   ".repeat(*depth);
+                        let branch = if *depth > 0 { "└─ " } else { "" };
 
                         println!(
-                            "{}. {} - {} messages{}",
+                            "{}{}{}. {} - {} messages{}",
+                            indent,
+                            branch.dimmed(),
                             (i + 1).to_string().bright_white(),
                             session.session_id.bright_cyan(),
                             session.message_count.to_string().bright_yellow(),
                             current_marker
                         );
-                        println!("   Last updated: {}", session.last_updated.dimmed());
+                        let indent = format!("{}{}   ", indent, " ".repeat(branch.chars().count()));
+                        if let Some(title) = &session.title {
+                            println!("{}{}", indent, title.bright_white());
+                        }
+                        println!(
+                            "{}Created: {}  Last updated: {}",
+                            indent,
+                            session.created_at.dimmed(),
+                            session.last_updated.dimmed()
+                        );
+                        let mut details = Vec::new();
+                        if let Some(model) = &session.model {
+                            details.push(format!("model {}", model));
+                        }
+                        if session.total_tokens() > 0 {
+                            details.push(format!("{} tokens", session.total_tokens()));
+                        }
+                        if let Some(branch) = &session.git_branch {
+                            details.push(format!("branch {}", branch));
+                        }
+                        if !details.is_empty() {
+                            println!("{}{}", indent, details.join(", ").dimmed());
+                        }
+                        if let Some(dir) = &session.working_dir {
+                            println!(
+                                "{}Directory: {}",
+                                indent,
+                                dir.display().to_string().dimmed()
+                            );
+                        }
+                        if !session.active_skills.is_empty() {
+                            println!(
+                                "{}Skills: {}",
+                                indent,
+                                session.active_skills.join(", ").dimmed()
+                            );
+                        }
+                        if !session.tags.is_empty() {
+                            let tags: Vec<String> =
+                                session.tags.iter().map(|tag| format!("#{}", tag)).collect();
+                            println!("{}Tags: {}", indent, tags.join(" ").bright_magenta());
+                        }
+                        match (&session.parent_session_id, session.forked_at) {
+                            (Some(parent), Some(messages)) => println!(
+                                "{}Forked from: {} after message {}",
+                                indent,
+                                parent.dimmed(),
+                                messages
+                            ),
+                            (Some(parent), None) => {
+                                println!("{}Delegated from: {}", indent, parent.dimmed())
+                            }
+                            _ => {}
+                        }
                     }
 
                     println!();
@@ -245,20 +669,203 @@ impl KotaCli {
         Ok(())
     }
 
-    fn load_session(&mut self, session_id: &str) -> Result<()> {
+    /// `/fork [n]`, `n` is numbered as in `/history`, the whole history without it
+    fn fork_session(&mut self, number: Option<usize>) -> Result<()> {
+        // 先保存当前会话, 分叉后切换到新会话; 不覆盖时分叉仍然包含内存中的消息
+        self.save_session()?;
         let context = self
             .agent_instance
             .context_mut()
             .expect("Context manager not initialized");
+        let Some(index) = number
+            .map(|number| number - 1)
+            .or_else(|| context.get_messages().len().checked_sub(1))
+        else {
+            println!("{} Nothing to fork, the session is empty", "❌".red());
+            return Ok(());
+        };
 
-        // 保存当前会话
-        if let Err(e) = context.save() {
+        let parent = context.session_id().to_string();
+        match context.fork(index, context.next_fork_id()) {
+            Ok(fork) => {
+                println!(
+                    "{} Forked {} into {} with {} messages",
+                    "🌿".bright_green(),
+                    parent.bright_cyan(),
+                    fork.session_id().bright_cyan(),
+                    fork.get_messages().len().to_string().bright_yellow()
+                );
+                println!(
+                    "{} Use '/load {}' to go back to the original session",
+                    "💡".bright_blue(),
+                    parent
+                );
+                *context = fork;
+            }
+            Err(e) => println!("{} Failed to fork session: {}", "❌".red(), e),
+        }
+        Ok(())
+    }
+
+    /// `/tag` and `/untag`, the session is saved right away
+    fn tag_session(&mut self, tags: &str, add: bool) -> Result<()> {
+        let context = self
+            .agent_instance
+            .context_mut()
+            .expect("Context manager not initialized");
+        for tag in tags
+            .split_whitespace()
+            .map(|tag| tag.trim_start_matches('#'))
+        {
+            if tag.is_empty() {
+                continue;
+            }
+            let changed = if add {
+                context.add_tag(tag)
+            } else {
+                context.remove_tag(tag)
+            };
+            match (add, changed) {
+                (true, true) => println!("{} Tagged session with #{}", "🏷️".bright_blue(), tag),
+                (true, false) => println!("{} Session already has #{}", "💡".bright_blue(), tag),
+                (false, true) => println!("{} Removed #{}", "🏷️".bright_blue(), tag),
+                (false, false) => println!("{} Session has no tag #{}", "❌".red(), tag),
+            }
+        }
+        self.save_session()?;
+        Ok(())
+    }
+
+    /// `/load <session_id> [n]` or `/load #<k>` for the k-th `/search` result
+    fn load_command(&mut self, args: &str) -> Result<()> {
+        if let Some(result) = args.strip_prefix('#') {
+            let selected = result
+                .parse::<usize>()
+                .ok()
+                .and_then(|k| k.checked_sub(1))
+                .and_then(|k| self.search_results.get(k).cloned());
+            match selected {
+                Some((session_id, index)) => {
+                    if self.load_session(&session_id)? {
+                        self.show_message(index);
+                    }
+                }
+                None => println!(
+                    "{} No search result {}, run /search <query> first",
+                    "❌".red(),
+                    args
+                ),
+            }
+            return Ok(());
+        }
+
+        let mut parts = args.split_whitespace();
+        let session_id = parts.next().unwrap_or("");
+        match parts.next().map(str::parse::<usize>) {
+            None => {
+                self.load_session(session_id)?;
+            }
+            Some(Ok(number)) if number > 0 => {
+                if self.load_session(session_id)? {
+                    self.show_message(number - 1);
+                }
+            }
+            Some(_) => println!("{} Usage: /load <session_id> [message number]", "❌".red()),
+        }
+        Ok(())
+    }
+
+    /// Print the message at `index` of the current session with its neighbours
+    fn show_message(&self, index: usize) {
+        let Some(context) = self.agent_instance.context() else {
+            return;
+        };
+        let messages = context.get_messages();
+        if index >= messages.len() {
+            println!("{} The session has no message {}", "❌".red(), index + 1);
+            return;
+        }
+        let start = index.saturating_sub(1);
+        let end = (index + 2).min(messages.len());
+        for (i, message) in messages[start..end].iter().enumerate() {
+            print_message(start + i + 1, message);
+        }
+    }
+
+    fn search_sessions(&mut self, query: &str) -> Result<()> {
+        let context = self
+            .agent_instance
+            .context()
+            .expect("Context manager not initialized");
+        let results = match context.search_sessions(query) {
+            Ok(results) => results,
+            Err(e) => {
+                println!("{} Failed to search sessions: {}", "❌".red(), e);
+                return Ok(());
+            }
+        };
+
+        self.search_results.clear();
+        if results.is_empty() {
             println!(
+                "{} No session mentions '{}'",
+                "🔍".bright_blue(),
+                query.bright_white()
+            );
+            return Ok(());
+        }
+
+        println!(
+            "{} Sessions matching '{}':",
+            "🔍".bright_blue(),
+            query.bright_white()
+        );
+        println!();
+        for session in results.iter().take(10) {
+            println!(
+                "{} - {} matching messages, last updated {}",
+                session.session_id.bright_cyan(),
+                session.matching_messages.to_string().bright_yellow(),
+                session.last_updated.dimmed()
+            );
+            for hit in session.hits.iter().take(3) {
+                self.search_results
+                    .push((session.session_id.clone(), hit.index));
+                println!(
+                    "  {} message {} ({}): {}",
+                    format!("#{}", self.search_results.len()).bright_green(),
+                    hit.index + 1,
+                    hit.role,
+                    hit.snippet
+                );
+            }
+            println!();
+        }
+        println!(
+            "{} Use '/load #<k>' to open a result, or '/load <session_id> <n>' for message n",
+            "💡".bright_blue()
+        );
+        Ok(())
+    }
+
+    /// Save the current session and switch to `session_id`
+    ///
+    /// Returns `false` when the current session was left unsaved, it stays open then.
+    fn load_session(&mut self, session_id: &str) -> Result<bool> {
+        // 保存当前会话, 用户拒绝覆盖时留在当前会话, 以免丢掉未保存的消息
+        match self.save_session() {
+            Ok(true) => {}
+            Ok(false) => return Ok(false),
+            Err(e) => println!(
                 "{} Warning: Failed to save current session: {}",
                 "⚠️".yellow(),
                 e
-            );
+            ),
         }
+        let context = self
+            .agent_instance
+            .context_mut()
+            .expect("Context manager not initialized");
 
         // 切换到新会话
         context.switch_session(session_id.to_string());
@@ -292,7 +899,45 @@ impl KotaCli {
             }
         }
         println!();
-        Ok(())
+        Ok(true)
+    }
+
+    /// Save the current session, asking before overwriting changes made by another process
+    ///
+    /// Returns `false` when the session was left unsaved.
+    fn save_session(&mut self) -> Result<bool> {
+        let context = self
+            .agent_instance
+            .context_mut()
+            .expect("Context manager not initialized");
+        let conflict = match context.save() {
+            Ok(()) => return Ok(true),
+            Err(e) => e.downcast::<SessionConflict>()?,
+        };
+
+        println!("{} {}", "⚠️".yellow(), conflict);
+        if conflict.found.is_none() {
+            println!("   It has been deleted since");
+        }
+        print!("   Overwrite it with this session? [y/N] ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        self.input.read_line(&mut answer)?;
+        if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            context.force_save()?;
+            println!(
+                "{} Saved session {}",
+                "💾".bright_green(),
+                conflict.session_id.bright_cyan()
+            );
+            return Ok(true);
+        }
+        println!(
+            "{} Not saved; '/fork' keeps this conversation as a new session, '/load {}' switches to the saved one",
+            "💡".bright_blue(),
+            conflict.session_id
+        );
+        Ok(false)
     }
 
     fn delete_session(&mut self, session_id: &str) -> Result<()> {
@@ -307,10 +952,7 @@ impl KotaCli {
             return Ok(());
         }
 
-        // 创建临时上下文管理器来删除指定会话
-        let temp_context = ContextManager::new("./.chat_sessions", session_id.to_string())?;
-
-        match temp_context.delete_session() {
+        match context.store().delete(session_id) {
             Ok(true) => {
                 println!(
                     "{} Successfully deleted session: {}",
@@ -339,6 +981,7 @@ impl KotaCli {
     }
 
     fn list_skills(&mut self) -> Result<()> {
+        self.agent_instance.refresh_skills();
         let skill_manager = self
             .agent_instance
             .skill_manager()
@@ -352,23 +995,49 @@ impl KotaCli {
             println!();
 
             for (i, skill) in skills.iter().enumerate() {
-                let active_marker = if skill_manager
-                    .get_active_skill()
-                    .map(|s| s.name == skill.name)
-                    .unwrap_or(false)
-                {
+                let active_marker = if skill_manager.is_active(&skill.name) {
                     " (active)".bright_green()
                 } else {
                     "".normal()
                 };
 
+                let mut details = skill
+                    .version
+                    .as_ref()
+                    .map(|version| format!(" v{}", version))
+                    .unwrap_or_default();
+                if let Some(scope) = skill.scope.filter(|s| *s != SkillScope::Project) {
+                    details.push_str(&format!(" [{}]", scope.label()));
+                }
                 println!(
-                    "{}. {}{}",
+                    "{}. {}{}{}",
                     (i + 1).to_string().bright_white(),
                     skill.name.bright_cyan(),
+                    details.dimmed(),
                     active_marker
                 );
                 println!("   {}", skill.description.dimmed());
+                if !skill.dependencies.is_empty() {
+                    println!(
+                        "   {}",
+                        format!("Requires: {}", skill.dependencies.join(", ")).dimmed()
+                    );
+                }
+                if let Some(model) = &skill.model {
+                    println!("   {}", format!("Model: {}", model).dimmed());
+                }
+                if !skill.allowed_tools.is_empty() {
+                    println!(
+                        "   {}",
+                        format!("Tools: {}", skill.allowed_tools.join(", ")).dimmed()
+                    );
+                }
+                if !skill.disallowed_tools.is_empty() {
+                    println!(
+                        "   {}",
+                        format!("Without tools: {}", skill.disallowed_tools.join(", ")).dimmed()
+                    );
+                }
                 println!();
             }
 
@@ -387,7 +1056,7 @@ impl KotaCli {
             .skill_manager_mut()
             .expect("Skill manager not initialized");
         match skill_manager.activate_skill(skill_name) {
-            Ok(_) => {
+            Ok(activated) => {
                 println!(
                     "{} Activated skill: {}",
                     "✅".bright_green(),
@@ -396,6 +1065,17 @@ impl KotaCli {
                 if let Some(skill) = skill_manager.get_skill(skill_name) {
                     println!("   {}", skill.description.dimmed());
                 }
+                let dependencies: Vec<_> = activated
+                    .iter()
+                    .filter(|name| name.as_str() != skill_name)
+                    .map(|name| name.as_str())
+                    .collect();
+                if !dependencies.is_empty() {
+                    println!(
+                        "   {}",
+                        format!("Also activated: {}", dependencies.join(", ")).dimmed()
+                    );
+                }
                 println!(
                     "{} Skill will be applied to next message",
                     "💡".bright_blue()
@@ -413,13 +1093,48 @@ impl KotaCli {
         Ok(())
     }
 
-    fn deactivate_skill(&mut self) -> Result<()> {
+    fn deactivate_skill(&mut self, skill_name: Option<&str>) -> Result<()> {
         let skill_manager = self
             .agent_instance
             .skill_manager_mut()
             .expect("Skill manager not initialized");
-        skill_manager.deactivate_skill();
-        println!("{} Skill deactivated", "✅".bright_green());
+        match skill_name {
+            Some(name) => match skill_manager.deactivate_skill(name) {
+                Ok(()) => println!(
+                    "{} Deactivated skill: {}",
+                    "✅".bright_green(),
+                    name.bright_cyan()
+                ),
+                Err(e) => println!("{} Failed to deactivate skill: {}", "❌".red(), e),
+            },
+            None => {
+                skill_manager.deactivate_all_skills();
+                println!("{} All skills deactivated", "✅".bright_green());
+            }
+        }
+        println!();
+        Ok(())
+    }
+
+    fn toggle_auto_skills(&mut self, enable: Option<bool>) -> Result<()> {
+        let skill_manager = self
+            .agent_instance
+            .skill_manager_mut()
+            .expect("Skill manager not initialized");
+        let enable = enable.unwrap_or(skill_manager.auto_select().is_none());
+        if enable {
+            if skill_manager.auto_select().is_none() {
+                skill_manager.set_auto_select(Some(AutoSelect::default()));
+            }
+            println!("{} Automatic skill selection enabled", "✅".bright_green());
+            println!(
+                "{} Matching skills are applied to each message only",
+                "💡".bright_blue()
+            );
+        } else {
+            skill_manager.set_auto_select(None);
+            println!("{} Automatic skill selection disabled", "✅".bright_green());
+        }
         println!();
         Ok(())
     }
@@ -468,27 +1183,97 @@ impl KotaCli {
         println!("{}", "🧠 Thinking...".yellow());
         println!("{}", "● kota:".blue());
 
-        let response_result = self.agent_instance.chat(input).await;
+        // Ctrl-C 只取消当前这一轮, 不退出程序
+        // 先准备本轮的令牌再监听, 开始前按下的 Ctrl-C 也不会丢失
+        let cancel = self.agent_instance.cancel_signal();
+        cancel.reset();
+        let ctrl_c = tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
+            }
+        });
+
+        let mut printer = EventPrinter::default();
+        let response_result = self
+            .agent_instance
+            .chat_with_events(input, |event| printer.print(event))
+            .await;
+        ctrl_c.abort();
 
         println!();
 
         match response_result {
             Ok(resp) => {
+                let usage = resp.usage();
+                let session_tokens = self
+                    .agent_instance
+                    .session_usage()
+                    .map(|usage| usage.total.total_tokens())
+                    .unwrap_or(usage.total_tokens);
                 println!(
-                    "{} Total tokens used: {}",
+                    "{} Tokens: {} in ({} cached), {} out · session total: {}",
                     "📊".bright_blue(),
-                    resp.usage().total_tokens
+                    usage.input_tokens,
+                    usage.cached_input_tokens,
+                    usage.output_tokens,
+                    session_tokens
                 );
+                self.print_budget_status();
             }
-            Err(e) => {
+            Err(e) if e.is::<TurnCancelled>() => {
+                println!("{} Interrupted", "⏹".yellow());
+            }
+            Err(e) if e.is::<SessionConflict>() => {
+                self.save_session()?;
+            }
+            Err(e) if e.is::<BudgetExceeded>() => {
+                println!("{} {}", "⛔".red(), e);
+                println!(
+                    "{} Use /cost to see the usage of this session",
+                    "💡".bright_blue()
+                );
+            }
+            Err(e) if e.is::<RetriesExhausted>() => {
                 println!("{} Failed to get AI response: {}", "❌".red(), e);
                 println!(
-                    "{} Please check your API key and network connection",
+                    "{} Retries and fallback models were exhausted; check your network connection and provider status",
                     "💡".bright_blue()
                 );
             }
+            Err(e) => {
+                println!("{} Failed to get AI response: {}", "❌".red(), e);
+            }
         }
 
         Ok(())
     }
 }
+
+/// Print one message of the history, numbered like `/history`
+fn print_message(number: usize, message: &Message) {
+    let serializable = SerializableMessage::from(message);
+    let role_color = match serializable.role.as_str() {
+        "user" => "👤 User".bright_cyan(),
+        "assistant" => "🤖 Assistant".bright_green(),
+        "tool" => "🔧 Tool".bright_magenta(),
+        _ => "❓ Unknown".bright_yellow(),
+    };
+
+    println!("{}. {}", number.to_string().bright_white(), role_color);
+
+    // 限制显示长度，避免输出过长
+    let content = if serializable.content.chars().count() > 200 {
+        format!(
+            "{}...",
+            serializable.content.chars().take(200).collect::<String>()
+        )
+    } else {
+        serializable.content
+    };
+
+    // 缩进显示内容
+    for line in content.lines() {
+        println!("   {}", line);
+    }
+    println!();
+}
@@ -0,0 +1,300 @@
+//! Non-interactive one-shot mode
+//!
+//! `kota -p "prompt"` runs a single prompt through the full tool loop and exits,
+//! which makes kota usable from CI jobs and git hooks. The prompt can also be
+//! piped on stdin.
+
+use anyhow::Result;
+use rig::completion::Usage;
+use serde::Serialize;
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::kota_code::agent::{AgentEvent, AgentInstance, TurnCancelled};
+
+/// Exit code for a successful run
+pub const EXIT_SUCCESS: i32 = 0;
+/// Exit code when the agent fails, e.g. a provider error or the turn limit was hit
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code for invalid command line arguments
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code when the run is interrupted with Ctrl-C
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Output format of headless mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Assistant text streamed as plain text
+    #[default]
+    Text,
+    /// A single JSON report printed when the run finishes
+    Json,
+    /// One JSON object per line for every event, followed by the report
+    StreamJson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "stream-json" => Ok(OutputFormat::StreamJson),
+            _ => Err(anyhow::anyhow!(
+                "Unknown output format: {}. Supported formats: text, json, stream-json",
+                s
+            )),
+        }
+    }
+}
+
+/// Parsed command line arguments
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CliArgs {
+    /// Run one prompt and exit instead of starting the REPL
+    pub headless: bool,
+    /// Prompt given on the command line, read from stdin when `None`
+    pub prompt: Option<String>,
+    pub output_format: OutputFormat,
+    pub help: bool,
+}
+
+impl CliArgs {
+    /// Parse arguments, excluding the program name
+    ///
+    /// `--output-format` implies headless mode. Positional arguments are joined
+    /// into the prompt.
+    pub fn parse<I, S>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut parsed = CliArgs::default();
+        let mut words = Vec::new();
+        let mut args = args.into_iter().map(Into::into);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => parsed.help = true,
+                "-p" | "--print" => parsed.headless = true,
+                "--output-format" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("--output-format requires a value"))?;
+                    parsed.output_format = value.parse()?;
+                    parsed.headless = true;
+                }
+                _ if arg.starts_with("--output-format=") => {
+                    parsed.output_format = arg["--output-format=".len()..].parse()?;
+                    parsed.headless = true;
+                }
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(anyhow::anyhow!("Unknown option: {}", arg));
+                }
+                _ => words.push(arg),
+            }
+        }
+
+        if !words.is_empty() {
+            if !parsed.headless {
+                return Err(anyhow::anyhow!(
+                    "A prompt argument requires -p/--print, e.g. kota -p \"{}\"",
+                    words.join(" ")
+                ));
+            }
+            // `-` explicitly asks for the prompt on stdin
+            if words != ["-"] {
+                parsed.prompt = Some(words.join(" "));
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn usage() -> &'static str {
+        r#"Usage: kota [OPTIONS] [PROMPT]
+
+Starts the interactive REPL when no options are given.
+
+Options:
+  -p, --print                 Run PROMPT (or stdin) through the agent once and exit
+      --output-format <FMT>   Output format in headless mode: text, json, stream-json
+  -h, --help                  Print this help
+
+Exit codes: 0 success, 1 agent error, 2 invalid arguments, 130 interrupted"#
+    }
+}
+
+/// A tool call made during a headless run
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolCallRecord {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+    /// Tool output, `None` when the run stopped before the tool finished
+    pub result: Option<String>,
+    /// The tool failed and `result` holds its error
+    pub is_error: bool,
+}
+
+/// Summary of a headless run, printed by the JSON output formats
+#[derive(Debug, Clone, Serialize)]
+pub struct HeadlessReport {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    pub success: bool,
+    pub session_id: Option<String>,
+    /// Final assistant text
+    pub result: String,
+    pub error: Option<String>,
+    pub interrupted: bool,
+    pub tool_calls: Vec<ToolCallRecord>,
+    /// Files written, edited, deleted or created by the file tools
+    pub files_touched: Vec<String>,
+    pub usage: Usage,
+}
+
+impl HeadlessReport {
+    /// Process exit code for this run
+    pub fn exit_code(&self) -> i32 {
+        if self.success {
+            EXIT_SUCCESS
+        } else if self.interrupted {
+            EXIT_INTERRUPTED
+        } else {
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Run one prompt through the agent and write the output to `out`
+///
+/// Agent failures are reported in the returned [`HeadlessReport`]; only
+/// failures to write the output are returned as errors.
+pub async fn run_headless<W: Write>(
+    agent: &mut AgentInstance,
+    prompt: &str,
+    format: OutputFormat,
+    out: &mut W,
+) -> Result<HeadlessReport> {
+    let mut tool_calls: Vec<ToolCallRecord> = Vec::new();
+    let mut usage = Usage::new();
+    let mut text = String::new();
+    let mut write_error = None;
+
+    let result = agent
+        .chat_with_events(prompt, |event| {
+            match event {
+                AgentEvent::TextDelta { text: delta } => text.push_str(delta),
+                AgentEvent::ToolCallStart {
+                    id,
+                    name,
+                    arguments,
+                    ..
+                } => tool_calls.push(ToolCallRecord {
+                    id: id.clone(),
+                    name: name.clone(),
+                    arguments: arguments.clone(),
+                    result: None,
+                    is_error: false,
+                }),
+                AgentEvent::ToolResult {
+                    id,
+                    content,
+                    is_error,
+                    ..
+                } => {
+                    if let Some(call) = tool_calls.iter_mut().rev().find(|c| &c.id == id) {
+                        call.result = Some(content.clone());
+                        call.is_error = *is_error;
+                    }
+                }
+                AgentEvent::Usage(event_usage) => usage += *event_usage,
+                _ => {}
+            }
+
+            let written = match (format, event) {
+                (OutputFormat::Text, AgentEvent::TextDelta { text }) => {
+                    write!(out, "{}", text).and_then(|_| out.flush())
+                }
+                (OutputFormat::StreamJson, event) => serde_json::to_string(event)
+                    .map_err(std::io::Error::from)
+                    .and_then(|line| writeln!(out, "{}", line))
+                    .and_then(|_| out.flush()),
+                _ => Ok(()),
+            };
+            if let Err(e) = written {
+                write_error.get_or_insert(e);
+            }
+        })
+        .await;
+
+    if let Some(e) = write_error {
+        return Err(e.into());
+    }
+
+    let needs_newline = !text.is_empty() && !text.ends_with('\n');
+    let files_touched = files_touched(&tool_calls);
+    let session_id = agent.context().map(|c| c.session_id().to_string());
+    let report = match result {
+        Ok(response) => HeadlessReport {
+            kind: "result",
+            success: true,
+            session_id,
+            result: response.response().to_string(),
+            error: None,
+            interrupted: false,
+            tool_calls,
+            files_touched,
+            usage: response.usage(),
+        },
+        Err(e) => HeadlessReport {
+            kind: "result",
+            success: false,
+            session_id,
+            result: text,
+            interrupted: e.is::<TurnCancelled>(),
+            error: Some(e.to_string()),
+            tool_calls,
+            files_touched,
+            usage,
+        },
+    };
+
+    match format {
+        OutputFormat::Text => {
+            if needs_newline {
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?,
+        OutputFormat::StreamJson => writeln!(out, "{}", serde_json::to_string(&report)?)?,
+    }
+    out.flush()?;
+
+    Ok(report)
+}
+
+/// Paths passed to the file tools, in first-touched order
+///
+/// Only calls that finished successfully count, a failed edit touched nothing.
+fn files_touched(tool_calls: &[ToolCallRecord]) -> Vec<String> {
+    let mut files: Vec<String> = Vec::new();
+    for call in tool_calls {
+        if call.result.is_none() || call.is_error {
+            continue;
+        }
+        let key = match call.name.as_str() {
+            "write_file" | "edit_file" | "delete_file" => "file_path",
+            "make_dir" => "dir_path",
+            _ => continue,
+        };
+        if let Some(path) = call.arguments.get(key).and_then(|p| p.as_str()) {
+            if !files.iter().any(|f| f == path) {
+                files.push(path.to_string());
+            }
+        }
+    }
+    files
+}
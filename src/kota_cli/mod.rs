@@ -1,13 +1,16 @@
 use crate::kota_code::agent::{AgentBuilder, AgentInstance};
 use crate::kota_code::context::ContextManager;
+use crate::kota_code::runtime::KotaConfig;
 use crate::kota_code::skills::SkillManager;
 use anyhow::Result;
 use colored::*;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::io::BufRead;
 
 mod command;
 pub mod command_registry;
+pub mod headless;
 mod render;
 mod tab;
 
@@ -26,35 +29,53 @@ const LOGO: &str = r#"
 
 pub struct KotaCli {
     pub agent_instance: AgentInstance,
-    pub api_base: String,
+    pub api_base: Option<String>,
     pub model_name: String,
     pub api_key: String,
     pub command_registry: Option<CommandRegistry>,
+    /// Session and message index of each result of the last `/search`
+    search_results: Vec<(String, usize)>,
+    /// Answers to confirmation prompts, stdin unless set with [`KotaCli::with_input`]
+    input: Box<dyn BufRead + Send>,
 }
 
 impl KotaCli {
     pub fn new(
-        api_key: String,
-        api_base: String,
-        model_name: String,
+        config: &KotaConfig,
         context: ContextManager,
         skill_manager: SkillManager,
         command_registry: Option<CommandRegistry>,
     ) -> Result<Self> {
-        let agent_instance = AgentBuilder::new(api_key.clone(), model_name.clone())?
+        let agent_instance = AgentBuilder::from_config(config)?
             .with_context(context)
             .with_skill_manager(skill_manager)
             .build()?;
 
         Ok(Self {
-            agent_instance,
-            api_base,
-            model_name,
-            api_key,
             command_registry,
+            ..Self::from_agent(agent_instance, config)
         })
     }
 
+    /// CLI around an agent that is already built, without custom commands
+    pub fn from_agent(agent_instance: AgentInstance, config: &KotaConfig) -> Self {
+        Self {
+            agent_instance,
+            api_base: config.api_base.clone(),
+            model_name: config.model.clone(),
+            api_key: config.api_key.clone(),
+            command_registry: None,
+            search_results: Vec::new(),
+            input: Box::new(std::io::BufReader::new(std::io::stdin())),
+        }
+    }
+
+    /// Read the answers to confirmation prompts from `input` instead of stdin
+    pub fn with_input<R: BufRead + Send + 'static>(mut self, input: R) -> Self {
+        self.input = Box::new(input);
+        self
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         println!("{}", LOGO);
         self.show_welcome()?;
@@ -1,7 +1,63 @@
 use anyhow::Result;
 use colored::*;
+use std::io::Write;
 
 use super::KotaCli;
+use crate::kota_code::agent::AgentEvent;
+
+/// Prints streamed agent events to the terminal
+#[derive(Default)]
+pub struct EventPrinter {
+    streamed_reasoning: bool,
+}
+
+impl EventPrinter {
+    pub fn print(&mut self, event: &AgentEvent) {
+        match event {
+            AgentEvent::SkillsSelected { skills } => {
+                for skill in skills {
+                    println!(
+                        "{} Auto-applied skill {}: {}",
+                        "🎯".bright_blue(),
+                        skill.name.bright_cyan(),
+                        skill.reason().dimmed()
+                    );
+                }
+                println!();
+            }
+            AgentEvent::Compacted(compaction) => {
+                println!(
+                    "{} Compacted {} earlier messages into a summary (~{} → ~{} tokens)",
+                    "🗜".bright_blue(),
+                    compaction.messages,
+                    compaction.tokens_before,
+                    compaction.tokens_after
+                );
+                println!();
+            }
+            AgentEvent::TextDelta { text } => {
+                print!("{}", text);
+            }
+            AgentEvent::ReasoningDelta { reasoning } => {
+                self.streamed_reasoning = true;
+                print!("{}", reasoning.dimmed());
+            }
+            AgentEvent::Reasoning { reasoning, .. } => {
+                // Providers that stream deltas send the complete block afterwards
+                if !self.streamed_reasoning {
+                    print!("{}", reasoning.dimmed());
+                }
+                self.streamed_reasoning = false;
+            }
+            // Built-in tools print their own progress when they run
+            AgentEvent::ToolCallStart { .. }
+            | AgentEvent::ToolResult { .. }
+            | AgentEvent::Usage(_)
+            | AgentEvent::FinalResponse(_) => {}
+        }
+        let _ = std::io::stdout().flush();
+    }
+}
 
 impl KotaCli {
     pub fn show_welcome(&self) -> Result<()> {
@@ -1,5 +1,5 @@
 use colored::*;
-use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
 use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::{Hinter, HistoryHinter};
@@ -9,7 +9,6 @@ use std::borrow::Cow::{self, Borrowed, Owned};
 use std::collections::HashSet;
 
 pub struct KotaHelper {
-    completer: FilenameCompleter,
     highlighter: MatchingBracketHighlighter,
     validator: MatchingBracketValidator,
     hinter: HistoryHinter,
@@ -24,15 +23,23 @@ impl Default for KotaHelper {
         commands.insert("/config".to_string());
         commands.insert("/help".to_string());
         commands.insert("/history".to_string());
+        commands.insert("/cost".to_string());
+        commands.insert("/compact".to_string());
+        commands.insert("/undo".to_string());
+        commands.insert("/memory".to_string());
         commands.insert("/skills".to_string());
         commands.insert("/skill".to_string());
         commands.insert("/skill-off".to_string());
+        commands.insert("/skill-auto".to_string());
         commands.insert("/load".to_string());
         commands.insert("/sessions".to_string());
+        commands.insert("/search".to_string());
+        commands.insert("/fork".to_string());
+        commands.insert("/tag".to_string());
+        commands.insert("/untag".to_string());
         commands.insert("/delete".to_string());
 
         Self {
-            completer: FilenameCompleter::new(),
             highlighter: MatchingBracketHighlighter::new(),
             validator: MatchingBracketValidator::new(),
             hinter: HistoryHinter {},
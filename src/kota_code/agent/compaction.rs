@@ -0,0 +1,136 @@
+//! Compaction of long conversations
+//!
+//! Older messages are summarized by the model into one structured message that
+//! replaces them in the history. The original messages stay archived in the
+//! session file.
+
+use anyhow::{bail, Result};
+use rig::completion::message::AssistantContent;
+use rig::completion::{CompletionModel, Message};
+use serde::Serialize;
+
+use super::{AgentInstance, AgentType};
+use crate::kota_code::context::SerializableMessage;
+use crate::kota_code::usage::TokenUsage;
+
+/// System prompt of the summarization request
+const COMPACTION_PREAMBLE: &str = "You compact the history of a conversation between a user and \
+Kota, an AI code assistant. Write a summary that lets Kota continue the work without the \
+original messages. Use these sections and keep every file path, command, identifier and \
+decision that may matter later:
+
+## Goal
+## Decisions and constraints
+## Files and code
+## Progress
+## Open questions and next steps
+
+Answer with the summary only.";
+
+/// Tool outputs longer than this are cut in the transcript sent for summarization
+const MAX_TOOL_OUTPUT_CHARS: usize = 2_000;
+
+/// Result of compacting the history
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Compaction {
+    /// Messages replaced by the summary
+    pub messages: usize,
+    /// Estimated tokens of the history before compaction
+    pub tokens_before: usize,
+    /// Estimated tokens of the history after compaction
+    pub tokens_after: usize,
+    /// Tokens used by the summarization request
+    pub usage: TokenUsage,
+}
+
+impl AgentInstance {
+    /// Summarize the older part of the history
+    ///
+    /// The messages before [`ContextManager::compaction_end`] are replaced by a
+    /// summary written by the model, and the session is saved.
+    ///
+    /// [`ContextManager::compaction_end`]: crate::kota_code::context::ContextManager::compaction_end
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` without a context manager or when there is nothing to compact
+    pub async fn compact(&mut self) -> Result<Option<Compaction>> {
+        let Some(context) = self.context() else {
+            return Ok(None);
+        };
+        let Some(end) = context.compaction_end() else {
+            return Ok(None);
+        };
+        let tokens_before = context.estimated_tokens();
+        let transcript = render_transcript(&context.get_messages()[..end]);
+
+        let (summary, usage) = self.summarize(&transcript).await?;
+        let model_name = self.effective_model();
+        let Some(context) = self.context_mut() else {
+            return Ok(None);
+        };
+        let messages = context.compact(end, &summary);
+        context.record_usage(&model_name, usage);
+        context.save()?;
+        Ok(Some(Compaction {
+            messages,
+            tokens_before,
+            tokens_after: context.estimated_tokens(),
+            usage,
+        }))
+    }
+
+    /// Ask the model for a summary of `transcript`, without tools
+    async fn summarize(&self, transcript: &str) -> Result<(String, TokenUsage)> {
+        let prompt = format!(
+            "Summarize this conversation:\n\n<conversation>\n{}\n</conversation>",
+            transcript
+        );
+        let (choice, usage) = dispatch_agent!(&self.agent, agent => {
+            let response = agent
+                .model
+                .completion_request(Message::user(prompt))
+                .preamble(COMPACTION_PREAMBLE.to_string())
+                .max_tokens_opt(agent.max_tokens)
+                .send()
+                .await?;
+            (response.choice, response.usage)
+        });
+
+        let summary = choice
+            .iter()
+            .filter_map(|content| match content {
+                AssistantContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if summary.trim().is_empty() {
+            bail!("The model returned an empty summary");
+        }
+        Ok((summary, TokenUsage::from(usage)))
+    }
+}
+
+/// Plain text transcript of `messages` for the summarization request
+fn render_transcript(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let message = SerializableMessage::from(message);
+            let content = match message.role.as_str() {
+                "tool" if message.content.chars().count() > MAX_TOOL_OUTPUT_CHARS => format!(
+                    "{}... [truncated]",
+                    message
+                        .content
+                        .chars()
+                        .take(MAX_TOOL_OUTPUT_CHARS)
+                        .collect::<String>()
+                ),
+                _ => message.content,
+            };
+            format!("[{}]\n{}", message.role, content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
@@ -0,0 +1,228 @@
+//! Sub-agents spawned by the `delegate_task` tool
+//!
+//! A [`SubAgentFactory`] remembers how the parent agent was built, so a child
+//! [`AgentInstance`] can be created on demand with the same provider and settings
+//! but a fresh [`ContextManager`]. Child transcripts are stored next to the
+//! parent's sessions in the same store and linked to it through `parent_session_id`.
+
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+use super::mock::MockCompletionModel;
+use super::retry::RetryPolicy;
+use super::{AgentBuilder, AgentInstance, GenerationSettings, Provider};
+use crate::kota_code::context::ContextManager;
+use crate::kota_code::file_history::FileHistory;
+use crate::kota_code::memory::ProjectMemory;
+use crate::kota_code::runtime::{CancelSignal, PromptLoader};
+use crate::kota_code::skills::SkillManager;
+use crate::kota_code::store::SessionStore;
+use crate::kota_code::usage::{Budget, PriceTable, SessionUsage, TokenUsage};
+
+/// Appended to every delegated task, the child's last answer is all the parent sees
+const SUMMARY_INSTRUCTIONS: &str =
+    "You are a sub-agent working on a task delegated by another agent. \
+When you are done, answer with a concise summary of what you did and what you found; \
+it is the only part of this conversation the other agent will see.";
+
+/// A task handed to a sub-agent
+#[derive(Debug, Clone, Default)]
+pub struct SubAgentTask {
+    /// What the sub-agent should do
+    pub task: String,
+    /// Names of the tools the sub-agent may use, all built-in tools when `None`
+    pub tools: Option<Vec<String>>,
+    /// Skill activated for the sub-agent
+    pub skill: Option<String>,
+}
+
+/// Outcome of a delegated task
+#[derive(Debug, Clone)]
+pub struct SubAgentResult {
+    /// Final answer of the sub-agent
+    pub summary: String,
+    /// Session holding the sub-agent's transcript, `None` when the parent has no context
+    pub session_id: Option<String>,
+    /// Tokens used by the sub-agent
+    pub usage: TokenUsage,
+}
+
+/// Builds sub-agents that share the parent agent's provider and settings
+#[derive(Clone)]
+pub struct SubAgentFactory {
+    provider: Provider,
+    api_key: String,
+    api_base: Option<String>,
+    model_name: String,
+    mock_model: Option<MockCompletionModel>,
+    preamble: Option<String>,
+    prompts: PromptLoader,
+    memory: Option<ProjectMemory>,
+    settings: GenerationSettings,
+    retry_policy: RetryPolicy,
+    fallback_models: Vec<String>,
+    prices: PriceTable,
+    tool_progress: bool,
+    skills: Option<SkillManager>,
+    /// Store of the parent's sessions, sub-agents without one have no context
+    store: Option<Arc<dyn SessionStore>>,
+    /// File changes of sub-agents are undone with the parent's turn
+    file_history: FileHistory,
+    /// Signal of the parent agent, cancelling its turn cancels the running sub-agents
+    cancel: CancelSignal,
+    /// Session of the parent agent, kept up to date when the parent switches sessions
+    parent_session: Arc<Mutex<Option<String>>>,
+    /// Budget left to the parent's session when its current turn started
+    budget: Arc<Mutex<Option<Budget>>>,
+    /// Usage of sub-agents the parent has not recorded yet
+    usage: Arc<Mutex<SessionUsage>>,
+}
+
+impl SubAgentFactory {
+    /// Snapshot the configuration of `builder` for later sub-agents
+    pub(super) fn from_builder(
+        builder: &AgentBuilder,
+        mock_model: Option<MockCompletionModel>,
+    ) -> Self {
+        Self {
+            provider: builder.provider.clone(),
+            api_key: builder.api_key.clone(),
+            api_base: builder.api_base.clone(),
+            model_name: builder.model_name.clone(),
+            mock_model,
+            preamble: builder.preamble.clone(),
+            prompts: builder.prompts.clone(),
+            memory: builder.memory.clone(),
+            settings: builder.settings.clone(),
+            retry_policy: builder.retry_policy.clone(),
+            fallback_models: builder.fallback_models.clone(),
+            prices: builder.prices.clone(),
+            tool_progress: builder.tool_progress,
+            skills: builder.skill_manager.clone(),
+            store: builder
+                .context
+                .as_ref()
+                .map(|context| context.store().clone()),
+            file_history: builder.file_history.nested(),
+            cancel: builder.cancel.clone(),
+            parent_session: Arc::new(Mutex::new(
+                builder
+                    .context
+                    .as_ref()
+                    .map(|context| context.session_id().to_string()),
+            )),
+            budget: Arc::default(),
+            usage: Arc::default(),
+        }
+    }
+
+    /// Record the session new sub-agents are linked to
+    pub fn set_parent_session(&self, session_id: &str) {
+        *self.parent_session.lock().unwrap() = Some(session_id.to_string());
+    }
+
+    /// Session new sub-agents are linked to
+    pub fn parent_session(&self) -> Option<String> {
+        self.parent_session.lock().unwrap().clone()
+    }
+
+    /// Record the budget left to the parent's session, `None` for no limit
+    pub fn set_budget(&self, budget: Option<Budget>) {
+        *self.budget.lock().unwrap() = budget;
+    }
+
+    /// Take the usage of the sub-agents run since the last call
+    ///
+    /// The parent records it in its own session, so delegated work shows up in
+    /// `/cost` and counts against the session budget.
+    pub fn take_usage(&self) -> SessionUsage {
+        std::mem::take(&mut *self.usage.lock().unwrap())
+    }
+
+    /// Build a sub-agent for `task` without running it
+    ///
+    /// Sub-agents cannot delegate again, so `delegate_task` is never available to them.
+    /// The sub-agent is cancelled together with the parent's running turn and
+    /// may only spend what is left of the parent's budget.
+    pub fn build(&self, task: &SubAgentTask) -> Result<AgentInstance> {
+        let mut builder = AgentBuilder::for_provider(
+            self.provider.clone(),
+            self.api_key.clone(),
+            self.model_name.clone(),
+        )
+        .with_settings(self.settings.clone())
+        .with_retry_policy(self.retry_policy.clone())
+        .with_fallback_models(self.fallback_models.clone())
+        .with_price_table(self.prices.clone())
+        .with_tool_progress(self.tool_progress)
+        .with_file_history(self.file_history.clone())
+        .with_cancel_signal(self.cancel.child())
+        .with_sub_agents(false);
+        builder.mock_model = self.mock_model.clone();
+        builder.preamble = self.preamble.clone();
+        builder.prompts = self.prompts.clone();
+        builder.memory = self.memory.clone();
+        if let Some(api_base) = &self.api_base {
+            builder = builder.with_api_base(api_base.clone());
+        }
+        if let Some(budget) = self.budget.lock().unwrap().as_ref() {
+            // 同一轮中先前子代理的用量尚未计入父会话
+            let pending = self.usage.lock().unwrap();
+            builder = builder.with_budget(budget.remaining(&pending, &self.prices));
+        }
+        if let Some(tools) = &task.tools {
+            builder = builder.with_allowed_tools(tools.clone());
+        }
+
+        if let Some(skill) = &task.skill {
+            let mut skills = self
+                .skills
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("Skills are not available to sub-agents"))?;
+            // 子代理只使用任务指定的 skill, 不继承父代理激活的 skill
+            skills.deactivate_all_skills();
+            skills.activate_skill(skill)?;
+            builder = builder.with_skill_manager(skills);
+        }
+
+        if let Some(store) = &self.store {
+            let parent = self
+                .parent_session()
+                .unwrap_or_else(|| "unknown".to_string());
+            let session_id = self.next_session_id(store.as_ref(), &parent);
+            let context =
+                ContextManager::from_store(store.clone(), session_id).with_parent_session(parent);
+            builder = builder.with_context(context);
+        }
+
+        builder.build()
+    }
+
+    /// Run `task` to completion in a new sub-agent
+    pub async fn run(&self, task: &SubAgentTask) -> Result<SubAgentResult> {
+        let mut agent = self.build(task)?;
+        let prompt = format!("{}\n\n{}", task.task, SUMMARY_INSTRUCTIONS);
+        let result = agent.chat(&prompt).await;
+        // 失败或被取消的子代理同样消耗了 token
+        if let Some(usage) = agent.session_usage() {
+            let mut pending = self.usage.lock().unwrap();
+            for turn in &usage.turns {
+                pending.record(&turn.model, turn.usage);
+            }
+        }
+        let response = result?;
+        Ok(SubAgentResult {
+            summary: response.response().to_string(),
+            session_id: agent.context().map(|c| c.session_id().to_string()),
+            usage: TokenUsage::from(response.usage()),
+        })
+    }
+
+    /// First unused `<parent>-task-<n>` session id
+    fn next_session_id(&self, store: &dyn SessionStore, parent: &str) -> String {
+        (1..)
+            .map(|n| format!("{}-task-{}", parent, n))
+            .find(|id| !store.exists(id).unwrap_or(false))
+            .unwrap()
+    }
+}
@@ -0,0 +1,271 @@
+//! Typed events emitted while the agent streams a response
+//!
+//! [`AgentInstance::stream_events`](super::AgentInstance::stream_events) turns the
+//! provider-specific rig stream into a single [`AgentEventStream`], so embedders can
+//! render, capture or forward the output instead of having it printed to stdout.
+
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use rig::agent::{
+    FinalResponse, HookAction, MultiTurnStreamItem, StreamingPromptHook, StreamingResult,
+    ToolCallHookAction,
+};
+use rig::completion::{message::ToolResultContent, CompletionModel, GetTokenUsage, Message, Usage};
+use rig::streaming::{StreamedAssistantContent, StreamedUserContent};
+
+use super::Compaction;
+use crate::kota_code::skills::SkillMatch;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// An event produced during one agent turn
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// Skills applied to this turn by automatic skill selection
+    SkillsSelected { skills: Vec<SkillMatch> },
+    /// The older history was summarized before this turn
+    Compacted(Compaction),
+    /// A chunk of assistant text
+    TextDelta { text: String },
+    /// A chunk of model reasoning
+    ReasoningDelta { reasoning: String },
+    /// A complete reasoning block, sent after its deltas by providers that stream them
+    Reasoning {
+        id: Option<String>,
+        reasoning: String,
+        signature: Option<String>,
+    },
+    /// The model asked for a tool to be executed
+    ToolCallStart {
+        id: String,
+        call_id: Option<String>,
+        name: String,
+        arguments: serde_json::Value,
+    },
+    /// A tool finished and its output was sent back to the model
+    ToolResult {
+        id: String,
+        call_id: Option<String>,
+        name: String,
+        content: String,
+        /// The tool failed and `content` is the error sent to the model
+        is_error: bool,
+    },
+    /// Token usage reported for one completion call
+    Usage(Usage),
+    /// The final response of the turn, with usage aggregated over all completion calls
+    FinalResponse(FinalResponse),
+}
+
+/// Stream of [`AgentEvent`]s for one agent turn
+pub type AgentEventStream = Pin<Box<dyn Stream<Item = Result<AgentEvent>> + Send>>;
+
+/// Convert a provider-specific multi-turn stream into an [`AgentEventStream`]
+///
+/// `outcomes` must be the outcomes recorded by the tools and the
+/// [`ToolOutcomeHook`] of the same stream.
+pub(crate) fn into_event_stream<R>(
+    stream: StreamingResult<R>,
+    outcomes: ToolOutcomes,
+) -> AgentEventStream
+where
+    R: GetTokenUsage + 'static,
+{
+    // Tool results only carry the call id, so remember which tool each id belongs to
+    let mut tool_names: HashMap<String, String> = HashMap::new();
+
+    let events = stream.filter_map(move |item| {
+        let event = match item {
+            Ok(MultiTurnStreamItem::StreamAssistantItem(content)) => match content {
+                StreamedAssistantContent::Text(text) => {
+                    Some(Ok(AgentEvent::TextDelta { text: text.text }))
+                }
+                StreamedAssistantContent::ReasoningDelta { reasoning, .. } => {
+                    Some(Ok(AgentEvent::ReasoningDelta { reasoning }))
+                }
+                StreamedAssistantContent::Reasoning(reasoning) => Some(Ok(AgentEvent::Reasoning {
+                    id: reasoning.id,
+                    reasoning: reasoning.reasoning.join("\n"),
+                    signature: reasoning.signature,
+                })),
+                StreamedAssistantContent::ToolCall { tool_call, .. } => {
+                    tool_names.insert(tool_call.id.clone(), tool_call.function.name.clone());
+                    Some(Ok(AgentEvent::ToolCallStart {
+                        id: tool_call.id,
+                        call_id: tool_call.call_id,
+                        name: tool_call.function.name,
+                        arguments: tool_call.function.arguments,
+                    }))
+                }
+                StreamedAssistantContent::ToolCallDelta { .. } => None,
+                StreamedAssistantContent::Final(response) => response
+                    .token_usage()
+                    .map(|usage| Ok(AgentEvent::Usage(usage))),
+            },
+            Ok(MultiTurnStreamItem::StreamUserItem(StreamedUserContent::ToolResult {
+                tool_result,
+                internal_call_id,
+            })) => {
+                let content = tool_result
+                    .content
+                    .iter()
+                    .map(|c| match c {
+                        ToolResultContent::Text(text) => text.text.clone(),
+                        ToolResultContent::Image(_) => "[image]".to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Some(Ok(AgentEvent::ToolResult {
+                    name: tool_names.get(&tool_result.id).cloned().unwrap_or_default(),
+                    id: tool_result.id,
+                    call_id: tool_result.call_id,
+                    is_error: outcomes.take_failed(&internal_call_id),
+                    content,
+                }))
+            }
+            Ok(MultiTurnStreamItem::FinalResponse(response)) => {
+                Some(Ok(AgentEvent::FinalResponse(response)))
+            }
+            Ok(_) => None,
+            Err(e) => Some(Err(anyhow::anyhow!(e))),
+        };
+        futures::future::ready(event)
+    });
+
+    Box::pin(events)
+}
+
+/// Whether the tool calls of an agent failed, shared by its tools and its hook
+///
+/// rig sends a failing tool to the model as the text of its error, so the
+/// [`SharedTool`](crate::kota_code::runtime::tool_registry::SharedTool) wrapper
+/// records the typed result of each call. Tool calls of a turn run one after
+/// another, and [`ToolOutcomeHook`] files the latest result under rig's internal
+/// call id for the event stream.
+#[derive(Clone, Default)]
+pub(crate) struct ToolOutcomes(Arc<Mutex<OutcomeState>>);
+
+#[derive(Default)]
+struct OutcomeState {
+    /// Result of the tool call in progress, `None` until the tool returns
+    current: Option<bool>,
+    /// Internal call ids of the failed calls not yet streamed
+    failed: HashSet<String>,
+}
+
+impl ToolOutcomes {
+    /// Record whether the tool call in progress succeeded
+    pub(crate) fn record(&self, succeeded: bool) {
+        self.0.lock().unwrap().current = Some(succeeded);
+    }
+
+    fn start_call(&self) {
+        self.0.lock().unwrap().current = None;
+    }
+
+    /// File the call in progress, a tool that never returned (e.g. an unknown
+    /// tool name) counts as failed
+    fn finish_call(&self, internal_call_id: &str) {
+        let mut state = self.0.lock().unwrap();
+        if state.current.take() != Some(true) {
+            state.failed.insert(internal_call_id.to_string());
+        }
+    }
+
+    fn fail_call(&self, internal_call_id: &str) {
+        self.0
+            .lock()
+            .unwrap()
+            .failed
+            .insert(internal_call_id.to_string());
+    }
+
+    fn take_failed(&self, internal_call_id: &str) -> bool {
+        self.0.lock().unwrap().failed.remove(internal_call_id)
+    }
+}
+
+/// Hook that records the outcome of every tool call before handing it to `inner`
+#[derive(Clone)]
+pub(crate) struct ToolOutcomeHook<H> {
+    inner: H,
+    outcomes: ToolOutcomes,
+}
+
+impl<H> ToolOutcomeHook<H> {
+    pub(crate) fn new(inner: H, outcomes: ToolOutcomes) -> Self {
+        Self { inner, outcomes }
+    }
+}
+
+impl<M, H> StreamingPromptHook<M> for ToolOutcomeHook<H>
+where
+    M: CompletionModel,
+    H: StreamingPromptHook<M>,
+{
+    async fn on_completion_call(&self, prompt: &Message, history: &[Message]) -> HookAction {
+        self.inner.on_completion_call(prompt, history).await
+    }
+
+    async fn on_text_delta(&self, text_delta: &str, aggregated_text: &str) -> HookAction {
+        self.inner.on_text_delta(text_delta, aggregated_text).await
+    }
+
+    async fn on_tool_call_delta(
+        &self,
+        tool_call_id: &str,
+        internal_call_id: &str,
+        tool_name: Option<&str>,
+        tool_call_delta: &str,
+    ) -> HookAction {
+        self.inner
+            .on_tool_call_delta(tool_call_id, internal_call_id, tool_name, tool_call_delta)
+            .await
+    }
+
+    async fn on_stream_completion_response_finish(
+        &self,
+        prompt: &Message,
+        response: &<M as CompletionModel>::StreamingResponse,
+    ) -> HookAction {
+        self.inner
+            .on_stream_completion_response_finish(prompt, response)
+            .await
+    }
+
+    async fn on_tool_call(
+        &self,
+        tool_name: &str,
+        tool_call_id: Option<String>,
+        internal_call_id: &str,
+        args: &str,
+    ) -> ToolCallHookAction {
+        self.outcomes.start_call();
+        let action = self
+            .inner
+            .on_tool_call(tool_name, tool_call_id, internal_call_id, args)
+            .await;
+        // 被跳过的工具没有执行, 其结果是拒绝原因
+        if let ToolCallHookAction::Skip { .. } = action {
+            self.outcomes.fail_call(internal_call_id);
+        }
+        action
+    }
+
+    async fn on_tool_result(
+        &self,
+        tool_name: &str,
+        tool_call_id: Option<String>,
+        internal_call_id: &str,
+        args: &str,
+        result: &str,
+    ) -> HookAction {
+        self.outcomes.finish_call(internal_call_id);
+        self.inner
+            .on_tool_result(tool_name, tool_call_id, internal_call_id, args, result)
+            .await
+    }
+}
@@ -0,0 +1,315 @@
+//! Scripted completion model for offline agent testing
+//!
+//! The mock provider replays a list of assistant turns instead of calling a real LLM,
+//! so the complete tool loop of an [`AgentInstance`](super::AgentInstance) can run
+//! deterministically in `cargo test`.
+//!
+//! A script is a YAML or JSON list of turns:
+//!
+//! ```yaml
+//! - tool_calls:
+//!     - name: write_file
+//!       arguments: { file_path: "hello.txt", content: "hi" }
+//!       expect_result: "Successfully wrote"
+//! - text: "Created hello.txt"
+//! ```
+
+use anyhow::{Context, Result};
+use rig::completion::{
+    self, message::AssistantContent, message::Reasoning, message::ToolResultContent,
+    message::UserContent, CompletionError, CompletionRequest, GetTokenUsage, Message,
+};
+use rig::streaming::{RawStreamingChoice, RawStreamingToolCall, StreamingCompletionResponse};
+use rig::OneOrMany;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// A tool call issued by a scripted turn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockToolCall {
+    /// Tool call id, generated from the turn and call index when omitted
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+    /// Text the tool result sent back by the agent must contain
+    #[serde(default)]
+    pub expect_result: Option<String>,
+}
+
+/// Token usage reported for a scripted turn
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MockUsage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cached_input_tokens: u64,
+}
+
+/// One scripted assistant turn
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MockTurn {
+    /// Reasoning streamed as deltas before the text, like providers without reasoning blocks
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<MockToolCall>,
+    #[serde(default)]
+    pub usage: Option<MockUsage>,
+}
+
+/// Ordered list of assistant turns replayed by the mock provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MockScript {
+    pub turns: Vec<MockTurn>,
+}
+
+impl MockScript {
+    pub fn new(turns: Vec<MockTurn>) -> Self {
+        Self { turns }
+    }
+
+    /// Parse a script from a JSON string
+    pub fn from_json_str(content: &str) -> Result<Self> {
+        serde_json::from_str(content).context("Failed to parse mock script as JSON")
+    }
+
+    /// Parse a script from a YAML string
+    pub fn from_yaml_str(content: &str) -> Result<Self> {
+        serde_yaml::from_str(content).context("Failed to parse mock script as YAML")
+    }
+
+    /// Load a script from a `.json`, `.yaml` or `.yml` file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read mock script: {:?}", path))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json_str(&content),
+            _ => Self::from_yaml_str(&content),
+        }
+    }
+}
+
+/// Raw response returned by the mock provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MockResponse {
+    pub usage: MockUsage,
+}
+
+impl GetTokenUsage for MockResponse {
+    fn token_usage(&self) -> Option<completion::Usage> {
+        let mut usage = completion::Usage::new();
+        usage.input_tokens = self.usage.input_tokens;
+        usage.output_tokens = self.usage.output_tokens;
+        usage.cached_input_tokens = self.usage.cached_input_tokens;
+        usage.total_tokens = self.usage.input_tokens + self.usage.output_tokens;
+        Some(usage)
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    turns: VecDeque<MockTurn>,
+    served: usize,
+    /// (tool call id, expected substring) pairs to check against the next request
+    expectations: Vec<(String, String)>,
+    requests: Vec<CompletionRequest>,
+    /// Model name each request was sent to
+    models: Vec<String>,
+}
+
+/// Completion model that replays a [`MockScript`]
+///
+/// Clones share the same script position, so the agent and the test can both
+/// hold a handle to it.
+#[derive(Debug, Clone, Default)]
+pub struct MockCompletionModel {
+    state: Arc<Mutex<MockState>>,
+    /// Model name recorded with every request
+    pub model: String,
+}
+
+impl MockCompletionModel {
+    pub fn new(script: MockScript) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                turns: script.turns.into(),
+                ..MockState::default()
+            })),
+            model: "mock".to_string(),
+        }
+    }
+
+    /// All requests received so far, in order
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Model names the requests were sent to, in order
+    pub fn request_models(&self) -> Vec<String> {
+        self.state.lock().unwrap().models.clone()
+    }
+
+    /// Number of scripted turns not yet replayed
+    pub fn remaining_turns(&self) -> usize {
+        self.state.lock().unwrap().turns.len()
+    }
+
+    /// Record the request, check pending tool result expectations and pop the next turn
+    fn next_turn(&self, request: CompletionRequest) -> Result<MockTurn, CompletionError> {
+        let mut state = self.state.lock().unwrap();
+
+        for (id, expected) in std::mem::take(&mut state.expectations) {
+            match find_tool_result(&request, &id) {
+                Some(result) if result.contains(&expected) => {}
+                Some(result) => {
+                    return Err(CompletionError::ProviderError(format!(
+                        "Mock tool result for '{}' does not contain '{}': {}",
+                        id, expected, result
+                    )));
+                }
+                None => {
+                    return Err(CompletionError::ProviderError(format!(
+                        "Mock expected a tool result for '{}' but none was sent",
+                        id
+                    )));
+                }
+            }
+        }
+
+        state.requests.push(request);
+        state.models.push(self.model.clone());
+        let mut turn = state.turns.pop_front().ok_or_else(|| {
+            CompletionError::ProviderError("Mock script has no turns left".to_string())
+        })?;
+
+        let turn_index = state.served;
+        state.served += 1;
+        for (i, call) in turn.tool_calls.iter_mut().enumerate() {
+            let id = call
+                .id
+                .get_or_insert_with(|| format!("mock_call_{}_{}", turn_index, i))
+                .clone();
+            if let Some(expected) = &call.expect_result {
+                state.expectations.push((id, expected.clone()));
+            }
+        }
+        Ok(turn)
+    }
+}
+
+/// Find the text of the tool result with the given id in the request history
+fn find_tool_result(request: &CompletionRequest, id: &str) -> Option<String> {
+    request
+        .chat_history
+        .iter()
+        .find_map(|message| match message {
+            Message::User { content } => content.iter().find_map(|c| match c {
+                UserContent::ToolResult(result) if result.id == id => Some(
+                    result
+                        .content
+                        .iter()
+                        .filter_map(|c| match c {
+                            ToolResultContent::Text(text) => Some(text.text.clone()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+                _ => None,
+            }),
+            _ => None,
+        })
+}
+
+impl completion::CompletionModel for MockCompletionModel {
+    type Response = MockResponse;
+    type StreamingResponse = MockResponse;
+    type Client = MockScript;
+
+    fn make(client: &Self::Client, model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            ..Self::new(client.clone())
+        }
+    }
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<completion::CompletionResponse<Self::Response>, CompletionError> {
+        let turn = self.next_turn(request)?;
+
+        let mut content = Vec::new();
+        if let Some(reasoning) = &turn.reasoning {
+            content.push(AssistantContent::Reasoning(Reasoning::new(reasoning)));
+        }
+        if let Some(text) = &turn.text {
+            content.push(AssistantContent::text(text));
+        }
+        for call in &turn.tool_calls {
+            content.push(AssistantContent::tool_call(
+                call.id.clone().unwrap_or_default(),
+                call.name.clone(),
+                call.arguments.clone(),
+            ));
+        }
+        let choice =
+            OneOrMany::many(content).unwrap_or_else(|_| OneOrMany::one(AssistantContent::text("")));
+
+        let raw_response = MockResponse {
+            usage: turn.usage.unwrap_or_default(),
+        };
+        Ok(completion::CompletionResponse {
+            choice,
+            usage: raw_response.token_usage().unwrap_or_default(),
+            raw_response,
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let turn = self.next_turn(request)?;
+
+        let mut chunks: Vec<Result<RawStreamingChoice<MockResponse>, CompletionError>> = Vec::new();
+        if let Some(reasoning) = &turn.reasoning {
+            for word in reasoning.split_inclusive(' ') {
+                chunks.push(Ok(RawStreamingChoice::ReasoningDelta {
+                    id: None,
+                    reasoning: word.to_string(),
+                }));
+            }
+        }
+        if let Some(text) = &turn.text {
+            // Emit word-sized deltas so consumers see a realistic stream
+            for word in text.split_inclusive(' ') {
+                chunks.push(Ok(RawStreamingChoice::Message(word.to_string())));
+            }
+        }
+        for call in &turn.tool_calls {
+            chunks.push(Ok(RawStreamingChoice::ToolCall(RawStreamingToolCall::new(
+                call.id.clone().unwrap_or_default(),
+                call.name.clone(),
+                call.arguments.clone(),
+            ))));
+        }
+        chunks.push(Ok(RawStreamingChoice::FinalResponse(MockResponse {
+            usage: turn.usage.unwrap_or_default(),
+        })));
+
+        Ok(StreamingCompletionResponse::stream(Box::pin(
+            futures::stream::iter(chunks),
+        )))
+    }
+}
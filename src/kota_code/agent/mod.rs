@@ -1,54 +1,107 @@
 use anyhow::{Ok, Result};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+pub mod delegate;
+pub mod events;
+pub mod mock;
+pub mod retry;
+mod transcript;
+
 use rig::{
     agent::Agent,
-    client::{CompletionClient, ProviderClient},
-    providers::{
-        anthropic, cohere,
-        deepseek::{self, DEEPSEEK_CHAT},
-        ollama, openai,
-    },
+    client::CompletionClient,
+    providers::{anthropic, cohere, deepseek, ollama, openai},
     streaming::StreamingPrompt,
 };
 
 use super::context::ContextManager;
+use super::file_history::FileHistory;
+use super::memory::ProjectMemory;
 use super::plan::PlanManager;
-use super::runtime::{dyn_tools_loader::LuaToolLoader, ToolRegistry};
-use super::skills::SkillManager;
+use super::runtime::tool_registry::SharedTool;
+use super::runtime::{
+    dyn_tools_loader::LuaToolLoader, CancelSignal, KotaConfig, PromptLoader, PromptVariables,
+    ToolRegistry,
+};
+use super::skills::{Skill, SkillManager};
+use super::store::SessionStore;
 use super::tools::{
-    WrappedCreateDirectoryTool, WrappedDeleteFileTool, WrappedEditFileTool,
-    WrappedExecuteBashCommandTool, WrappedGrepSearchTool, WrappedReadFileTool,
+    create_directory::CreateDirectoryTool,
+    delegate_task::DelegateTaskTool,
+    delete_file::DeleteFileTool,
+    edit_file::EditFileTool,
+    execute_bash_command::ExecuteBashCommandTool,
+    grep_search::GrepSearchTool,
+    read_file::ReadFileTool,
+    read_skill_resource::{ReadSkillResourceTool, SharedSkills},
+    remember::RememberTool,
+    scan_codebase::ScanCodebaseTool,
+    update_plan::UpdatePlanTool,
+    write_file::WriteFileTool,
+    SkillScriptTool, SnapshotTool, WrappedCreateDirectoryTool, WrappedDelegateTaskTool,
+    WrappedDeleteFileTool, WrappedEditFileTool, WrappedExecuteBashCommandTool,
+    WrappedGrepSearchTool, WrappedReadFileTool, WrappedReadSkillResourceTool, WrappedRememberTool,
     WrappedScanCodebaseTool, WrappedUpdatePlanTool, WrappedWriteFileTool,
 };
+use super::usage::{Budget, BudgetExceeded, BudgetStatus, PriceTable, SessionUsage, TokenUsage};
+use delegate::SubAgentFactory;
+use events::{into_event_stream, ToolOutcomeHook, ToolOutcomes};
+pub use events::{AgentEvent, AgentEventStream};
+use futures::StreamExt;
+use mock::{MockCompletionModel, MockScript};
+use retry::{RequestScope, RetryPolicy, RetryingHttpClient};
 
 macro_rules! build_agent {
-    ($client_expr:expr, $model_name:expr, $preamble:expr, $dyn_tools:expr, $variant:ident) => {{
+    ($client_expr:expr, $model_name:expr, $preamble:expr, $settings:expr, $dyn_tools:expr, $variant:ident) => {{
         let client = $client_expr?;
-        let agent = client
+        let mut agent = client
             .agent($model_name)
             .preamble(&$preamble)
-            .max_tokens(4096)
             .tools($dyn_tools)
             .build();
+        $settings.apply_to(&mut agent);
         AgentType::$variant(agent)
     }};
 }
 
+/// Run `$body` with `$agent` bound to the provider-specific agent inside `$agent_type`
+macro_rules! dispatch_agent {
+    ($agent_type:expr, $agent:ident => $body:expr) => {
+        match $agent_type {
+            AgentType::OpenAI($agent) => $body,
+            AgentType::OpenAICompatible($agent) => $body,
+            AgentType::Anthropic($agent) => $body,
+            AgentType::Cohere($agent) => $body,
+            AgentType::DeepSeek($agent) => $body,
+            AgentType::Ollama($agent) => $body,
+            AgentType::Mock($agent) => $body,
+        }
+    };
+}
+
 macro_rules! impl_stream_chat {
     ($agent:expr, $input:expr, $hook:expr, $history:expr) => {
         $agent
             .stream_prompt($input)
             .with_hook($hook)
-            .multi_turn(20)
             .with_history($history)
             .await
     };
 }
 
+mod compaction;
+pub use compaction::Compaction;
+mod undo;
+pub use undo::{Undo, UndoIncomplete};
+
 /// Supported LLM providers
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Provider {
     /// OpenAI (GPT-4, GPT-3.5, etc.)
     OpenAI,
+    /// Any server speaking the OpenAI chat completions API (vLLM, LiteLLM, ...)
+    OpenAICompatible,
     /// Anthropic Claude models
     Anthropic,
     /// Cohere models
@@ -57,6 +110,99 @@ pub enum Provider {
     DeepSeek,
     /// Local Ollama models
     Ollama,
+    /// Scripted offline model for tests
+    Mock,
+}
+
+impl FromStr for Provider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "openai" => Ok(Provider::OpenAI),
+            "openai-compatible" | "openai_compatible" => Ok(Provider::OpenAICompatible),
+            "anthropic" => Ok(Provider::Anthropic),
+            "cohere" => Ok(Provider::Cohere),
+            "deepseek" => Ok(Provider::DeepSeek),
+            "ollama" => Ok(Provider::Ollama),
+            "mock" => Ok(Provider::Mock),
+            _ => Err(anyhow::anyhow!(
+                "Unknown provider: {}. Supported providers: openai, openai-compatible, anthropic, cohere, deepseek, ollama, mock",
+                s
+            )),
+        }
+    }
+}
+
+/// Sampling parameters and limits applied to every request
+///
+/// Defaults can be set in `kota.setup`, overridden per [`AgentBuilder`] and
+/// per skill through its SKILL.md frontmatter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationSettings {
+    /// Sampling temperature, 0.7 by default, `None` uses the provider default
+    pub temperature: Option<f64>,
+    /// Nucleus sampling probability, `None` uses the provider default
+    pub top_p: Option<f64>,
+    /// Maximum number of output tokens per completion call
+    pub max_tokens: u64,
+    /// Maximum depth of the tool loop for one prompt
+    pub max_turns: usize,
+}
+
+impl Default for GenerationSettings {
+    fn default() -> Self {
+        Self {
+            temperature: Some(0.7),
+            top_p: None,
+            max_tokens: 4096,
+            max_turns: 20,
+        }
+    }
+}
+
+impl GenerationSettings {
+    /// Return these settings with the overrides declared by `skill` applied
+    pub fn with_skill_overrides(&self, skill: &Skill) -> Self {
+        Self {
+            temperature: skill.temperature.or(self.temperature),
+            top_p: skill.top_p.or(self.top_p),
+            max_tokens: skill.max_tokens.unwrap_or(self.max_tokens),
+            max_turns: skill.max_turns.unwrap_or(self.max_turns),
+        }
+    }
+
+    /// Write the settings into a provider-specific agent
+    fn apply_to<M: rig::completion::CompletionModel>(&self, agent: &mut Agent<M>) {
+        agent.temperature = self.temperature;
+        agent.max_tokens = Some(self.max_tokens);
+        agent.default_max_turns = Some(self.max_turns);
+        // rig has no dedicated field for top_p, it is merged into the request body
+        // next to the other additional params
+        let mut params = match agent.additional_params.take() {
+            Some(serde_json::Value::Object(params)) => params,
+            Some(other) => {
+                agent.additional_params = Some(other);
+                return;
+            }
+            None => serde_json::Map::new(),
+        };
+        match self.top_p {
+            Some(top_p) => params.insert("top_p".to_string(), top_p.into()),
+            None => params.remove("top_p"),
+        };
+        agent.additional_params = (!params.is_empty()).then_some(serde_json::Value::Object(params));
+    }
+}
+
+/// Error returned when a turn is cancelled through the agent's [`CancelSignal`]
+#[derive(Debug, thiserror::Error)]
+#[error("Turn cancelled")]
+pub struct TurnCancelled {
+    /// Assistant text streamed before the turn was cancelled
+    pub partial_response: String,
+    /// Tokens used by the completion calls that finished before the cancellation
+    pub usage: TokenUsage,
 }
 
 /// Agent enum to handle different provider types
@@ -65,15 +211,51 @@ pub enum Provider {
 /// with them through a unified interface.
 pub enum AgentType {
     /// OpenAI agent
-    OpenAI(Agent<openai::responses_api::ResponsesCompletionModel>),
+    OpenAI(Agent<openai::responses_api::ResponsesCompletionModel<RetryingHttpClient>>),
+    /// OpenAI-compatible agent using the chat completions API
+    OpenAICompatible(Agent<openai::completion::CompletionModel<RetryingHttpClient>>),
     /// Anthropic Claude agent
-    Anthropic(Agent<anthropic::completion::CompletionModel>),
+    Anthropic(Agent<anthropic::completion::CompletionModel<RetryingHttpClient>>),
     /// Cohere agent
-    Cohere(Agent<cohere::CompletionModel>),
+    Cohere(Agent<cohere::CompletionModel<RetryingHttpClient>>),
     /// DeepSeek agent
-    DeepSeek(Agent<deepseek::CompletionModel>),
+    DeepSeek(Agent<deepseek::CompletionModel<RetryingHttpClient>>),
     /// Ollama local agent
-    Ollama(Agent<ollama::CompletionModel>),
+    Ollama(Agent<ollama::CompletionModel<RetryingHttpClient>>),
+    /// Scripted mock agent
+    Mock(Agent<MockCompletionModel>),
+}
+
+/// Hook accepted by every provider's streaming prompt
+///
+/// Implemented automatically for any hook that implements
+/// [`rig::agent::StreamingPromptHook`] for all supported completion models.
+pub trait AgentHook:
+    rig::agent::StreamingPromptHook<
+        openai::responses_api::ResponsesCompletionModel<RetryingHttpClient>,
+    > + rig::agent::StreamingPromptHook<openai::completion::CompletionModel<RetryingHttpClient>>
+    + rig::agent::StreamingPromptHook<anthropic::completion::CompletionModel<RetryingHttpClient>>
+    + rig::agent::StreamingPromptHook<cohere::CompletionModel<RetryingHttpClient>>
+    + rig::agent::StreamingPromptHook<deepseek::CompletionModel<RetryingHttpClient>>
+    + rig::agent::StreamingPromptHook<ollama::CompletionModel<RetryingHttpClient>>
+    + rig::agent::StreamingPromptHook<MockCompletionModel>
+    + Clone
+    + 'static
+{
+}
+
+impl<H> AgentHook for H where
+    H: rig::agent::StreamingPromptHook<
+            openai::responses_api::ResponsesCompletionModel<RetryingHttpClient>,
+        > + rig::agent::StreamingPromptHook<openai::completion::CompletionModel<RetryingHttpClient>>
+        + rig::agent::StreamingPromptHook<anthropic::completion::CompletionModel<RetryingHttpClient>>
+        + rig::agent::StreamingPromptHook<cohere::CompletionModel<RetryingHttpClient>>
+        + rig::agent::StreamingPromptHook<deepseek::CompletionModel<RetryingHttpClient>>
+        + rig::agent::StreamingPromptHook<ollama::CompletionModel<RetryingHttpClient>>
+        + rig::agent::StreamingPromptHook<MockCompletionModel>
+        + Clone
+        + 'static
+{
 }
 
 /// Complete agent instance with context and skill management
@@ -82,9 +264,40 @@ pub enum AgentType {
 /// providing a complete solution for building AI assistants.
 pub struct AgentInstance {
     pub agent: AgentType,
+    /// Generation settings used when no skill overrides them
+    pub settings: GenerationSettings,
     pub context: Option<ContextManager>,
     pub skill_manager: Option<SkillManager>,
     pub tool_registry: ToolRegistry,
+    /// Cancels the running turn, shared with the tools
+    pub cancel: CancelSignal,
+    /// Model the token usage is recorded under
+    pub model_name: String,
+    /// Prices used to estimate the session cost
+    pub prices: PriceTable,
+    /// Spending limits of the session, new turns are refused once exceeded
+    pub budget: Option<Budget>,
+    /// Spawns the sub-agents of the `delegate_task` tool
+    pub sub_agents: Option<SubAgentFactory>,
+    /// Files changed by the tools in the latest turns, shared with the file tools
+    pub file_history: FileHistory,
+    /// `KOTA.md` files included in the system prompt
+    pub memory: ProjectMemory,
+    /// System prompt without the memory section
+    base_preamble: String,
+    /// Skills read by `read_skill_resource`
+    skill_catalog: Option<SharedSkills>,
+    /// Tools registered at build time, skill tools may not replace them
+    base_tools: Vec<SharedTool>,
+    /// Base tools removed because the active skills do not allow them
+    disabled_tools: Vec<String>,
+    /// Tools of the active skills currently registered with the agent
+    skill_tools: Vec<String>,
+    /// Model the provider agent was built with
+    base_model: String,
+    /// Whether each tool call succeeded, recorded by the tools of this agent
+    tool_outcomes: ToolOutcomes,
+    tool_progress: bool,
 }
 
 impl AgentInstance {
@@ -117,80 +330,347 @@ impl AgentInstance {
     pub fn tool_registry_mut(&mut self) -> &mut ToolRegistry {
         &mut self.tool_registry
     }
+
+    /// Get a handle that cancels the running turn
+    ///
+    /// Calling [`CancelSignal::cancel`] from another task makes the current
+    /// [`AgentInstance::chat`] return a [`TurnCancelled`] error.
+    pub fn cancel_signal(&self) -> CancelSignal {
+        self.cancel.clone()
+    }
+
+    /// Get the default generation settings
+    pub fn settings(&self) -> &GenerationSettings {
+        &self.settings
+    }
+
+    /// Replace the default generation settings
+    pub fn set_settings(&mut self, settings: GenerationSettings) {
+        self.settings = settings;
+        self.apply_settings(&self.settings.clone());
+    }
+
+    /// Generation settings for the next turn, including overrides of the active skills
+    ///
+    /// When several active skills override the same setting, the skill activated
+    /// last wins.
+    pub fn effective_settings(&self) -> GenerationSettings {
+        match &self.skill_manager {
+            Some(manager) => manager
+                .get_active_skills()
+                .into_iter()
+                .fold(self.settings.clone(), |settings, skill| {
+                    settings.with_skill_overrides(skill)
+                }),
+            None => self.settings.clone(),
+        }
+    }
+
+    /// System prompt for the next turn, with the active skills and memory files
+    pub fn system_preamble(&self) -> String {
+        compose_preamble(
+            &self.base_preamble,
+            self.skill_manager.as_ref(),
+            &self.memory,
+        )
+    }
+
+    /// Model the next turn is sent to, the skill model if an active skill sets one
+    pub fn effective_model(&self) -> String {
+        self.skill_manager
+            .as_ref()
+            .and_then(|manager| manager.active_model())
+            .map(str::to_string)
+            .unwrap_or_else(|| self.model_name.clone())
+    }
+
+    /// Names of the base tools removed by the `allowed_tools` and
+    /// `disallowed_tools` of the active skills
+    pub fn disabled_tool_names(&self) -> &[String] {
+        &self.disabled_tools
+    }
+
+    /// Bring the tool server in line with the active skills
+    ///
+    /// Registers the Lua tools and scripts of the active skills and removes
+    /// those of skills that are no longer active. Base tools the active skills
+    /// do not allow are removed until the skills are deactivated.
+    async fn sync_skill_tools(&mut self) {
+        let base_names: Vec<String> = self.base_tools.iter().map(|tool| tool.0.name()).collect();
+        let mut tools: Vec<Box<dyn rig::tool::ToolDyn>> = Vec::new();
+        let mut disabled = Vec::new();
+        if let Some(manager) = &self.skill_manager {
+            let active = manager.get_active_skills();
+            for skill in &active {
+                for tool in self.skill_tools_of(skill) {
+                    let name = tool.name();
+                    if base_names.contains(&name) {
+                        eprintln!(
+                            "Warning: Tool '{}' of skill '{}' conflicts with a built-in tool",
+                            name, skill.name
+                        );
+                    } else if !tools.iter().any(|t| t.name() == name)
+                        && !active.iter().any(|skill| skill.disallows_tool(&name))
+                    {
+                        tools.push(tool);
+                    }
+                }
+            }
+            disabled = base_names
+                .iter()
+                .filter(|name| !manager.allows_tool(name))
+                .cloned()
+                .collect();
+        }
+        let wanted: Vec<String> = tools.iter().map(|tool| tool.name()).collect();
+
+        let handle = dispatch_agent!(&self.agent, agent => agent.tool_server_handle.clone());
+        let removed = self
+            .skill_tools
+            .iter()
+            .filter(|name| !wanted.contains(name))
+            .chain(
+                disabled
+                    .iter()
+                    .filter(|name| !self.disabled_tools.contains(name)),
+            );
+        for name in removed {
+            if let Err(e) = handle.remove_tool(name).await {
+                eprintln!("Warning: Failed to remove tool '{}': {}", name, e);
+            }
+        }
+        for tool in &self.base_tools {
+            let name = tool.0.name();
+            if self.disabled_tools.contains(&name) && !disabled.contains(&name) {
+                if let Err(e) = handle.add_tool(tool.clone()).await {
+                    eprintln!("Warning: Failed to restore tool '{}': {}", name, e);
+                }
+            }
+        }
+        for tool in tools {
+            let name = tool.name();
+            if self.skill_tools.contains(&name) {
+                continue;
+            }
+            if let Err(e) = handle
+                .add_tool(SharedTool(tool.into(), self.tool_outcomes.clone()))
+                .await
+            {
+                eprintln!("Warning: Failed to register skill tool '{}': {}", name, e);
+            }
+        }
+        self.skill_tools = wanted;
+        self.disabled_tools = disabled;
+    }
+
+    /// Lua tools and scripts shipped with `skill`
+    fn skill_tools_of(&self, skill: &Skill) -> Vec<Box<dyn rig::tool::ToolDyn>> {
+        let mut tools: Vec<Box<dyn rig::tool::ToolDyn>> = Vec::new();
+        if let Some(tools_file) = skill.tools_file() {
+            match LuaToolLoader::load_tools_from(&tools_file) {
+                std::result::Result::Ok(lua_tools) => {
+                    tools.extend(
+                        lua_tools
+                            .into_iter()
+                            .map(|tool| Box::new(tool) as Box<dyn rig::tool::ToolDyn>),
+                    );
+                }
+                Err(e) => eprintln!(
+                    "Warning: Failed to load Lua tools of skill '{}': {}",
+                    skill.name, e
+                ),
+            }
+        }
+        if let Some(dir) = &skill.dir {
+            for script in skill.scripts() {
+                let tool = SkillScriptTool::new(script, dir.clone(), self.cancel.clone())
+                    .with_progress(self.tool_progress);
+                tools.push(Box::new(tool));
+            }
+        }
+        tools
+    }
+
+    /// Reload the skills when a skill directory or `SKILL.md` changed
+    ///
+    /// Runs before every turn, so edited skills take effect without a restart.
+    /// Returns whether the skills were reloaded.
+    pub fn refresh_skills(&mut self) -> bool {
+        let Some(manager) = &mut self.skill_manager else {
+            return false;
+        };
+        if !manager.reload_if_changed() {
+            return false;
+        }
+        if let Some(catalog) = &self.skill_catalog {
+            *catalog.write().unwrap() = skill_list(manager);
+        }
+        true
+    }
+
+    /// Names of the tools added by the active skills
+    pub fn skill_tool_names(&self) -> &[String] {
+        &self.skill_tools
+    }
+
+    fn apply_preamble(&mut self) {
+        let preamble = self.system_preamble();
+        dispatch_agent!(&mut self.agent, agent => agent.preamble = Some(preamble.clone()));
+    }
+
+    fn apply_settings(&mut self, settings: &GenerationSettings) {
+        dispatch_agent!(&mut self.agent, agent => settings.apply_to(agent))
+    }
+
+    /// Send the following requests to `model`, served by the same provider
+    fn apply_model(&mut self, model: &str) {
+        dispatch_agent!(&mut self.agent, agent => {
+            if agent.model.model != model {
+                let mut completion_model = (*agent.model).clone();
+                completion_model.model = model.to_string();
+                agent.model = Arc::new(completion_model);
+            }
+        })
+    }
+
+    /// Read the `KOTA.md` files again and update the system prompt
+    pub fn reload_memory(&mut self) -> Result<()> {
+        self.memory.reload()?;
+        self.apply_preamble();
+        Ok(())
+    }
+
+    /// Token usage of the current session, `None` without a context manager
+    pub fn session_usage(&self) -> Option<&SessionUsage> {
+        self.context().map(|context| context.usage())
+    }
+
+    /// Estimated cost of the current session in USD
+    ///
+    /// Returns `None` without a context manager or when a turn used a model
+    /// missing from the price table.
+    pub fn session_cost(&self) -> Option<f64> {
+        self.session_usage()?.cost(&self.prices)
+    }
+
+    /// Where the current session stands relative to the budget
+    pub fn budget_status(&self) -> BudgetStatus {
+        match (&self.budget, self.session_usage()) {
+            (Some(budget), Some(usage)) => budget.status(usage, &self.prices),
+            _ => BudgetStatus::Ok,
+        }
+    }
+
+    /// Limits left in the session budget, `None` without a budget or context
+    fn remaining_budget(&self) -> Option<Budget> {
+        match (&self.budget, self.session_usage()) {
+            (Some(budget), Some(usage)) => Some(budget.remaining(usage, &self.prices)),
+            _ => None,
+        }
+    }
 }
 
 impl AgentInstance {
+    /// Stream the agent's response as typed events
+    ///
+    /// Runs the full tool loop and yields text and reasoning deltas, tool calls,
+    /// tool results, usage and the final response as they happen. Nothing is
+    /// printed and the context manager is not touched.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The user input message
+    /// * `hook` - Session hook for tracking
+    /// * `history` - Conversation history
+    ///
+    /// # Returns
+    ///
+    /// Returns a stream of [`AgentEvent`]s ending with [`AgentEvent::FinalResponse`]
+    pub async fn stream_events<H: AgentHook>(
+        &self,
+        input: &str,
+        hook: H,
+        history: Vec<rig::completion::Message>,
+    ) -> Result<AgentEventStream> {
+        let hook = ToolOutcomeHook::new(hook, self.tool_outcomes.clone());
+        Ok(dispatch_agent!(&self.agent, agent => {
+            into_event_stream(
+                impl_stream_chat!(agent, input, hook, history),
+                self.tool_outcomes.clone(),
+            )
+        }))
+    }
+
     /// Stream chat with the agent
     ///
+    /// Consumes [`AgentInstance::stream_events`], passing every event to `on_event`.
+    /// The turn stops early with a [`TurnCancelled`] error when the agent's
+    /// [`CancelSignal`] is triggered. It runs under the token of the current
+    /// turn; [`AgentInstance::chat_with_events`] starts the turn, direct callers
+    /// call [`CancelSignal::begin_turn`] first.
+    ///
     /// # Arguments
     ///
     /// * `input` - The user input message
     /// * `hook` - Session hook for tracking
     /// * `history` - Conversation history
+    /// * `on_event` - Callback invoked for each streamed event
     ///
     /// # Returns
     ///
-    /// Returns a completion response after streaming to stdout
-    pub async fn stream_chat<H>(
+    /// Returns the final response once the stream is finished
+    pub async fn stream_chat<H, F>(
         &self,
         input: &str,
         hook: H,
         history: Vec<rig::completion::Message>,
+        mut on_event: F,
     ) -> Result<rig::agent::FinalResponse>
     where
-        H: rig::agent::StreamingPromptHook<openai::responses_api::ResponsesCompletionModel>
-            + Clone
-            + 'static,
-        H: rig::agent::StreamingPromptHook<anthropic::completion::CompletionModel>
-            + Clone
-            + 'static,
-        H: rig::agent::StreamingPromptHook<cohere::CompletionModel> + Clone + 'static,
-        H: rig::agent::StreamingPromptHook<deepseek::CompletionModel> + Clone + 'static,
-        H: rig::agent::StreamingPromptHook<ollama::CompletionModel> + Clone + 'static,
+        H: AgentHook,
+        F: FnMut(&AgentEvent),
     {
-        match &self.agent {
-            AgentType::OpenAI(agent) => {
-                let mut stream = impl_stream_chat!(agent, input, hook.clone(), history.clone());
-                rig::agent::stream_to_stdout(&mut stream)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
-            }
-            AgentType::Anthropic(agent) => {
-                let mut stream = impl_stream_chat!(agent, input, hook.clone(), history.clone());
-                rig::agent::stream_to_stdout(&mut stream)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
+        let token = self.cancel.token();
+        let mut stream = self.stream_events(input, hook, history).await?;
+        let mut final_response = None;
+        let mut partial_response = String::new();
+        let mut usage = TokenUsage::default();
+        loop {
+            let event = tokio::select! {
+                event = stream.next() => event,
+                _ = token.cancelled() => {
+                    // Dropping the stream aborts the pending request and tool calls
+                    return Err(TurnCancelled { partial_response, usage }.into());
+                }
+            };
+            let Some(event) = event else {
+                break;
+            };
+            // 取消后到达的事件 (例如被终止命令的结果) 不再转发
+            if token.is_cancelled() {
+                return Err(TurnCancelled {
+                    partial_response,
+                    usage,
+                }
+                .into());
             }
-            AgentType::Cohere(agent) => {
-                let mut stream = impl_stream_chat!(agent, input, hook.clone(), history.clone());
-                rig::agent::stream_to_stdout(&mut stream)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
+            let event = event?;
+            match &event {
+                AgentEvent::TextDelta { text } => partial_response.push_str(text),
+                AgentEvent::Usage(call_usage) => usage += TokenUsage::from(*call_usage),
+                _ => {}
             }
-            AgentType::DeepSeek(agent) => {
-                let mut stream = impl_stream_chat!(agent, input, hook.clone(), history.clone());
-                rig::agent::stream_to_stdout(&mut stream)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
-            }
-            AgentType::Ollama(agent) => {
-                let mut stream = impl_stream_chat!(agent, input, hook.clone(), history.clone());
-                rig::agent::stream_to_stdout(&mut stream)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
+            on_event(&event);
+            if let AgentEvent::FinalResponse(response) = event {
+                final_response = Some(response);
             }
         }
+        final_response.ok_or_else(|| anyhow::anyhow!("Stream ended without a final response"))
     }
 
     /// Convenient method to chat with automatic context management
     ///
-    /// This method handles the complete chat flow:
-    /// - Creates session hook automatically
-    /// - Retrieves conversation history from context
-    /// - Adds user message to context
-    /// - Streams the chat response
-    /// - Saves assistant response to context
-    /// - Auto-saves context to disk
+    /// Same as [`AgentInstance::chat_with_events`] but ignores the streamed events.
     ///
     /// # Arguments
     ///
@@ -219,12 +699,64 @@ impl AgentInstance {
     /// }
     /// ```
     pub async fn chat(&mut self, input: &str) -> Result<rig::agent::FinalResponse> {
+        self.chat_with_events(input, |_| {}).await
+    }
+
+    /// Chat with automatic context management while observing streamed events
+    ///
+    /// This method handles the complete chat flow:
+    /// - Starts the turn of the [`CancelSignal`], a cancel of the token prepared
+    ///   with [`CancelSignal::reset`] before the call stops the turn right away
+    /// - Refuses the turn with [`BudgetExceeded`] once the session budget is used up
+    /// - Compacts the older history once it nears the token budget
+    /// - Starts recording the file changes of the turn for [`AgentInstance::undo`]
+    /// - Creates session hook automatically
+    /// - Retrieves conversation history from context
+    /// - Adds user message to context
+    /// - Reloads skills whose `SKILL.md` changed on disk
+    /// - Applies automatically selected skills for this turn, if enabled
+    /// - Applies the tools, model and settings of the active skills for this turn
+    /// - Streams the chat response, passing every event to `on_event`
+    /// - Saves the turn's messages, including tool calls, tool results and
+    ///   reasoning, and token usage to context, under the model that answered
+    ///   each completion call
+    /// - Records the partial turn and its usage if it is cancelled or fails
+    /// - Fails with [`retry::RetriesExhausted`] when a provider request ran out of
+    ///   retries and fallback models
+    /// - Auto-saves context to disk
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The user input message
+    /// * `on_event` - Callback invoked for each streamed event
+    ///
+    /// # Returns
+    ///
+    /// Returns a completion response with usage information
+    pub async fn chat_with_events<F>(
+        &mut self,
+        input: &str,
+        mut on_event: F,
+    ) -> Result<rig::agent::FinalResponse>
+    where
+        F: FnMut(&AgentEvent),
+    {
         use super::runtime::SessionIdHook;
         use rig::completion::Message;
+        use transcript::TurnTranscript;
 
-        // 添加用户消息到上下文
-        if let Some(context) = self.context_mut() {
-            context.add_message(Message::user(input));
+        // 在任何准备工作之前开始本轮, 之后的 Ctrl-C 都会取消这一轮
+        self.cancel.begin_turn();
+
+        if let BudgetStatus::Exceeded { used } = self.budget_status() {
+            return Err(BudgetExceeded { used }.into());
+        }
+
+        // 历史接近 token 预算时, 先把较早的消息压缩为摘要
+        if self.context().is_some_and(|c| c.needs_compaction()) {
+            if let Some(compaction) = self.compact().await? {
+                on_event(&AgentEvent::Compacted(compaction));
+            }
         }
 
         // 创建会话钩子
@@ -232,32 +764,134 @@ impl AgentInstance {
             .context()
             .map(|c| c.session_id().to_string())
             .unwrap_or_else(|| "unknown".to_string());
+        if let Some(sub_agents) = &self.sub_agents {
+            sub_agents.set_parent_session(&session_id);
+            sub_agents.set_budget(self.remaining_budget());
+        }
+        // 本轮工具修改文件前的快照, 供 /undo 恢复
+        self.file_history.begin_turn(&session_id);
         let hook = SessionIdHook::new(session_id);
 
-        // 获取历史消息
-        let mut history = self
+        // 获取历史消息 (不包含本次输入, 输入会作为 prompt 单独发送)
+        let history = self
             .context()
             .map(|c| c.get_messages().to_vec())
             .unwrap_or_default();
 
-        // 如果有激活的 skill，在历史消息前添加 skill instructions
-        if let Some(skill_manager) = &self.skill_manager {
-            if let Some(skill) = skill_manager.get_active_skill() {
-                let skill_prompt = format!(
-                    "[ACTIVE SKILL: {}]\n{}\n\n{}",
-                    skill.name, skill.description, skill.instructions
-                );
-                history.insert(0, Message::user(&skill_prompt));
+        // 添加用户消息到上下文
+        if let Some(context) = self.context_mut() {
+            context.add_message(Message::user(input));
+        }
+
+        // SKILL.md 被修改后重新加载, 无需重启
+        self.refresh_skills();
+
+        // 开启自动选择时, 为本轮挑选与输入最相关的 skill
+        if let Some(skill_manager) = &mut self.skill_manager {
+            let selected = skill_manager.select_for_turn(input);
+            if !selected.is_empty() {
+                on_event(&AgentEvent::SkillsSelected { skills: selected });
             }
         }
 
-        // 执行流式聊天
-        let response = self.stream_chat(input, hook, history).await?;
+        // skill 可能在两轮之间变化, 每轮重新注册 skill 工具并生成 system prompt
+        self.sync_skill_tools().await;
+        self.apply_preamble();
 
-        // 保存助手响应到上下文
+        // 应用 skill 覆盖的生成参数和模型执行流式聊天, 结束后恢复默认值
+        let settings = self.effective_settings();
+        self.apply_settings(&settings);
+        let model_name = self.effective_model();
+        let skill_model = self
+            .skill_manager
+            .as_ref()
+            .and_then(|manager| manager.active_model())
+            .map(str::to_string);
+        self.apply_model(&skill_model.unwrap_or_else(|| self.base_model.clone()));
+        // 记录本轮的全部消息, 包括工具调用, 工具结果和推理内容
+        let mut transcript = TurnTranscript::default();
+        // 每次补全调用的用量记在实际应答的模型下, fallback 后可能不是请求的模型
+        let mut usage_by_model: Vec<(String, TokenUsage)> = Vec::new();
+        let requests = RequestScope::default();
+        let counter = hook.clone();
+        let chat = self.stream_chat(input, hook, history, |event| {
+            transcript.record(counter.completion_calls(), event);
+            if let AgentEvent::Usage(usage) = event {
+                let model = requests
+                    .served_model()
+                    .unwrap_or_else(|| model_name.clone());
+                add_usage(&mut usage_by_model, model, TokenUsage::from(*usage));
+            }
+            on_event(event);
+        });
+        // 只有本轮的请求会写入 requests
+        let result = requests.scope(chat).await;
+        // rig 会把部分 provider 错误转成字符串, 重试耗尽的类型从 requests 中恢复
+        let result = result.map_err(|e| match requests.exhausted() {
+            Some(exhausted) if !e.is::<TurnCancelled>() => e.context(exhausted),
+            _ => e,
+        });
+        // 子代理的用量计入父会话, 与父代理共用预算
+        let sub_agent_usage: Vec<(String, TokenUsage)> = self
+            .sub_agents
+            .as_ref()
+            .map(|sub_agents| sub_agents.take_usage().turns)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|turn| (turn.model, turn.usage))
+            .collect();
+        if let Some(skill_manager) = &mut self.skill_manager {
+            let active_skills = skill_manager.active_skill_names();
+            skill_manager.clear_turn_skills();
+            if let Some(context) = &mut self.context {
+                context.set_active_skills(active_skills);
+            }
+        }
+        self.apply_preamble();
+        self.apply_settings(&self.settings.clone());
+        self.apply_model(&self.base_model.clone());
+
+        // 被取消或失败时保存已输出的部分内容, 避免上下文里留下没有回复的用户消息
+        if let Err(e) = &result {
+            let cancelled = e.downcast_ref::<TurnCancelled>();
+            if let Some(cancelled) = cancelled.filter(|_| usage_by_model.is_empty()) {
+                usage_by_model.push((model_name, cancelled.usage));
+            }
+            usage_by_model.extend(sub_agent_usage);
+            if let Some(context) = self.context_mut() {
+                // 失败前已完成的补全调用同样计入用量和预算
+                for (model, usage) in &usage_by_model {
+                    context.record_usage(model, *usage);
+                }
+                let messages = match cancelled {
+                    Some(_) => transcript.interrupt(),
+                    None => transcript.fail(&e.to_string()),
+                };
+                for message in messages {
+                    context.add_message(message);
+                }
+                context.save()?;
+            }
+            return result;
+        }
+        let response = result?;
+
+        // 保存本轮消息和 token 用量到上下文
         if let Some(context) = self.context_mut() {
-            let response_content = response.response();
-            context.add_message(Message::assistant(response_content));
+            for message in transcript.finish(response.response()) {
+                context.add_message(message);
+            }
+            // rig 只为带文本的补全调用发送用量事件, 只调用工具的补全用量按汇总补上
+            let streamed = usage_by_model.iter().map(|(_, usage)| *usage).sum();
+            let missing = TokenUsage::from(response.usage()).saturating_sub(&streamed);
+            if missing != TokenUsage::default() {
+                let model = requests.served_model().unwrap_or(model_name);
+                add_usage(&mut usage_by_model, model, missing);
+            }
+            usage_by_model.extend(sub_agent_usage);
+            for (model, usage) in &usage_by_model {
+                context.record_usage(model, *usage);
+            }
 
             // 自动保存上下文
             context.save()?;
@@ -267,6 +901,14 @@ impl AgentInstance {
     }
 }
 
+/// Add `usage` to the entry of `model`
+fn add_usage(usage_by_model: &mut Vec<(String, TokenUsage)>, model: String, usage: TokenUsage) {
+    match usage_by_model.iter_mut().find(|(m, _)| *m == model) {
+        Some((_, total)) => *total += usage,
+        None => usage_by_model.push((model, usage)),
+    }
+}
+
 /// Builder for creating AI agents with custom configuration
 ///
 /// # Example
@@ -286,10 +928,29 @@ impl AgentInstance {
 pub struct AgentBuilder {
     provider: Provider,
     api_key: String,
+    api_base: Option<String>,
     model_name: String,
+    mock_script: Option<MockScript>,
+    /// Model shared with a parent agent so one script drives both
+    mock_model: Option<MockCompletionModel>,
+    preamble: Option<String>,
+    prompts: PromptLoader,
+    memory: Option<ProjectMemory>,
+    settings: GenerationSettings,
+    cancel: CancelSignal,
+    tool_progress: bool,
+    retry_policy: RetryPolicy,
+    fallback_models: Vec<String>,
+    prices: PriceTable,
+    budget: Option<Budget>,
+    allowed_tools: Option<Vec<String>>,
+    sub_agents: bool,
     plan_manager: PlanManager,
+    file_history: FileHistory,
+    session_store: Option<Arc<dyn SessionStore>>,
     context: Option<ContextManager>,
     skill_manager: Option<SkillManager>,
+    skill_catalog: Option<SharedSkills>,
     tool_registry: ToolRegistry,
 }
 
@@ -306,15 +967,275 @@ impl AgentBuilder {
     /// Returns a builder that can be configured and built into an agent
     pub fn new(api_key: String, model_name: String) -> Result<Self> {
         let provider = Self::get_provider_from_model(&model_name)?;
-        Ok(Self {
+        Ok(Self::for_provider(provider, api_key, model_name))
+    }
+
+    /// Create a new agent builder for an explicit provider
+    ///
+    /// Unlike [`AgentBuilder::new`], the provider is not inferred from the model name,
+    /// so any model name is accepted.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The LLM provider to use
+    /// * `api_key` - API key for the LLM provider
+    /// * `model_name` - Model name as understood by the provider
+    pub fn for_provider(provider: Provider, api_key: String, model_name: String) -> Self {
+        Self {
             provider,
             api_key,
+            api_base: None,
             model_name,
+            mock_script: None,
+            mock_model: None,
+            preamble: None,
+            prompts: PromptLoader::default(),
+            memory: None,
+            settings: GenerationSettings::default(),
+            cancel: CancelSignal::new(),
+            tool_progress: true,
+            retry_policy: RetryPolicy::default(),
+            fallback_models: Vec::new(),
+            prices: PriceTable::default(),
+            budget: None,
+            allowed_tools: None,
+            sub_agents: true,
             plan_manager: PlanManager::new(),
+            file_history: FileHistory::new(),
+            session_store: None,
             context: None,
             skill_manager: None,
+            skill_catalog: None,
             tool_registry: ToolRegistry::new(),
-        })
+        }
+    }
+
+    /// Create a new agent builder from a loaded configuration
+    ///
+    /// Uses the `provider` key when present and falls back to inferring
+    /// the provider from the model name otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration loaded from `.kota/config.lua`
+    pub fn from_config(config: &KotaConfig) -> Result<Self> {
+        let provider = match &config.provider {
+            Some(provider) => provider.clone(),
+            None => Self::get_provider_from_model(&config.model)?,
+        };
+        let mut builder =
+            Self::for_provider(provider, config.api_key.clone(), config.model.clone())
+                .with_settings(config.generation_settings())
+                .with_retry_policy(config.retry.clone())
+                .with_fallback_models(config.fallback_models.clone())
+                .with_price_table(config.prices.clone());
+        if let Some(api_base) = &config.api_base {
+            builder = builder.with_api_base(api_base.clone());
+        }
+        if let Some(budget) = config.budget {
+            builder = builder.with_budget(budget);
+        }
+        if let Some(path) = &config.mock_script {
+            builder = builder.with_mock_script(MockScript::from_file(path)?);
+        }
+        Ok(builder)
+    }
+
+    /// Set the base URL of the provider API
+    ///
+    /// Required by [`Provider::OpenAICompatible`]; other providers use their default endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_base` - Base URL such as `http://localhost:8000/v1`
+    pub fn with_api_base(mut self, api_base: String) -> Self {
+        self.api_base = Some(api_base);
+        self
+    }
+
+    /// Set the script replayed by [`Provider::Mock`]
+    ///
+    /// # Arguments
+    ///
+    /// * `script` - The scripted assistant turns
+    pub fn with_mock_script(mut self, script: MockScript) -> Self {
+        self.mock_script = Some(script);
+        self
+    }
+
+    /// Replace the system prompt
+    ///
+    /// The preamble is otherwise loaded from `.kota/prompts`. Placeholders such as
+    /// `{{cwd}}` and `{{tools}}` are substituted like in prompt files.
+    ///
+    /// # Arguments
+    ///
+    /// * `preamble` - System prompt template for the agent
+    pub fn with_preamble(mut self, preamble: impl Into<String>) -> Self {
+        self.preamble = Some(preamble.into());
+        self
+    }
+
+    /// Load the system prompt from another directory than `.kota/prompts`
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory containing `init.lua` or `system.md`
+    pub fn with_prompts_dir<P: AsRef<std::path::Path>>(mut self, dir: P) -> Self {
+        self.prompts = PromptLoader::new(dir);
+        self
+    }
+
+    /// Use the given memory files instead of discovering `KOTA.md` files
+    ///
+    /// By default the project memory is discovered from the current directory,
+    /// its parents and `~/.kota`.
+    ///
+    /// # Arguments
+    ///
+    /// * `memory` - Memory files appended to the system prompt
+    pub fn with_memory(mut self, memory: ProjectMemory) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Set all generation settings at once
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Sampling parameters and limits for every request
+    pub fn with_settings(mut self, settings: GenerationSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Set the sampling temperature
+    ///
+    /// # Arguments
+    ///
+    /// * `temperature` - Sampling temperature passed to the provider
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.settings.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the nucleus sampling probability
+    ///
+    /// # Arguments
+    ///
+    /// * `top_p` - Top-p value passed to the provider
+    pub fn with_top_p(mut self, top_p: f64) -> Self {
+        self.settings.top_p = Some(top_p);
+        self
+    }
+
+    /// Set the maximum number of output tokens per completion call
+    ///
+    /// # Arguments
+    ///
+    /// * `max_tokens` - Output token limit, 4096 by default
+    pub fn with_max_tokens(mut self, max_tokens: u64) -> Self {
+        self.settings.max_tokens = max_tokens;
+        self
+    }
+
+    /// Set the maximum depth of the tool loop
+    ///
+    /// # Arguments
+    ///
+    /// * `max_turns` - Number of tool-calling turns allowed per prompt, 20 by default
+    pub fn with_max_turns(mut self, max_turns: usize) -> Self {
+        self.settings.max_turns = max_turns;
+        self
+    }
+
+    /// Share a cancel signal with the agent and its tools
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel` - Signal used to cancel the running turn
+    pub fn with_cancel_signal(mut self, cancel: CancelSignal) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Set the retry policy for rate limits and server errors
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Retry count and backoff, use [`RetryPolicy::none`] to disable retries
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Set the models tried in order when the primary model keeps failing
+    ///
+    /// Fallback models are served by the same provider and endpoint as the primary model.
+    ///
+    /// # Arguments
+    ///
+    /// * `models` - Ordered list of fallback model names
+    pub fn with_fallback_models(mut self, models: Vec<String>) -> Self {
+        self.fallback_models = models;
+        self
+    }
+
+    /// Set the per-model prices used to estimate the session cost
+    ///
+    /// # Arguments
+    ///
+    /// * `prices` - Prices in USD per million tokens, keyed by model name
+    pub fn with_price_table(mut self, prices: PriceTable) -> Self {
+        self.prices = prices;
+        self
+    }
+
+    /// Set spending limits for the session
+    ///
+    /// Token usage is tracked by the context manager, so the budget only applies
+    /// to agents built with [`AgentBuilder::with_context`].
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - Cost and token limits, new turns are refused once one is reached
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Restrict the agent to the named tools
+    ///
+    /// Building fails when a name does not match a registered tool.
+    ///
+    /// # Arguments
+    ///
+    /// * `tools` - Names of the tools the agent may use
+    pub fn with_allowed_tools(mut self, tools: Vec<String>) -> Self {
+        self.allowed_tools = Some(tools);
+        self
+    }
+
+    /// Enable or disable the `delegate_task` tool
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether the agent may delegate tasks to sub-agents, true by default
+    pub fn with_sub_agents(mut self, enabled: bool) -> Self {
+        self.sub_agents = enabled;
+        self
+    }
+
+    /// Enable or disable the progress lines built-in tools print to stdout
+    ///
+    /// Disable it when stdout is consumed by a program, e.g. in headless mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether built-in tools print their progress
+    pub fn with_tool_progress(mut self, enabled: bool) -> Self {
+        self.tool_progress = enabled;
+        self
     }
 
     /// Set a custom plan manager for task management
@@ -327,6 +1248,16 @@ impl AgentBuilder {
         self
     }
 
+    /// Record the file changes of the tools in `history`, used to undo turns
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - A FileHistory shared with the file tools
+    pub fn with_file_history(mut self, history: FileHistory) -> Self {
+        self.file_history = history;
+        self
+    }
+
     /// Set a context manager for conversation history
     ///
     /// # Arguments
@@ -337,6 +1268,19 @@ impl AgentBuilder {
         self
     }
 
+    /// Save sessions in `store` instead of the store of the context
+    ///
+    /// Applies to the context set with [`AgentBuilder::with_context`] and to the
+    /// sessions of sub-agents. Without a context, a new session is started in `store`.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - A SessionStore such as FileStore, SqliteStore or MemoryStore
+    pub fn with_session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.session_store = Some(store);
+        self
+    }
+
     /// Set a skill manager for specialized agent behaviors
     ///
     /// # Arguments
@@ -353,9 +1297,49 @@ impl AgentBuilder {
     ///
     /// Returns an AgentInstance that includes the agent, context manager, and skill manager
     pub fn build(mut self) -> Result<AgentInstance> {
+        // The mock model is created up front so sub-agents can share its script
+        let mock_model = match self.provider {
+            Provider::Mock => Some(match (&self.mock_model, &self.mock_script) {
+                (Some(model), _) => model.clone(),
+                (None, Some(script)) => MockCompletionModel::new(script.clone()),
+                (None, None) => {
+                    anyhow::bail!("A mock script is required for the mock provider")
+                }
+            }),
+            _ => None,
+        };
+
+        if let Some(store) = self.session_store.take() {
+            self.context = Some(match self.context.take() {
+                Some(context) => context.with_store(store),
+                None => ContextManager::from_store(
+                    store,
+                    format!("session-{}", chrono::Utc::now().format("%Y%m%d-%H%M%S-%f")),
+                ),
+            });
+        }
+
+        let memory = self
+            .memory
+            .get_or_insert_with(ProjectMemory::discover_default)
+            .clone();
+
+        // Skills read by `read_skill_resource`, updated when the skills are reloaded
+        self.skill_catalog = self
+            .skill_manager
+            .as_ref()
+            .map(|manager| Arc::new(RwLock::new(skill_list(manager))));
+
         // Register built-in tools first
         self.register_builtin_tools();
 
+        let sub_agents = self
+            .sub_agents
+            .then(|| SubAgentFactory::from_builder(&self, mock_model.clone()));
+        if let Some(factory) = &sub_agents {
+            self.register_delegate_tool(factory.clone());
+        }
+
         // Load Lua tools from .kota/tools/mod.lua
         let lua_tools = LuaToolLoader::load_tools().unwrap_or_else(|e| {
             eprintln!("Warning: Failed to load Lua tools: {}", e);
@@ -367,83 +1351,247 @@ impl AgentBuilder {
             self.tool_registry.add(Box::new(tool));
         }
 
-        let preamble = self.get_preamble();
+        if let Some(allowed) = &self.allowed_tools {
+            let registered = self.tool_registry.names();
+            if let Some(unknown) = allowed.iter().find(|name| !registered.contains(name)) {
+                anyhow::bail!(
+                    "Unknown tool '{}', available tools: {}",
+                    unknown,
+                    registered.join(", ")
+                );
+            }
+            self.tool_registry
+                .retain(|tool| allowed.contains(&tool.name()));
+        }
+
+        let base_preamble = self.preamble();
+        let preamble = compose_preamble(&base_preamble, self.skill_manager.as_ref(), &memory);
+        let http_client =
+            RetryingHttpClient::new(self.retry_policy.clone(), self.fallback_models.clone());
+        let tool_outcomes = ToolOutcomes::default();
+        // 保留工具的共享句柄, skill 限制工具后可以重新注册
+        let base_tools: Vec<SharedTool> = self
+            .tool_registry
+            .take_all()
+            .into_iter()
+            .map(|tool| SharedTool(tool.into(), tool_outcomes.clone()))
+            .collect();
+        let dyn_tools = || -> Vec<Box<dyn rig::tool::ToolDyn>> {
+            base_tools
+                .iter()
+                .map(|tool| Box::new(tool.clone()) as Box<dyn rig::tool::ToolDyn>)
+                .collect()
+        };
 
         let agent = match self.provider {
             Provider::OpenAI => {
                 build_agent!(
-                    openai::Client::new(&self.api_key),
+                    openai::Client::<RetryingHttpClient>::builder()
+                        .api_key(&self.api_key)
+                        .http_client(http_client)
+                        .build(),
                     &self.model_name,
                     preamble,
-                    self.tool_registry.take_all(),
+                    self.settings,
+                    dyn_tools(),
                     OpenAI
                 )
             }
+            Provider::OpenAICompatible => {
+                let api_base = self.api_base.as_deref().ok_or_else(|| {
+                    anyhow::anyhow!("api_base is required for the openai-compatible provider")
+                })?;
+                build_agent!(
+                    openai::CompletionsClient::<RetryingHttpClient>::builder()
+                        .api_key(&self.api_key)
+                        .base_url(api_base)
+                        .http_client(http_client)
+                        .build(),
+                    &self.model_name,
+                    preamble,
+                    self.settings,
+                    dyn_tools(),
+                    OpenAICompatible
+                )
+            }
             Provider::Anthropic => {
                 build_agent!(
-                    anthropic::Client::new(&self.api_key),
+                    anthropic::Client::<RetryingHttpClient>::builder()
+                        .api_key(&self.api_key)
+                        .http_client(http_client)
+                        .build(),
                     &self.model_name,
                     preamble,
-                    self.tool_registry.take_all(),
+                    self.settings,
+                    dyn_tools(),
                     Anthropic
                 )
             }
             Provider::Cohere => {
                 build_agent!(
-                    cohere::Client::new(&self.api_key),
+                    cohere::Client::<RetryingHttpClient>::builder()
+                        .api_key(&self.api_key)
+                        .http_client(http_client)
+                        .build(),
                     &self.model_name,
                     preamble,
-                    self.tool_registry.take_all(),
+                    self.settings,
+                    dyn_tools(),
                     Cohere
                 )
             }
             Provider::DeepSeek => {
                 build_agent!(
-                    deepseek::Client::new(&self.api_key),
-                    DEEPSEEK_CHAT,
+                    deepseek::Client::<RetryingHttpClient>::builder()
+                        .api_key(&self.api_key)
+                        .http_client(http_client)
+                        .build(),
+                    &self.model_name,
                     preamble,
-                    self.tool_registry.take_all(),
+                    self.settings,
+                    dyn_tools(),
                     DeepSeek
                 )
             }
             Provider::Ollama => {
                 build_agent!(
-                    ollama::Client::new(rig::client::Nothing),
-                    self.model_name.strip_prefix("ollama-").unwrap(),
+                    ollama::Client::<RetryingHttpClient>::builder()
+                        .api_key(rig::client::Nothing)
+                        .http_client(http_client)
+                        .build(),
+                    self.model_name
+                        .strip_prefix("ollama-")
+                        .unwrap_or(&self.model_name),
                     preamble,
-                    self.tool_registry.take_all(),
+                    self.settings,
+                    dyn_tools(),
                     Ollama
                 )
             }
+            Provider::Mock => {
+                let model = mock_model.expect("mock model is created for the mock provider");
+                let mut agent = rig::agent::AgentBuilder::new(model)
+                    .preamble(&preamble)
+                    .tools(dyn_tools())
+                    .build();
+                self.settings.apply_to(&mut agent);
+                AgentType::Mock(agent)
+            }
         };
 
+        let base_model = dispatch_agent!(&agent, agent => agent.model.model.clone());
         Ok(AgentInstance {
             agent,
+            settings: self.settings,
             context: self.context,
+            file_history: self.file_history,
             skill_manager: self.skill_manager,
+            skill_catalog: self.skill_catalog,
             tool_registry: self.tool_registry,
+            cancel: self.cancel,
+            model_name: self.model_name,
+            prices: self.prices,
+            budget: self.budget,
+            sub_agents,
+            memory,
+            base_preamble,
+            base_tools,
+            disabled_tools: Vec::new(),
+            skill_tools: Vec::new(),
+            base_model,
+            tool_outcomes,
+            tool_progress: self.tool_progress,
         })
     }
 
+    /// Register the `delegate_task` tool
+    fn register_delegate_tool(&mut self, factory: SubAgentFactory) {
+        if self.tool_progress {
+            self.tool_registry
+                .add(Box::new(WrappedDelegateTaskTool::new(factory)));
+        } else {
+            self.tool_registry
+                .add(Box::new(DelegateTaskTool::new(factory)));
+        }
+    }
+
     /// Register all built-in tools to the tool registry
     fn register_builtin_tools(&mut self) {
+        if !self.tool_progress {
+            self.register_quiet_builtin_tools();
+            return;
+        }
         self.tool_registry.add(Box::new(WrappedReadFileTool::new()));
+        let history = &self.file_history;
+        self.tool_registry.add(Box::new(SnapshotTool::new(
+            WrappedWriteFileTool::new(),
+            history.clone(),
+        )));
+        self.tool_registry.add(Box::new(SnapshotTool::new(
+            WrappedEditFileTool::new(),
+            history.clone(),
+        )));
+        self.tool_registry.add(Box::new(SnapshotTool::new(
+            WrappedDeleteFileTool::new(),
+            history.clone(),
+        )));
         self.tool_registry
-            .add(Box::new(WrappedWriteFileTool::new()));
-        self.tool_registry.add(Box::new(WrappedEditFileTool::new()));
-        self.tool_registry
-            .add(Box::new(WrappedDeleteFileTool::new()));
-        self.tool_registry
-            .add(Box::new(WrappedExecuteBashCommandTool::new()));
+            .add(Box::new(WrappedExecuteBashCommandTool::new(
+                self.cancel.clone(),
+            )));
         self.tool_registry
             .add(Box::new(WrappedScanCodebaseTool::new()));
-        self.tool_registry
-            .add(Box::new(WrappedCreateDirectoryTool::new()));
+        self.tool_registry.add(Box::new(SnapshotTool::new(
+            WrappedCreateDirectoryTool::new(),
+            self.file_history.clone(),
+        )));
         self.tool_registry
             .add(Box::new(WrappedGrepSearchTool::new()));
         self.tool_registry.add(Box::new(WrappedUpdatePlanTool::new(
             self.plan_manager.clone(),
         )));
+        if let Some(memory) = &self.memory {
+            self.tool_registry.add(Box::new(SnapshotTool::new(
+                WrappedRememberTool::new(memory.project_file(), memory.global_file()),
+                self.file_history.clone(),
+            )));
+        }
+        if let Some(skills) = self.skill_catalog.clone() {
+            self.tool_registry
+                .add(Box::new(WrappedReadSkillResourceTool::new(skills)));
+        }
+    }
+
+    /// Register the built-in tools without their terminal progress output
+    fn register_quiet_builtin_tools(&mut self) {
+        self.tool_registry.add(Box::new(ReadFileTool));
+        let history = &self.file_history;
+        self.tool_registry
+            .add(Box::new(SnapshotTool::new(WriteFileTool, history.clone())));
+        self.tool_registry
+            .add(Box::new(SnapshotTool::new(EditFileTool, history.clone())));
+        self.tool_registry
+            .add(Box::new(SnapshotTool::new(DeleteFileTool, history.clone())));
+        self.tool_registry
+            .add(Box::new(ExecuteBashCommandTool::new(self.cancel.clone())));
+        self.tool_registry.add(Box::new(ScanCodebaseTool));
+        self.tool_registry.add(Box::new(SnapshotTool::new(
+            CreateDirectoryTool,
+            self.file_history.clone(),
+        )));
+        self.tool_registry.add(Box::new(GrepSearchTool));
+        self.tool_registry
+            .add(Box::new(UpdatePlanTool::new(self.plan_manager.clone())));
+        if let Some(memory) = &self.memory {
+            self.tool_registry.add(Box::new(SnapshotTool::new(
+                RememberTool::new(memory.project_file(), memory.global_file()),
+                self.file_history.clone(),
+            )));
+        }
+        if let Some(skills) = self.skill_catalog.clone() {
+            self.tool_registry
+                .add(Box::new(ReadSkillResourceTool::new(skills)));
+        }
     }
 
     fn get_provider_from_model(model_name: &str) -> Result<Provider> {
@@ -459,30 +1607,40 @@ impl AgentBuilder {
 
             // DeepSeek models
             name if name.starts_with("deepseek-") => Ok(Provider::DeepSeek),
-            
+
             // Models by ollama
             name if name.starts_with("ollama-") => Ok(Provider::Ollama),
 
             _ => Err(anyhow::anyhow!(
-                "Unknown model: {}. Please specify a supported model.",
+                "Unknown model: {}. Please specify a supported model or set `provider` explicitly.",
                 model_name
             )),
         }
     }
 
-    fn get_preamble(&self) -> &str {
-        r#"
-        Your name is Kato. You are a helpful AI code assistant with comprehensive file system and command execution access. 
-        You can read, write, edit (with patches), and delete files, execute bash commands, scan codebase structures, search text in the codebase and create directories. 
-        Use the edit_file tool for making small, targeted changes to existing files - it's more efficient than rewriting entire files.
-        
-        You also have access to Plan Mode via the update_plan tool. Use it to:
-        - Create structured execution plans for complex tasks
-        - Break down work into manageable tasks with dependencies
-        - Track progress and update task status (pending, in_progress, completed, blocked)
-        - Show current plan and identify next available tasks
-        
-        Please provide clear and concise responses and be careful when modifying files or executing commands."#
+    /// Render the preamble from the override or `.kota/prompts`
+    fn preamble(&self) -> String {
+        let variables = PromptVariables::collect(self.tool_registry.names());
+        match &self.preamble {
+            Some(template) => variables.render(template),
+            None => self.prompts.load_or_default(&variables),
+        }
+    }
+}
+
+fn skill_list(manager: &SkillManager) -> Vec<Skill> {
+    manager.list_skills().into_iter().cloned().collect()
+}
+
+/// Append the active skills and the memory section to the system prompt
+fn compose_preamble(base: &str, skills: Option<&SkillManager>, memory: &ProjectMemory) -> String {
+    let preamble = match memory.render() {
+        Some(section) => format!("{}\n\n{}", base, section),
+        None => base.to_string(),
+    };
+    match skills {
+        Some(skills) => skills.get_enhanced_preamble(&preamble),
+        None => preamble,
     }
 }
 
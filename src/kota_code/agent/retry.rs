@@ -0,0 +1,350 @@
+//! Retries and model fallback for provider HTTP requests
+//!
+//! [`RetryingHttpClient`] is plugged into every rig provider client. Each completion
+//! request is retried with exponential backoff when the provider answers with a
+//! rate limit (429) or server error (5xx), honouring the `Retry-After` header.
+//! When the primary model keeps failing, the request is replayed with each model
+//! of the fallback list in order; a model the provider rejects as unknown (a 404
+//! or a `model_not_found` error code) is skipped without retrying. Retrying per
+//! request means a failure in the middle of a tool loop never runs the previous
+//! tool calls again.
+
+use bytes::Bytes;
+use futures::StreamExt;
+use rig::http_client::{
+    self, sse::BoxedStream, HeaderMap, HttpClientExt, LazyBody, MultipartForm, Request, Response,
+};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// When and how often failed provider requests are retried
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries per model after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound for the backoff and for `Retry-After` delays
+    pub max_backoff: Duration,
+    /// Factor applied to the delay after every retry
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Backoff before retry number `retry` (starting at 0)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let delay = self.initial_backoff.as_secs_f64() * self.multiplier.powi(retry as i32);
+        Duration::from_secs_f64(delay.min(self.max_backoff.as_secs_f64()))
+    }
+
+    /// Delay before the next retry, preferring the server's `Retry-After` header
+    fn delay(&self, retry: u32, headers: Option<&HeaderMap>) -> Duration {
+        headers
+            .and_then(parse_retry_after)
+            .map(|delay| delay.min(self.max_backoff))
+            .unwrap_or_else(|| self.backoff(retry))
+    }
+}
+
+/// Whether a response status is worth retrying
+pub fn is_retryable_status(status: u16) -> bool {
+    status == 408 || status == 429 || (500..=599).contains(&status)
+}
+
+/// Whether an error response means the requested model does not exist
+///
+/// Such errors are not retried, but the next fallback model may still work.
+/// Besides a 404, only the OpenAI-style `model_not_found` error code counts;
+/// other bad requests that merely mention the model are reported as is.
+pub fn is_model_error(status: u16, message: &str) -> bool {
+    if status == 404 {
+        return true;
+    }
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(message) else {
+        return false;
+    };
+    let code = json
+        .pointer("/error/code")
+        .or_else(|| json.get("code"))
+        .and_then(|code| code.as_str());
+    code == Some("model_not_found")
+}
+
+/// Parse a `Retry-After` header given in seconds or as an HTTP date
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get("retry-after")?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return (seconds >= 0.0).then(|| Duration::from_secs_f64(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// HTTP client for rig providers that retries and falls back to other models
+#[derive(Debug, Clone, Default)]
+pub struct RetryingHttpClient {
+    inner: reqwest::Client,
+    policy: RetryPolicy,
+    fallback_models: Arc<Vec<String>>,
+}
+
+/// Error of a request that still failed after its retries and fallback models
+///
+/// Requests that failed on the first attempt, such as rejected API keys, are
+/// reported with the provider's error as is.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Gave up after {attempts} attempts with {models} model(s): {message}")]
+pub struct RetriesExhausted {
+    /// Requests sent, the first attempt included
+    pub attempts: u32,
+    /// Models tried, the requested one included
+    pub models: usize,
+    /// Error of the last attempt
+    pub message: String,
+}
+
+tokio::task_local! {
+    static REQUESTS: RequestScope;
+}
+
+#[derive(Debug, Default)]
+struct RequestReport {
+    served_model: Option<String>,
+    exhausted: Option<RetriesExhausted>,
+}
+
+/// What the provider requests of one turn reported
+///
+/// Requests only report to the scope whose [`RequestScope::scope`] they run in,
+/// so concurrent turns and sub-agents never see each other's requests. rig
+/// turns some provider errors into plain strings, the scope keeps
+/// [`RetriesExhausted`] around for the turn's error.
+#[derive(Debug, Clone, Default)]
+pub struct RequestScope(Arc<Mutex<RequestReport>>);
+
+impl RequestScope {
+    /// Run `future` with its provider requests reporting to this scope
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        REQUESTS.scope(self.clone(), future).await
+    }
+
+    /// Model that answered the latest successful request
+    ///
+    /// Differs from the requested model after a fallback. `None` before the first
+    /// success and for providers that do not name the model in the request body.
+    pub fn served_model(&self) -> Option<String> {
+        self.0.lock().unwrap().served_model.clone()
+    }
+
+    /// The latest request that ran out of retries and fallback models
+    pub fn exhausted(&self) -> Option<RetriesExhausted> {
+        self.0.lock().unwrap().exhausted.clone()
+    }
+
+    /// Record into the scope of the running task, if any
+    fn report(update: impl FnOnce(&mut RequestReport)) {
+        let _ = REQUESTS.try_with(|requests| update(&mut requests.0.lock().unwrap()));
+    }
+}
+
+impl RetryingHttpClient {
+    pub fn new(policy: RetryPolicy, fallback_models: Vec<String>) -> Self {
+        Self {
+            inner: reqwest::Client::new(),
+            policy,
+            fallback_models: Arc::new(fallback_models),
+        }
+    }
+
+    /// Send a request until it succeeds or every model ran out of retries
+    async fn execute(
+        &self,
+        method: http_client::Method,
+        uri: String,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> http_client::Result<reqwest::Response> {
+        // 先用请求里的模型, 失败后依次替换为 fallback 模型
+        let mut bodies = vec![body.clone()];
+        bodies.extend(
+            self.fallback_models
+                .iter()
+                .filter_map(|model| replace_model(&body, model)),
+        );
+
+        let mut last_error = None;
+        let mut attempts = 0;
+        let mut models = 0;
+        'models: for body in bodies {
+            models += 1;
+            for retry in 0..=self.policy.max_retries {
+                attempts += 1;
+                let result = self
+                    .inner
+                    .request(method.clone(), &uri)
+                    .headers(headers.clone())
+                    .body(body.clone())
+                    .send()
+                    .await;
+
+                let delay = match result {
+                    Ok(response) if response.status().is_success() => {
+                        RequestScope::report(|report| report.served_model = model_of(&body));
+                        return Ok(response);
+                    }
+                    Ok(response) => {
+                        let status = response.status();
+                        let delay = self.policy.delay(retry, Some(response.headers()));
+                        let message = response.text().await.unwrap_or_default();
+                        let model_error = is_model_error(status.as_u16(), &message);
+                        let error =
+                            http_client::Error::InvalidStatusCodeWithMessage(status, message);
+                        if model_error {
+                            // 这个模型不可用, 直接换下一个 fallback 模型
+                            last_error = Some(error);
+                            continue 'models;
+                        }
+                        if !is_retryable_status(status.as_u16()) {
+                            return Err(error);
+                        }
+                        last_error = Some(error);
+                        delay
+                    }
+                    Err(e) if e.is_connect() || e.is_timeout() => {
+                        last_error = Some(http_client::Error::Instance(e.into()));
+                        self.policy.delay(retry, None)
+                    }
+                    Err(e) => return Err(http_client::Error::Instance(e.into())),
+                };
+
+                if retry < self.policy.max_retries {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+
+        let error = last_error.unwrap_or(http_client::Error::StreamEnded);
+        if attempts == 1 {
+            return Err(error);
+        }
+        let exhausted = RetriesExhausted {
+            attempts,
+            models,
+            message: error.to_string(),
+        };
+        RequestScope::report(|report| report.exhausted = Some(exhausted.clone()));
+        Err(http_client::Error::Instance(Box::new(exhausted)))
+    }
+}
+
+/// Copy of a JSON request body with its `model` field replaced
+fn replace_model(body: &Bytes, model: &str) -> Option<Bytes> {
+    let mut json: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let field = json.as_object_mut()?.get_mut("model")?;
+    *field = serde_json::Value::String(model.to_string());
+    serde_json::to_vec(&json).ok().map(Bytes::from)
+}
+
+/// `model` field of a JSON request body
+fn model_of(body: &Bytes) -> Option<String> {
+    let json: serde_json::Value = serde_json::from_slice(body).ok()?;
+    json.get("model")?.as_str().map(str::to_string)
+}
+
+/// Copy status and headers of a reqwest response into an `http` response builder
+fn response_builder(response: &reqwest::Response) -> http::response::Builder {
+    let mut builder = http::Response::builder()
+        .status(response.status())
+        .version(response.version());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = response.headers().clone();
+    }
+    builder
+}
+
+impl HttpClientExt for RetryingHttpClient {
+    fn send<T, U>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = http_client::Result<Response<LazyBody<U>>>> + Send + 'static
+    where
+        T: Into<Bytes> + Send,
+        U: From<Bytes> + Send + 'static,
+    {
+        let (parts, body) = req.into_parts();
+        let body = body.into();
+        let client = self.clone();
+
+        async move {
+            let response = client
+                .execute(parts.method, parts.uri.to_string(), parts.headers, body)
+                .await?;
+            let builder = response_builder(&response);
+            let body: LazyBody<U> = Box::pin(async move {
+                let bytes = response
+                    .bytes()
+                    .await
+                    .map_err(|e| http_client::Error::Instance(e.into()))?;
+                Ok(U::from(bytes))
+            });
+            builder.body(body).map_err(http_client::Error::Protocol)
+        }
+    }
+
+    fn send_multipart<U>(
+        &self,
+        req: Request<MultipartForm>,
+    ) -> impl Future<Output = http_client::Result<Response<LazyBody<U>>>> + Send + 'static
+    where
+        U: From<Bytes> + Send + 'static,
+    {
+        // Multipart bodies are not used for completions, so they are sent once
+        self.inner.send_multipart(req)
+    }
+
+    fn send_streaming<T>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = http_client::Result<http_client::StreamingResponse>> + Send
+    where
+        T: Into<Bytes>,
+    {
+        let (parts, body) = req.into_parts();
+        let body = body.into();
+        let client = self.clone();
+
+        async move {
+            let response = client
+                .execute(parts.method, parts.uri.to_string(), parts.headers, body)
+                .await?;
+            let builder = response_builder(&response);
+            let stream: BoxedStream = Box::pin(
+                response
+                    .bytes_stream()
+                    .map(|chunk| chunk.map_err(|e| http_client::Error::Instance(e.into()))),
+            );
+            builder.body(stream).map_err(http_client::Error::Protocol)
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Messages of one turn rebuilt from its events
+//!
+//! rig keeps the tool loop's messages to itself, so the turn is recorded from the
+//! [`AgentEvent`]s instead: every completion call becomes an assistant message with
+//! its reasoning, text and tool calls, followed by one user message per tool result.
+//! The completion call an event belongs to is counted by the session hook.
+
+use super::AgentEvent;
+use rig::completion::message::{
+    AssistantContent, Reasoning, ToolCall, ToolFunction, ToolResultContent, UserContent,
+};
+use rig::completion::Message;
+use rig::OneOrMany;
+
+/// Marker appended to turns cancelled by the user
+pub(crate) const INTERRUPTED: &str = "[Interrupted by user]";
+
+#[derive(Debug, Default)]
+pub(crate) struct TurnTranscript {
+    messages: Vec<Message>,
+    completion: usize,
+    content: Vec<AssistantContent>,
+    text: String,
+    /// Reasoning deltas not yet followed by a complete reasoning block
+    reasoning: String,
+    /// Position in `content` of the block built from the latest reasoning deltas
+    streamed_reasoning: Option<usize>,
+    results: Vec<Message>,
+}
+
+impl TurnTranscript {
+    /// Record an event of completion call number `completion`
+    pub fn record(&mut self, completion: usize, event: &AgentEvent) {
+        if completion != self.completion {
+            self.flush();
+            self.completion = completion;
+        }
+        match event {
+            AgentEvent::TextDelta { text } => {
+                self.flush_reasoning();
+                self.text.push_str(text);
+            }
+            AgentEvent::ReasoningDelta { reasoning } => {
+                self.flush_text();
+                self.reasoning.push_str(reasoning);
+            }
+            AgentEvent::Reasoning {
+                id,
+                reasoning,
+                signature,
+            } => {
+                let block = AssistantContent::Reasoning(
+                    Reasoning::new(reasoning)
+                        .optional_id(id.clone())
+                        .with_signature(signature.clone()),
+                );
+                // 完整的推理块取代之前收到的增量
+                self.reasoning.clear();
+                match self.streamed_reasoning.take() {
+                    Some(index) => self.content[index] = block,
+                    None => {
+                        self.flush_text();
+                        self.content.push(block);
+                    }
+                }
+            }
+            AgentEvent::ToolCallStart {
+                id,
+                call_id,
+                name,
+                arguments,
+            } => {
+                self.flush_reasoning();
+                self.flush_text();
+                let mut call = ToolCall::new(
+                    id.clone(),
+                    ToolFunction {
+                        name: name.clone(),
+                        arguments: arguments.clone(),
+                    },
+                );
+                call.call_id = call_id.clone();
+                self.content.push(AssistantContent::ToolCall(call));
+            }
+            AgentEvent::ToolResult {
+                id,
+                call_id,
+                content,
+                ..
+            } => {
+                let content = OneOrMany::one(ToolResultContent::text(content));
+                let result = match call_id {
+                    Some(call_id) => {
+                        UserContent::tool_result_with_call_id(id, call_id.clone(), content)
+                    }
+                    None => UserContent::tool_result(id, content),
+                };
+                self.results.push(Message::User {
+                    content: OneOrMany::one(result),
+                });
+            }
+            AgentEvent::FinalResponse(_) => self.flush(),
+            _ => {}
+        }
+    }
+
+    /// Messages of a finished turn, ending with `response`
+    pub fn finish(mut self, response: &str) -> Vec<Message> {
+        self.flush();
+        if !matches!(self.messages.last(), Some(Message::Assistant { .. })) {
+            self.messages.push(Message::assistant(response));
+        }
+        self.messages
+    }
+
+    /// Messages of a cancelled turn
+    ///
+    /// Tool calls that did not return are dropped, providers reject calls
+    /// without a result. The turn ends with the interrupted marker.
+    pub fn interrupt(self) -> Vec<Message> {
+        self.end_with(INTERRUPTED)
+    }
+
+    /// Messages of a turn that failed with `error`, ending with a failure marker
+    /// in place of the interrupted marker
+    pub fn fail(self, error: &str) -> Vec<Message> {
+        self.end_with(&format!("[Turn failed: {}]", error))
+    }
+
+    fn end_with(mut self, marker: &str) -> Vec<Message> {
+        self.flush_reasoning();
+        self.flush_text();
+        let answered: Vec<String> = self
+            .results
+            .iter()
+            .filter_map(|message| match message {
+                Message::User { content } => match content.first() {
+                    UserContent::ToolResult(result) => Some(result.id),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        self.content.retain(|content| match content {
+            AssistantContent::ToolCall(call) => answered.contains(&call.id),
+            _ => true,
+        });
+
+        if !self.results.is_empty() {
+            self.flush();
+            self.messages.push(Message::assistant(marker));
+            return self.messages;
+        }
+        // 没有工具结果时, 标记追加到已输出的回复末尾
+        match self.content.last_mut() {
+            Some(AssistantContent::Text(text)) => {
+                text.text = format!("{}\n\n{}", text.text.trim_end(), marker);
+            }
+            _ => self.content.push(AssistantContent::text(marker)),
+        }
+        self.flush();
+        self.messages
+    }
+
+    /// Reasoning streamed only as deltas becomes a block without id or signature
+    fn flush_reasoning(&mut self) {
+        if !self.reasoning.is_empty() {
+            let reasoning = std::mem::take(&mut self.reasoning);
+            self.streamed_reasoning = Some(self.content.len());
+            self.content
+                .push(AssistantContent::Reasoning(Reasoning::new(&reasoning)));
+        }
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.content.push(AssistantContent::text(text));
+        }
+    }
+
+    fn flush(&mut self) {
+        self.flush_reasoning();
+        self.flush_text();
+        self.streamed_reasoning = None;
+        if let Ok(content) = OneOrMany::many(std::mem::take(&mut self.content)) {
+            self.messages.push(Message::Assistant { id: None, content });
+        }
+        self.messages.append(&mut self.results);
+    }
+}
@@ -0,0 +1,96 @@
+//! Undo of the latest turn
+//!
+//! The messages of the turn are removed from the session and the files the
+//! tools changed during the turn are restored from their snapshots in
+//! [`FileHistory`](crate::kota_code::file_history::FileHistory).
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::AgentInstance;
+use crate::kota_code::context::SerializableMessage;
+use crate::kota_code::file_history::FileRestore;
+
+/// Result of undoing a turn
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Undo {
+    /// Request that started the undone turn
+    pub request: Option<String>,
+    /// Messages removed from the session
+    pub messages: usize,
+    /// Files restored or removed
+    pub files: FileRestore,
+}
+
+/// Error returned when a turn was undone but saving the session or reloading
+/// memory failed afterwards
+///
+/// The files are already rolled back at that point, so the [`Undo`] is kept
+/// for reporting what changed.
+#[derive(Debug, thiserror::Error)]
+#[error("Undid the last turn, but {error:#}")]
+pub struct UndoIncomplete {
+    pub undo: Undo,
+    pub error: anyhow::Error,
+}
+
+impl AgentInstance {
+    /// Undo the latest turn of the current session
+    ///
+    /// Removes the last request and everything after it from the session and
+    /// rolls back the changes `write_file`, `edit_file`, `delete_file`,
+    /// `make_dir` and `remember` made during that turn, including those of
+    /// sub-agents. Memory is reloaded when a `KOTA.md` was rolled back. Turns
+    /// loaded from a saved session have no file snapshots, only their messages
+    /// are removed. The session is saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` when there is nothing to undo. Fails with [`UndoIncomplete`]
+    /// once the turn is undone
+    pub fn undo(&mut self) -> Result<Option<Undo>> {
+        let session_id = self
+            .context()
+            .map(|c| c.session_id().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let removed = match self.context_mut() {
+            Some(context) => context.undo_last_turn(),
+            None => Vec::new(),
+        };
+        let files = self
+            .file_history
+            .undo(&session_id)
+            .map(|(_, restore)| restore)
+            .unwrap_or_default();
+        if removed.is_empty() && files.is_empty() {
+            return Ok(None);
+        }
+
+        // KOTA.md 被还原后 preamble 里的记忆也要更新
+        let memory_files = [Some(self.memory.project_file()), self.memory.global_file()];
+        let memory_changed = files
+            .restored
+            .iter()
+            .chain(&files.removed)
+            .any(|path| memory_files.iter().flatten().any(|file| file == path));
+        let undo = Undo {
+            request: removed
+                .first()
+                .map(|message| SerializableMessage::from(message).content),
+            messages: removed.len(),
+            files,
+        };
+
+        let mut result = Ok(());
+        if memory_changed {
+            result = self.reload_memory();
+        }
+        if let Some(context) = self.context_mut() {
+            result = result.and(context.save());
+        }
+        match result {
+            Ok(()) => Ok(Some(undo)),
+            Err(error) => Err(UndoIncomplete { undo, error }.into()),
+        }
+    }
+}
@@ -1,35 +1,221 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Result};
 use rig::completion::Message;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::BTreeSet;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::runtime::prompts::git_branch;
+use super::search::{SearchQuery, SessionFilter, SessionMatch};
+use super::store::{FileStore, SessionStore};
+use super::tokens::{estimate_message_tokens, DEFAULT_CONTEXT_WINDOW};
+use super::usage::{SessionUsage, TokenUsage};
 
 /// 对话上下文管理器
 #[derive(Debug, Clone)]
 pub struct ContextManager {
-    storage_dir: PathBuf,
+    /// 保存会话的存储后端, 默认为 JSON 文件目录
+    store: Arc<dyn SessionStore>,
     session_id: String,
     messages: Vec<Message>,
-    max_messages: usize,
+    /// 历史消息的 token 预算, 超出时裁剪最早的消息
+    token_budget: usize,
+    /// 裁剪时保留的消息下标, 第一条用户请求总是保留
+    pinned: BTreeSet<usize>,
+    /// 被裁剪或压缩的原始消息, 只保存在会话文件中
+    archived: Vec<Message>,
+    compaction: CompactionPolicy,
+    usage: SessionUsage,
+    parent_session_id: Option<String>,
+    /// 分叉会话从父会话复制的消息数
+    forked_at: Option<usize>,
+    /// 会话创建时间, 保存时保持不变
+    created_at: String,
+    /// 手动设置或加载的标题, 为空时从第一条请求生成
+    title: Option<String>,
+    tags: Vec<String>,
+    /// 最近一轮激活的 skill
+    active_skills: Vec<String>,
+    /// 会话开始时的工作目录
+    working_dir: PathBuf,
+    /// 加载或上次保存时存储中的版本, 从未保存过时为 None
+    revision: Option<u64>,
+}
+
+/// Length of a title generated from the first request
+const TITLE_CHARS: usize = 60;
+
+/// Marker at the start of the message that replaces compacted messages
+pub const SUMMARY_HEADER: &str = "[Summary of the earlier conversation]";
+
+/// When the history is compacted, configured with `compaction` in `kota.setup`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionPolicy {
+    /// Compact before a turn once the history reaches `threshold`
+    pub auto: bool,
+    /// Fraction of the token budget at which automatic compaction starts
+    pub threshold: f64,
+    /// Fraction of the token budget kept verbatim as the most recent messages
+    pub keep_recent: f64,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            threshold: 0.8,
+            keep_recent: 0.3,
+        }
+    }
 }
 
 /// 会话元数据
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Fields added after the first release default to empty values for older session files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SessionMetadata {
     pub session_id: String,
     pub created_at: String,
     pub last_updated: String,
+    /// 每次保存加一, 用于发现其他进程的修改
+    #[serde(default)]
+    pub revision: u64,
     pub message_count: usize,
+    /// Token 用量, 旧的会话文件没有这个字段
+    #[serde(default)]
+    pub usage: SessionUsage,
+    /// 子代理会话或分叉会话所属的父会话
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_session_id: Option<String>,
+    /// 分叉时从父会话复制的消息数, 子代理会话没有这个字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_at: Option<usize>,
+    /// 固定的消息下标
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned: Vec<usize>,
+    /// 标题, 默认取第一条请求的开头
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// 最近一轮使用的模型
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 最近一轮激活的 skill
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub active_skills: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<PathBuf>,
+    /// 最近一次保存时工作目录所在的 git 分支
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl SessionMetadata {
+    /// Total tokens used by the session
+    pub fn total_tokens(&self) -> u64 {
+        self.usage.total.total_tokens()
+    }
 }
 
-/// 持久化的会话数据
-#[derive(Debug, Serialize, Deserialize)]
-struct SessionData {
-    pub metadata: SessionMetadata,
-    pub messages: Vec<SerializableMessage>,
+/// Order `sessions` as a tree of forks and sub-agent sessions
+///
+/// Each session follows its parent with its depth in the tree. Sessions whose
+/// parent is not in `sessions` are roots; siblings keep their order.
+pub fn session_tree(sessions: Vec<SessionMetadata>) -> Vec<(usize, SessionMetadata)> {
+    let ids: std::collections::HashSet<&str> = sessions
+        .iter()
+        .map(|session| session.session_id.as_str())
+        .collect();
+    let parent = |session: &SessionMetadata| {
+        session
+            .parent_session_id
+            .clone()
+            .filter(|parent| ids.contains(parent.as_str()) && parent != &session.session_id)
+    };
+    let parents: Vec<Option<String>> = sessions.iter().map(parent).collect();
+
+    let mut tree = Vec::with_capacity(sessions.len());
+    let mut visited = vec![false; sessions.len()];
+    // 先从根节点深度优先遍历, 再处理成环的剩余会话
+    let roots = (0..sessions.len())
+        .filter(|&i| parents[i].is_none())
+        .chain(0..sessions.len());
+    for root in roots {
+        let mut stack = vec![(root, 0)];
+        while let Some((index, depth)) = stack.pop() {
+            if std::mem::replace(&mut visited[index], true) {
+                continue;
+            }
+            tree.push((depth, index));
+            let id = &sessions[index].session_id;
+            stack.extend(
+                (0..sessions.len())
+                    .rev()
+                    .filter(|&i| parents[i].as_ref() == Some(id))
+                    .map(|i| (i, depth + 1)),
+            );
+        }
+    }
+
+    let mut sessions: Vec<Option<SessionMetadata>> = sessions.into_iter().map(Some).collect();
+    tree.into_iter()
+        .filter_map(|(depth, index)| sessions[index].take().map(|session| (depth, session)))
+        .collect()
 }
 
-/// 可序列化的消息类型
+/// 会话文件格式的版本
+///
+/// - 1: `{role, content}` 形式的纯文本消息, 文件中没有 `version` 字段
+/// - 2: 完整的 rig [`Message`], 包括工具调用 ID, 参数, 工具结果和推理内容
+pub const SESSION_VERSION: u32 = 2;
+
+/// Part of `context_window` the history may take
+fn history_budget(context_window: usize) -> usize {
+    context_window / 4 * 3
+}
+
+/// Ranges of messages trimmed together: a message and the tool results following it
+fn message_groups(messages: &[Message]) -> Vec<Range<usize>> {
+    let mut groups: Vec<Range<usize>> = Vec::new();
+    for (index, message) in messages.iter().enumerate() {
+        match groups.last_mut() {
+            Some(group) if is_tool_result(message) => group.end = index + 1,
+            _ => groups.push(index..index + 1),
+        }
+    }
+    groups
+}
+
+/// A user message that is not a tool result or a summary
+fn is_request(message: &Message) -> bool {
+    matches!(message, Message::User { .. }) && !is_tool_result(message) && !is_summary(message)
+}
+
+fn is_summary(message: &Message) -> bool {
+    match message {
+        Message::User { content } => matches!(
+            content.first(),
+            rig::completion::message::UserContent::Text(text) if text.text.starts_with(SUMMARY_HEADER)
+        ),
+        Message::Assistant { .. } => false,
+    }
+}
+
+fn is_tool_result(message: &Message) -> bool {
+    match message {
+        Message::User { content } => content
+            .iter()
+            .any(|c| matches!(c, rig::completion::message::UserContent::ToolResult(_))),
+        Message::Assistant { .. } => false,
+    }
+}
+
+/// 用于展示的消息摘要
+///
+/// 工具调用, 工具结果等非文本内容被概括为一行文字, 不能还原为原始消息.
+/// 也是版本 1 会话文件中消息的格式.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SerializableMessage {
     pub role: String,
@@ -38,33 +224,53 @@ pub struct SerializableMessage {
 
 impl From<&Message> for SerializableMessage {
     fn from(msg: &Message) -> Self {
+        use rig::completion::message::{AssistantContent, ToolResultContent, UserContent};
+
         match msg {
-            Message::User { content, .. } => Self {
-                role: "user".to_string(),
-                content: content
+            Message::User { content, .. } => {
+                // 只包含工具结果的用户消息以 tool 角色展示
+                let role = if content
                     .iter()
-                    .map(|c| match c {
-                        rig::completion::message::UserContent::Text(text) => text.text.clone(),
-                        _ => "[non-text content]".to_string(),
-                    })
-                    .collect::<Vec<_>>()
-                    .join(" "),
-            },
+                    .all(|c| matches!(c, UserContent::ToolResult(_)))
+                {
+                    "tool"
+                } else {
+                    "user"
+                };
+                Self {
+                    role: role.to_string(),
+                    content: content
+                        .iter()
+                        .map(|c| match c {
+                            UserContent::Text(text) => text.text.clone(),
+                            UserContent::ToolResult(result) => result
+                                .content
+                                .iter()
+                                .map(|c| match c {
+                                    ToolResultContent::Text(text) => text.text.clone(),
+                                    ToolResultContent::Image(_) => "[image]".to_string(),
+                                })
+                                .collect::<Vec<_>>()
+                                .join(" "),
+                            UserContent::Image(_) => "[image]".to_string(),
+                            _ => "[non-text content]".to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                }
+            }
             Message::Assistant { content, .. } => Self {
                 role: "assistant".to_string(),
                 content: content
                     .iter()
                     .map(|c| match c {
-                        rig::completion::message::AssistantContent::Text(text) => text.text.clone(),
-                        rig::completion::message::AssistantContent::ToolCall(_) => {
-                            "[tool call]".to_string()
-                        }
-                        rig::completion::message::AssistantContent::Reasoning(_) => {
-                            "[reasoning]".to_string()
-                        }
-                        rig::completion::message::AssistantContent::Image(_) => {
-                            "[image]".to_string()
-                        }
+                        AssistantContent::Text(text) => text.text.clone(),
+                        AssistantContent::ToolCall(call) => format!(
+                            "[tool call: {}({})]",
+                            call.function.name, call.function.arguments
+                        ),
+                        AssistantContent::Reasoning(_) => "[reasoning]".to_string(),
+                        AssistantContent::Image(_) => "[image]".to_string(),
                     })
                     .collect::<Vec<_>>()
                     .join(" "),
@@ -84,33 +290,262 @@ impl From<SerializableMessage> for Message {
 }
 
 impl ContextManager {
+    /// Save sessions as JSON files in `storage_dir`, see [`FileStore`]
     pub fn new<P: AsRef<Path>>(storage_dir: P, session_id: String) -> Result<Self> {
-        let storage_dir = storage_dir.as_ref().to_path_buf();
-        if !storage_dir.exists() {
-            fs::create_dir_all(&storage_dir).with_context(|| {
-                format!("Failed to create storage directory: {:?}", storage_dir)
-            })?;
-        }
-        Ok(Self {
-            storage_dir,
+        Ok(Self::from_store(
+            Arc::new(FileStore::new(storage_dir)?),
+            session_id,
+        ))
+    }
+
+    /// Save sessions in `store`
+    pub fn from_store(store: Arc<dyn SessionStore>, session_id: String) -> Self {
+        Self {
+            store,
             session_id,
             messages: Vec::new(),
-            max_messages: 100,
-        })
+            token_budget: history_budget(DEFAULT_CONTEXT_WINDOW),
+            pinned: BTreeSet::new(),
+            archived: Vec::new(),
+            compaction: CompactionPolicy::default(),
+            usage: SessionUsage::default(),
+            parent_session_id: None,
+            forked_at: None,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            title: None,
+            tags: Vec::new(),
+            active_skills: Vec::new(),
+            working_dir: std::env::current_dir().unwrap_or_default(),
+            revision: None,
+        }
+    }
+
+    /// Move the session to `store`, it is written there on the next save
+    pub fn with_store(self, store: Arc<dyn SessionStore>) -> Self {
+        Self {
+            store,
+            revision: None,
+            ..self
+        }
     }
 
-    pub fn with_max_messages(self, max_messages: usize) -> Self {
+    /// Link this session to the session of the agent that delegated it
+    pub fn with_parent_session(self, parent_session_id: String) -> Self {
         Self {
-            max_messages,
+            parent_session_id: Some(parent_session_id),
             ..self
         }
     }
 
+    /// Record `working_dir` instead of the current directory in the metadata
+    pub fn with_working_dir(mut self, working_dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = working_dir.into();
+        self
+    }
+
+    /// Limit the history to the part of `context_window` left for it
+    ///
+    /// A quarter of the window is kept free for the system prompt, tool
+    /// definitions and the response.
+    pub fn with_context_window(self, context_window: usize) -> Self {
+        self.with_token_budget(history_budget(context_window))
+    }
+
+    /// Limit the history to an estimated `token_budget` tokens
+    pub fn with_token_budget(mut self, token_budget: usize) -> Self {
+        self.token_budget = token_budget;
+        self.trim();
+        self
+    }
+
+    /// Estimated tokens the history may take
+    pub fn token_budget(&self) -> usize {
+        self.token_budget
+    }
+
+    /// Estimated tokens the history takes
+    pub fn estimated_tokens(&self) -> usize {
+        self.messages.iter().map(estimate_message_tokens).sum()
+    }
+
+    /// Add a message and trim the oldest messages once the history exceeds
+    /// the token budget
+    ///
+    /// A tool call is only dropped together with its results, pinned messages
+    /// and the latest message are never dropped.
     pub fn add_message(&mut self, message: Message) {
         self.messages.push(message);
-        if self.messages.len() > self.max_messages {
-            self.messages.remove(0);
+        self.trim();
+    }
+
+    /// Keep the message at `index` when the history is trimmed
+    ///
+    /// Returns `false` if there is no such message.
+    pub fn pin_message(&mut self, index: usize) -> bool {
+        if index >= self.messages.len() {
+            return false;
+        }
+        self.pinned.insert(index);
+        true
+    }
+
+    /// Let the message at `index` be trimmed again
+    ///
+    /// The first user request stays pinned.
+    pub fn unpin_message(&mut self, index: usize) -> bool {
+        self.pinned.remove(&index)
+    }
+
+    /// Whether the message at `index` is kept when the history is trimmed
+    pub fn is_pinned(&self, index: usize) -> bool {
+        self.pinned.contains(&index) || Some(index) == self.first_request()
+    }
+
+    /// Index of the first user message that is not a tool result
+    fn first_request(&self) -> Option<usize> {
+        self.messages
+            .iter()
+            .position(|message| matches!(message, Message::User { .. }) && !is_tool_result(message))
+    }
+
+    /// Drop the oldest unpinned messages until the history fits the budget
+    fn trim(&mut self) {
+        let mut tokens: Vec<usize> = self.messages.iter().map(estimate_message_tokens).collect();
+        let mut total: usize = tokens.iter().sum();
+        if total <= self.token_budget {
+            return;
+        }
+
+        // 工具调用和它的结果作为一组裁剪, 最后一组是最新的消息, 不裁剪
+        let groups = message_groups(&self.messages);
+        let mut dropped = vec![false; self.messages.len()];
+        for group in &groups[..groups.len().saturating_sub(1)] {
+            if total <= self.token_budget {
+                break;
+            }
+            if group.clone().any(|index| self.is_pinned(index)) {
+                continue;
+            }
+            for index in group.clone() {
+                dropped[index] = true;
+                total -= std::mem::take(&mut tokens[index]);
+            }
+        }
+
+        let mut kept = Vec::with_capacity(self.messages.len());
+        let mut pinned = BTreeSet::new();
+        for (index, message) in std::mem::take(&mut self.messages).into_iter().enumerate() {
+            if dropped[index] {
+                self.archived.push(message);
+                continue;
+            }
+            if self.pinned.contains(&index) {
+                pinned.insert(kept.len());
+            }
+            kept.push(message);
         }
+        self.messages = kept;
+        self.pinned = pinned;
+    }
+
+    pub fn with_compaction(mut self, compaction: CompactionPolicy) -> Self {
+        self.compaction = compaction;
+        self
+    }
+
+    pub fn compaction(&self) -> &CompactionPolicy {
+        &self.compaction
+    }
+
+    /// Whether the history should be compacted before the next turn
+    pub fn needs_compaction(&self) -> bool {
+        self.compaction.auto
+            && self.estimated_tokens() as f64
+                >= self.token_budget as f64 * self.compaction.threshold
+            && self.compaction_end().is_some()
+    }
+
+    /// End of the messages a compaction would summarize
+    ///
+    /// The most recent messages, up to `keep_recent` of the token budget and at
+    /// least the latest request, are kept verbatim. The compacted part always
+    /// ends before a user request, so tool calls stay with their results.
+    /// `None` when there is nothing to summarize.
+    pub fn compaction_end(&self) -> Option<usize> {
+        let keep_tokens = (self.token_budget as f64 * self.compaction.keep_recent) as usize;
+        let mut kept = 0;
+        let mut end = self.messages.len();
+        for (index, message) in self.messages.iter().enumerate().rev() {
+            if !is_request(message) {
+                continue;
+            }
+            let tokens: usize = self.messages[index..end]
+                .iter()
+                .map(estimate_message_tokens)
+                .sum();
+            // 最新的请求总是保留
+            if end < self.messages.len() && kept + tokens > keep_tokens {
+                break;
+            }
+            kept += tokens;
+            end = index;
+        }
+
+        // 只剩固定消息和之前的摘要时无需压缩
+        let summarizable =
+            (0..end).any(|index| !self.is_pinned(index) && !is_summary(&self.messages[index]));
+        summarizable.then_some(end)
+    }
+
+    /// Replace the messages before `end` with `summary`
+    ///
+    /// Pinned messages and the tool calls and results around them are kept in
+    /// front of the summary. The replaced messages are archived in the session
+    /// file.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of archived messages
+    pub fn compact(&mut self, end: usize, summary: &str) -> usize {
+        let end = end.min(self.messages.len());
+        let keep: BTreeSet<usize> = message_groups(&self.messages[..end])
+            .into_iter()
+            .filter(|group| group.clone().any(|index| self.is_pinned(index)))
+            .flatten()
+            .collect();
+
+        let recent = self.messages.split_off(end);
+        let mut messages = Vec::with_capacity(keep.len() + 1 + recent.len());
+        let mut pinned = BTreeSet::new();
+        let mut archived = 0;
+        for (index, message) in std::mem::take(&mut self.messages).into_iter().enumerate() {
+            if !keep.contains(&index) {
+                self.archived.push(message);
+                archived += 1;
+                continue;
+            }
+            if self.pinned.contains(&index) {
+                pinned.insert(messages.len());
+            }
+            messages.push(message);
+        }
+        messages.push(Message::user(format!(
+            "{}\n\n{}",
+            SUMMARY_HEADER,
+            summary.trim()
+        )));
+        let offset = messages.len();
+        pinned.extend(self.pinned.range(end..).map(|index| index - end + offset));
+        messages.extend(recent);
+
+        self.messages = messages;
+        self.pinned = pinned;
+        archived
+    }
+
+    /// Messages removed from the history by trimming or compaction
+    pub fn archived_messages(&self) -> &[Message] {
+        &self.archived
     }
 
     pub fn get_messages(&self) -> &[Message] {
@@ -121,93 +556,273 @@ impl ContextManager {
         &mut self.messages
     }
 
+    /// Record the token usage of one turn
+    pub fn record_usage(&mut self, model: &str, usage: TokenUsage) {
+        self.usage.record(model, usage);
+    }
+
+    /// Token usage accumulated over the session
+    pub fn usage(&self) -> &SessionUsage {
+        &self.usage
+    }
+
     pub fn clear(&mut self) {
         self.messages.clear();
+        self.pinned.clear();
+        self.archived.clear();
     }
 
-    pub fn save(&self) -> Result<()> {
-        let file_path = self.get_session_file_path();
-        let now = chrono::Utc::now().to_rfc3339();
-        let metadata = SessionMetadata {
-            session_id: self.session_id.clone(),
-            created_at: now.clone(),
-            last_updated: now,
-            message_count: self.messages.len(),
+    /// Remove the latest exchange: the last user request and every message after it
+    ///
+    /// Returns the removed messages, empty when the history has no request.
+    pub fn undo_last_turn(&mut self) -> Vec<Message> {
+        let Some(start) = self.messages.iter().rposition(is_request) else {
+            return Vec::new();
         };
-        let serializable_messages: Vec<SerializableMessage> = self
-            .messages
-            .iter()
-            .map(SerializableMessage::from)
-            .collect();
-        let session_data = SessionData {
-            metadata,
-            messages: serializable_messages,
+        self.pinned.retain(|&index| index < start);
+        self.messages.split_off(start)
+    }
+
+    /// Copy the history up to message `at_message_index` into a new session
+    ///
+    /// Tool results following that message are copied with it, so the fork
+    /// never ends with unanswered tool calls. The new session is linked to
+    /// this one through `parent_session_id`, saved and returned; this session
+    /// is left unchanged.
+    pub fn fork(&self, at_message_index: usize, new_session_id: String) -> Result<ContextManager> {
+        let Some(group) = message_groups(&self.messages)
+            .into_iter()
+            .find(|group| group.contains(&at_message_index))
+        else {
+            bail!(
+                "Message {} does not exist, the session has {} messages",
+                at_message_index,
+                self.messages.len()
+            );
+        };
+        let mut fork = ContextManager {
+            session_id: new_session_id,
+            messages: self.messages[..group.end].to_vec(),
+            pinned: self.pinned.range(..group.end).copied().collect(),
+            usage: SessionUsage::default(),
+            parent_session_id: Some(self.session_id.clone()),
+            forked_at: Some(group.end),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            revision: None,
+            ..self.clone()
         };
-        let json_data = serde_json::to_string_pretty(&session_data)
-            .context("Failed to serialize session data")?;
-        fs::write(&file_path, json_data)
-            .with_context(|| format!("Failed to write session file: {:?}", file_path))?;
+        if self.store.exists(&fork.session_id)? {
+            bail!("Session '{}' already exists", fork.session_id);
+        }
+        fork.save()?;
+        Ok(fork)
+    }
+
+    /// First unused `<session>-fork-<n>` session id
+    pub fn next_fork_id(&self) -> String {
+        (1..)
+            .map(|n| format!("{}-fork-{}", self.session_id, n))
+            .find(|id| !self.store.exists(id).unwrap_or(false))
+            .unwrap()
+    }
+
+    /// Messages copied from the parent session when this session was forked
+    pub fn forked_at(&self) -> Option<usize> {
+        self.forked_at
+    }
+
+    /// Save the session, always in the current [`SESSION_VERSION`]
+    ///
+    /// Fails with [`SessionConflict`](super::store::SessionConflict) if the
+    /// saved session changed since it was loaded or last saved here, for
+    /// example by another kota process working on the same session.
+    /// [`force_save`](Self::force_save) overwrites it anyway.
+    pub fn save(&mut self) -> Result<()> {
+        let metadata = self.metadata();
+        self.store
+            .save_if(self.revision, &metadata, &self.messages, &self.archived)?;
+        self.revision = Some(metadata.revision);
         Ok(())
     }
 
+    /// Save the session over the changes made elsewhere since it was loaded
+    pub fn force_save(&mut self) -> Result<()> {
+        self.revision = self.store.revision(&self.session_id)?;
+        self.save()
+    }
+
+    /// Whether the saved session changed since it was loaded or last saved here
+    pub fn changed_in_store(&self) -> Result<bool> {
+        Ok(self.store.revision(&self.session_id)? != self.revision)
+    }
+
+    /// Metadata of the session as it would be saved now
+    pub fn metadata(&self) -> SessionMetadata {
+        SessionMetadata {
+            session_id: self.session_id.clone(),
+            created_at: self.created_at.clone(),
+            last_updated: chrono::Utc::now().to_rfc3339(),
+            revision: self.revision.map_or(1, |revision| revision + 1),
+            message_count: self.messages.len(),
+            usage: self.usage.clone(),
+            parent_session_id: self.parent_session_id.clone(),
+            forked_at: self.forked_at,
+            pinned: self.pinned.iter().copied().collect(),
+            title: self.title(),
+            model: self.usage.turns.last().map(|turn| turn.model.clone()),
+            active_skills: self.active_skills.clone(),
+            working_dir: Some(self.working_dir.clone()),
+            git_branch: git_branch(&self.working_dir),
+            tags: self.tags.clone(),
+        }
+    }
+
+    /// Load the session, older session files are converted on the fly
     pub fn load(&mut self) -> Result<bool> {
-        let file_path = self.get_session_file_path();
-        if !file_path.exists() {
+        let Some(session_data) = self.store.load(&self.session_id)? else {
             return Ok(false);
+        };
+        self.messages = session_data.messages;
+        self.archived = session_data.archived;
+        self.usage = session_data.metadata.usage;
+        self.parent_session_id = session_data.metadata.parent_session_id;
+        self.forked_at = session_data.metadata.forked_at;
+        self.pinned = session_data.metadata.pinned.into_iter().collect();
+        self.created_at = session_data.metadata.created_at;
+        self.title = session_data.metadata.title;
+        self.tags = session_data.metadata.tags;
+        self.active_skills = session_data.metadata.active_skills;
+        if let Some(working_dir) = session_data.metadata.working_dir {
+            self.working_dir = working_dir;
         }
-        let json_data = fs::read_to_string(&file_path)
-            .with_context(|| format!("Failed to read session file: {:?}", file_path))?;
-        let session_data: SessionData =
-            serde_json::from_str(&json_data).context("Failed to deserialize session data")?;
-        self.messages = session_data
-            .messages
-            .into_iter()
-            .map(Message::from)
-            .collect();
+        self.revision = Some(session_data.metadata.revision);
+        self.trim();
         Ok(true)
     }
 
+    /// Saved sessions matching `filter`, most recently updated first
+    pub fn find_sessions(&self, filter: &SessionFilter) -> Result<Vec<SessionMetadata>> {
+        let mut sessions = self.list_sessions()?;
+        sessions.retain(|session| filter.matches(session));
+        Ok(sessions)
+    }
+
+    /// All saved sessions, most recently updated first
     pub fn list_sessions(&self) -> Result<Vec<SessionMetadata>> {
-        let mut sessions = Vec::new();
-        if !self.storage_dir.exists() {
-            return Ok(sessions);
-        }
-        for entry in fs::read_dir(&self.storage_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                if let Ok(json_data) = fs::read_to_string(&path) {
-                    if let Ok(session_data) = serde_json::from_str::<SessionData>(&json_data) {
-                        sessions.push(session_data.metadata);
-                    }
-                }
-            }
-        }
+        let mut sessions = self.store.list()?;
         sessions.sort_by(|a, b| b.last_updated.cmp(&a.last_updated));
         Ok(sessions)
     }
 
-    pub fn delete_session(&self) -> Result<bool> {
-        let file_path = self.get_session_file_path();
-        if file_path.exists() {
-            fs::remove_file(&file_path)
-                .with_context(|| format!("Failed to delete session file: {:?}", file_path))?;
-            Ok(true)
-        } else {
-            Ok(false)
+    /// Search the messages of all saved sessions
+    ///
+    /// Sessions are ranked by their matches, best first; see [`SearchQuery`]
+    /// for how matches are scored. Sessions that cannot be read are skipped.
+    pub fn search_sessions(&self, query: &str) -> Result<Vec<SessionMatch>> {
+        let query = SearchQuery::new(query);
+        if query.is_empty() {
+            return Ok(Vec::new());
         }
+        let mut matches = self.store.search(&query)?;
+        matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| b.last_updated.cmp(&a.last_updated))
+        });
+        Ok(matches)
     }
 
-    fn get_session_file_path(&self) -> PathBuf {
-        self.storage_dir.join(format!("{}.json", self.session_id))
+    /// Rewrite all sessions saved in an older version in the current [`SESSION_VERSION`]
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of migrated sessions
+    pub fn migrate_sessions(&self) -> Result<usize> {
+        self.store.migrate()
+    }
+
+    /// Delete the saved copy of this session, returns `false` if it was never saved
+    pub fn delete_session(&self) -> Result<bool> {
+        self.store.delete(&self.session_id)
     }
 
     pub fn session_id(&self) -> &str {
         &self.session_id
     }
 
+    /// Session of the agent that delegated this one, if any
+    pub fn parent_session_id(&self) -> Option<&str> {
+        self.parent_session_id.as_deref()
+    }
+
+    /// Store the session is saved in, shared with the sessions of sub-agents
+    pub fn store(&self) -> &Arc<dyn SessionStore> {
+        &self.store
+    }
+
     pub fn switch_session(&mut self, new_session_id: String) {
         self.session_id = new_session_id;
         self.messages.clear();
+        self.pinned.clear();
+        self.archived.clear();
+        self.usage = SessionUsage::default();
+        self.parent_session_id = None;
+        self.forked_at = None;
+        self.created_at = chrono::Utc::now().to_rfc3339();
+        self.title = None;
+        self.tags.clear();
+        self.active_skills.clear();
+        self.working_dir = std::env::current_dir().unwrap_or_default();
+        self.revision = None;
+    }
+
+    /// Title of the session, generated from the first request unless set
+    pub fn title(&self) -> Option<String> {
+        self.title.clone().or_else(|| {
+            let request = self.messages.iter().find(|message| is_request(message))?;
+            let text = SerializableMessage::from(request).content;
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if text.is_empty() {
+                return None;
+            }
+            Some(if text.chars().count() > TITLE_CHARS {
+                format!("{}…", text.chars().take(TITLE_CHARS).collect::<String>())
+            } else {
+                text
+            })
+        })
+    }
+
+    pub fn set_title(&mut self, title: impl Into<String>) {
+        self.title = Some(title.into());
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Add `tag` to the session, returns `false` if it already has it
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        if self.tags.iter().any(|t| t == tag) {
+            return false;
+        }
+        self.tags.push(tag.to_string());
+        true
+    }
+
+    /// Remove `tag` from the session, returns `false` if it did not have it
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let len = self.tags.len();
+        self.tags.retain(|t| t != tag);
+        self.tags.len() != len
+    }
+
+    /// Record the skills active during the latest turn
+    pub fn set_active_skills(&mut self, skills: Vec<String>) {
+        self.active_skills = skills;
+    }
+
+    pub fn created_at(&self) -> &str {
+        &self.created_at
     }
 }
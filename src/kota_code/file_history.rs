@@ -0,0 +1,200 @@
+//! Snapshots of the files changed by the tools, used to undo a turn
+//!
+//! The file tools record the state of a path before they change it. Snapshots
+//! are grouped by turn: the agent starts a turn before the model runs, and
+//! [`FileHistory::undo`] puts the files of the latest turn back as they were.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+/// Turns kept for undo, older snapshots are dropped
+const MAX_TURNS: usize = 20;
+
+/// State of a path before a tool changed it
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileState {
+    /// Nothing existed at the path
+    Missing,
+    /// A file with these contents
+    File(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSnapshot {
+    pub path: PathBuf,
+    pub before: FileState,
+}
+
+/// File changes of one turn
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurnChanges {
+    pub session_id: String,
+    /// First snapshot of every changed path, in the order they were taken
+    pub snapshots: Vec<FileSnapshot>,
+}
+
+/// Outcome of restoring the files of a turn
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FileRestore {
+    /// Files written back with their previous contents
+    pub restored: Vec<PathBuf>,
+    /// Files and directories the turn created
+    pub removed: Vec<PathBuf>,
+    /// Paths that could not be restored, with the reason
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl FileRestore {
+    pub fn is_empty(&self) -> bool {
+        self.restored.is_empty() && self.removed.is_empty() && self.failed.is_empty()
+    }
+}
+
+impl TurnChanges {
+    /// Put every path back in its state before the turn, latest change first
+    pub fn restore(&self) -> FileRestore {
+        let mut result = FileRestore::default();
+        for snapshot in self.snapshots.iter().rev() {
+            let path = &snapshot.path;
+            let outcome = match &snapshot.before {
+                FileState::File(contents) if fs::read(path).is_ok_and(|now| &now == contents) => {
+                    Ok(())
+                }
+                FileState::File(contents) => {
+                    let written = match path.parent() {
+                        Some(parent) => fs::create_dir_all(parent),
+                        None => Ok(()),
+                    }
+                    .and_then(|()| fs::write(path, contents));
+                    written.map(|()| result.restored.push(path.clone()))
+                }
+                FileState::Missing if path.is_dir() => {
+                    // 目录里还有本轮之外的文件时保留目录
+                    fs::remove_dir(path).map(|()| result.removed.push(path.clone()))
+                }
+                FileState::Missing if path.exists() => {
+                    fs::remove_file(path).map(|()| result.removed.push(path.clone()))
+                }
+                FileState::Missing => Ok(()),
+            };
+            if let Err(e) = outcome {
+                result.failed.push((path.clone(), e.to_string()));
+            }
+        }
+        result
+    }
+}
+
+/// Shared record of the file changes of the latest turns
+///
+/// Cloning shares the record, the tools and the agent hold the same history.
+#[derive(Debug, Clone, Default)]
+pub struct FileHistory {
+    turns: Arc<Mutex<Vec<TurnChanges>>>,
+    /// Sub-agents record into the turn of their parent instead of starting their own
+    nested: bool,
+}
+
+impl FileHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A handle recording into the current turn without starting turns itself
+    pub fn nested(&self) -> Self {
+        Self {
+            turns: self.turns.clone(),
+            nested: true,
+        }
+    }
+
+    /// Start recording the changes of a new turn of `session_id`
+    pub fn begin_turn(&self, session_id: &str) {
+        if self.nested {
+            return;
+        }
+        let mut turns = self.turns.lock().unwrap();
+        turns.push(TurnChanges {
+            session_id: session_id.to_string(),
+            snapshots: Vec::new(),
+        });
+        let excess = turns.len().saturating_sub(MAX_TURNS);
+        turns.drain(..excess);
+    }
+
+    /// Snapshot `path` and its missing parent directories before a tool changes them
+    ///
+    /// Only the first change of a path in a turn is recorded. Nothing is recorded
+    /// outside of a turn.
+    pub fn record(&self, path: &Path) {
+        let mut turns = self.turns.lock().unwrap();
+        let Some(turn) = turns.last_mut() else {
+            return;
+        };
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let recorded: HashSet<&Path> = turn
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.path.as_path())
+            .collect();
+
+        // 工具会创建缺失的父目录, 撤销时按相反顺序删除
+        let mut missing: Vec<PathBuf> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| !dir.exists())
+            .filter(|dir| !recorded.contains(dir))
+            .map(Path::to_path_buf)
+            .collect();
+        missing.reverse();
+        let before = if recorded.contains(path.as_path()) || path.is_dir() {
+            None
+        } else if path.is_file() {
+            match fs::read(&path) {
+                Ok(contents) => Some(FileState::File(contents)),
+                Err(_) => None,
+            }
+        } else {
+            Some(FileState::Missing)
+        };
+
+        turn.snapshots
+            .extend(missing.into_iter().map(|dir| FileSnapshot {
+                path: dir,
+                before: FileState::Missing,
+            }));
+        if let Some(before) = before {
+            turn.snapshots.push(FileSnapshot { path, before });
+        }
+    }
+
+    /// Remove the latest turn of `session_id` and restore its files
+    ///
+    /// Returns the turn with the outcome of the restore, `None` when no turn
+    /// of the session is recorded.
+    pub fn undo(&self, session_id: &str) -> Option<(TurnChanges, FileRestore)> {
+        let turn = {
+            let mut turns = self.turns.lock().unwrap();
+            let index = turns
+                .iter()
+                .rposition(|turn| turn.session_id == session_id)?;
+            turns.remove(index)
+        };
+        let restore = turn.restore();
+        Some((turn, restore))
+    }
+
+    /// Number of turns that can be undone for `session_id`
+    pub fn turns(&self, session_id: &str) -> usize {
+        self.turns
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|turn| turn.session_id == session_id)
+            .count()
+    }
+}
@@ -0,0 +1,184 @@
+//! Project memory loaded from `KOTA.md` files
+//!
+//! Memory files hold conventions and facts that should be part of every
+//! conversation. They are collected in this order, later files taking
+//! precedence over earlier ones:
+//!
+//! 1. `~/.kota/KOTA.md` (global)
+//! 2. `KOTA.md` in each parent directory of the project, outermost first
+//! 3. `KOTA.md` in the project root
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File name of memory files
+pub const MEMORY_FILE_NAME: &str = "KOTA.md";
+
+/// Where a memory file was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryScope {
+    /// `~/.kota/KOTA.md`, shared by all projects
+    Global,
+    /// A parent directory of the project
+    Parent,
+    /// The project root
+    Project,
+}
+
+impl MemoryScope {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MemoryScope::Global => "global",
+            MemoryScope::Parent => "parent",
+            MemoryScope::Project => "project",
+        }
+    }
+}
+
+/// A loaded memory file
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryFile {
+    pub path: PathBuf,
+    pub scope: MemoryScope,
+    pub content: String,
+}
+
+/// Memory files of a project in precedence order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectMemory {
+    project_root: PathBuf,
+    global_dir: Option<PathBuf>,
+    pub files: Vec<MemoryFile>,
+}
+
+impl ProjectMemory {
+    /// Collect the memory files for `project_root`
+    ///
+    /// # Arguments
+    ///
+    /// * `project_root` - Directory whose `KOTA.md` has the highest precedence
+    /// * `global_dir` - Directory holding the global `KOTA.md`, usually `~/.kota`
+    pub fn discover<P: AsRef<Path>>(project_root: P, global_dir: Option<PathBuf>) -> Result<Self> {
+        let mut memory = Self {
+            project_root: project_root.as_ref().to_path_buf(),
+            global_dir,
+            files: Vec::new(),
+        };
+        memory.reload()?;
+        Ok(memory)
+    }
+
+    /// Collect the memory files for the current directory and `~/.kota`
+    pub fn discover_default() -> Self {
+        let project_root = std::env::current_dir().unwrap_or_default();
+        let global_dir = home_dir().map(|home| home.join(".kota"));
+        Self::discover(&project_root, global_dir.clone()).unwrap_or_else(|e| {
+            eprintln!("Warning: Failed to load memory files: {:#}", e);
+            Self {
+                project_root,
+                global_dir,
+                files: Vec::new(),
+            }
+        })
+    }
+
+    /// Read the memory files again
+    pub fn reload(&mut self) -> Result<()> {
+        let mut candidates = Vec::new();
+        if let Some(global_dir) = &self.global_dir {
+            candidates.push((global_dir.join(MEMORY_FILE_NAME), MemoryScope::Global));
+        }
+        let mut parents: Vec<_> = self.project_root.ancestors().skip(1).collect();
+        parents.reverse();
+        for dir in parents {
+            candidates.push((dir.join(MEMORY_FILE_NAME), MemoryScope::Parent));
+        }
+        candidates.push((self.project_file(), MemoryScope::Project));
+
+        let mut files: Vec<MemoryFile> = Vec::new();
+        for (path, scope) in candidates {
+            if !path.is_file() || files.iter().any(|f| same_file(&f.path, &path)) {
+                continue;
+            }
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read memory file: {:?}", path))?;
+            files.push(MemoryFile {
+                path,
+                scope,
+                content,
+            });
+        }
+        self.files = files;
+        Ok(())
+    }
+
+    /// `KOTA.md` in the project root, which may not exist yet
+    pub fn project_file(&self) -> PathBuf {
+        self.project_root.join(MEMORY_FILE_NAME)
+    }
+
+    /// The global `KOTA.md`, `None` when the home directory is unknown
+    pub fn global_file(&self) -> Option<PathBuf> {
+        self.global_dir
+            .as_ref()
+            .map(|dir| dir.join(MEMORY_FILE_NAME))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.iter().all(|f| f.content.trim().is_empty())
+    }
+
+    /// Render the memory files as a section of the system prompt
+    pub fn render(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut section = String::from(
+            "# Project Memory\n\
+             The following KOTA.md files hold conventions and facts about this project. \
+             When they disagree, later files take precedence over earlier ones.\n",
+        );
+        for file in self.files.iter().filter(|f| !f.content.trim().is_empty()) {
+            section.push_str(&format!(
+                "\n## {} ({})\n\n{}\n",
+                file.path.display(),
+                file.scope.label(),
+                file.content.trim()
+            ));
+        }
+        Some(section)
+    }
+}
+
+/// Append a fact as a list item to a memory file, creating it when missing
+pub fn append_fact(path: &Path, fact: &str) -> Result<()> {
+    let fact = fact.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut content = if path.exists() {
+        fs::read_to_string(path)
+            .with_context(|| format!("Failed to read memory file: {:?}", path))?
+    } else {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        "# Memory\n\n".to_string()
+    };
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&format!("- {}\n", fact));
+    fs::write(path, content).with_context(|| format!("Failed to write memory file: {:?}", path))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+pub(crate) fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
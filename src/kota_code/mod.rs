@@ -47,32 +47,55 @@
 // Core modules
 pub mod agent;
 pub mod context;
+pub mod file_history;
 pub mod mcp;
+pub mod memory;
 pub mod plan;
 pub mod runtime;
+pub mod search;
 pub mod skills;
+pub mod store;
+pub mod tokens;
 pub mod tools;
+pub mod usage;
 
 // Re-export commonly used types for convenience
-pub use agent::{create_agent, AgentBuilder, AgentInstance, AgentType, Provider};
-pub use context::{ContextManager, SerializableMessage, SessionMetadata};
+pub use agent::{
+    create_agent, AgentBuilder, AgentEvent, AgentEventStream, AgentHook, AgentInstance, AgentType,
+    Compaction, GenerationSettings, Provider, TurnCancelled, Undo,
+};
+pub use context::{
+    CompactionPolicy, ContextManager, SerializableMessage, SessionMetadata, SESSION_VERSION,
+};
+pub use file_history::{FileHistory, FileRestore};
 pub use mcp::{client::McpClient, McpManager};
+pub use memory::{MemoryFile, MemoryScope, ProjectMemory};
 pub use plan::{Plan, PlanManager, Task, TaskStatus};
-pub use runtime::{CommandDef, KotaConfig, SessionIdHook, ToolRegistry};
-pub use skills::{Skill, SkillManager};
+pub use runtime::{CancelSignal, CommandDef, KotaConfig, SessionIdHook, ToolRegistry};
+pub use search::{MessageHit, SearchQuery, SessionFilter, SessionMatch};
+pub use skills::{AutoSelect, Skill, SkillDir, SkillManager, SkillMatch, SkillScope};
+pub use store::{FileStore, MemoryStore, SessionConflict, SessionStore, SqliteStore, StoreConfig};
 pub use tools::{
     FileToolError, WrappedCreateDirectoryTool, WrappedDeleteFileTool, WrappedEditFileTool,
     WrappedExecuteBashCommandTool, WrappedGrepSearchTool, WrappedReadFileTool,
     WrappedScanCodebaseTool, WrappedUpdatePlanTool, WrappedWriteFileTool,
 };
+pub use usage::{
+    Budget, BudgetExceeded, BudgetStatus, ModelPrice, PriceTable, SessionUsage, TokenUsage,
+};
 
 /// Prelude module for convenient imports
 pub mod prelude {
-    pub use super::agent::{create_agent, AgentBuilder, AgentInstance, AgentType, Provider};
+    pub use super::agent::{
+        create_agent, AgentBuilder, AgentEvent, AgentEventStream, AgentHook, AgentInstance,
+        AgentType, GenerationSettings, Provider, TurnCancelled,
+    };
     pub use super::context::{ContextManager, SerializableMessage, SessionMetadata};
     pub use super::mcp::{client::McpClient, McpManager};
+    pub use super::memory::ProjectMemory;
     pub use super::plan::{Plan, PlanManager, Task, TaskStatus};
-    pub use super::runtime::{CommandDef, KotaConfig, SessionIdHook, ToolRegistry};
+    pub use super::runtime::{CancelSignal, CommandDef, KotaConfig, SessionIdHook, ToolRegistry};
     pub use super::skills::{Skill, SkillManager};
     pub use super::tools::FileToolError;
+    pub use super::usage::{Budget, BudgetExceeded, BudgetStatus, PriceTable, TokenUsage};
 }
@@ -0,0 +1,81 @@
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+/// Shared handle used to cancel the agent turn that is currently running
+///
+/// Every turn gets a fresh [`CancellationToken`], so cancelling one turn does not
+/// affect the next. Clones share the same state, which lets a Ctrl-C handler
+/// cancel the turn while the agent and its tools observe the token.
+#[derive(Debug, Clone, Default)]
+pub struct CancelSignal {
+    current: Arc<Mutex<TurnToken>>,
+}
+
+#[derive(Debug, Default)]
+struct TurnToken {
+    token: CancellationToken,
+    /// 由 reset 为下一轮准备的令牌, 开始前被取消也要生效
+    prepared: bool,
+}
+
+impl CancelSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the running turn
+    pub fn cancel(&self) {
+        self.current.lock().unwrap().token.cancel();
+    }
+
+    /// Whether the running turn has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.current.lock().unwrap().token.is_cancelled()
+    }
+
+    /// Token of the running turn
+    pub fn token(&self) -> CancellationToken {
+        self.current.lock().unwrap().token.clone()
+    }
+
+    /// Prepare the token of the next turn and return it
+    ///
+    /// Call it before installing a cancel handler: a cancel arriving before
+    /// the turn starts then still cancels that turn.
+    pub fn reset(&self) -> CancellationToken {
+        let mut current = self.current.lock().unwrap();
+        *current = TurnToken {
+            token: CancellationToken::new(),
+            prepared: true,
+        };
+        current.token.clone()
+    }
+
+    /// Start a turn and return its token
+    ///
+    /// Uses the token prepared by [`CancelSignal::reset`], cancelled or not, and
+    /// a fresh one otherwise, so a cancel left over from an earlier turn never
+    /// stops this one.
+    pub fn begin_turn(&self) -> CancellationToken {
+        let mut current = self.current.lock().unwrap();
+        if !current.prepared {
+            current.token = CancellationToken::new();
+        }
+        current.prepared = false;
+        current.token.clone()
+    }
+
+    /// Signal for a sub-agent working inside the running turn
+    ///
+    /// Its turn token is a child of the running turn's token, so cancelling
+    /// this turn also cancels the sub-agent, while the sub-agent starting its
+    /// own turn leaves this signal untouched.
+    pub fn child(&self) -> CancelSignal {
+        Self {
+            current: Arc::new(Mutex::new(TurnToken {
+                token: self.token().child_token(),
+                prepared: true,
+            })),
+        }
+    }
+}
@@ -1,7 +1,17 @@
 use anyhow::Result;
 use mlua::prelude::*;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::kota_code::agent::retry::RetryPolicy;
+use crate::kota_code::agent::{GenerationSettings, Provider};
+use crate::kota_code::context::CompactionPolicy;
+use crate::kota_code::memory::home_dir;
+use crate::kota_code::skills::AutoSelect;
+use crate::kota_code::store::StoreConfig;
+use crate::kota_code::tokens::context_window;
+use crate::kota_code::usage::{Budget, ModelPrice, PriceTable};
+use std::time::Duration;
 
 /// Command definition that can be either a string or a Lua function
 #[derive(Debug, Clone)]
@@ -16,24 +26,65 @@ pub enum CommandDef {
 #[derive(Debug, Clone)]
 pub struct KotaConfig {
     pub model: String,
+    pub provider: Option<Provider>,
     pub api_key: String,
-    pub api_base: String,
+    /// Base URL of the provider's API, required for the openai-compatible provider
+    pub api_base: Option<String>,
     pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    /// Maximum output tokens per completion call
+    pub max_tokens: Option<u64>,
+    /// Maximum depth of the tool loop for one prompt
+    pub max_turns: Option<usize>,
+    /// Context window of the model in tokens, looked up from the model name when unset
+    pub context_window: Option<usize>,
+    /// When the history is summarized
+    pub compaction: CompactionPolicy,
+    /// Where sessions are saved
+    pub session_store: StoreConfig,
     pub enabled_tools: Vec<String>,
     pub disabled_tools: Vec<String>,
     pub commands: HashMap<String, CommandDef>,
+    /// Retries for rate limits and server errors
+    pub retry: RetryPolicy,
+    /// Models tried in order when the primary model keeps failing
+    pub fallback_models: Vec<String>,
+    /// Per-model prices in USD per million tokens
+    pub prices: PriceTable,
+    /// Spending limits for one session
+    pub budget: Option<Budget>,
+    /// Automatic skill selection, off unless configured
+    pub auto_skills: Option<AutoSelect>,
+    /// Extra skill directories, between `~/.kota/skills` and the project's skills
+    pub skill_dirs: Vec<PathBuf>,
+    /// Script file replayed by the mock provider
+    pub mock_script: Option<String>,
 }
 
 impl Default for KotaConfig {
     fn default() -> Self {
         Self {
             model: "gpt-4o".to_string(),
+            provider: None,
             api_key: String::new(),
-            api_base: "https://api.openai.com/v1".to_string(),
+            api_base: None,
             temperature: Some(0.7),
+            top_p: None,
+            max_tokens: None,
+            max_turns: None,
+            context_window: None,
+            compaction: CompactionPolicy::default(),
+            session_store: StoreConfig::default(),
             enabled_tools: vec![],
             disabled_tools: vec![],
             commands: HashMap::new(),
+            retry: RetryPolicy::default(),
+            fallback_models: vec![],
+            prices: PriceTable::default(),
+            budget: None,
+            auto_skills: None,
+            skill_dirs: vec![],
+            mock_script: None,
         }
     }
 }
@@ -105,6 +156,11 @@ impl KotaConfig {
             config.model = model;
         }
 
+        // Parse provider - overrides the prefix-based detection from the model name
+        if let Ok(provider) = captured.get::<_, String>("provider") {
+            config.provider = Some(provider.parse()?);
+        }
+
         // Parse api_key - Lua will have already evaluated os.getenv() expressions
         if let Ok(api_key) = captured.get::<_, String>("api_key") {
             config.api_key = api_key;
@@ -112,7 +168,12 @@ impl KotaConfig {
 
         // Parse api_base - Lua will have already evaluated os.getenv() expressions
         if let Ok(api_base) = captured.get::<_, String>("api_base") {
-            config.api_base = api_base;
+            config.api_base = Some(api_base);
+        }
+
+        // Parse mock_script
+        if let Ok(mock_script) = captured.get::<_, String>("mock_script") {
+            config.mock_script = Some(mock_script);
         }
 
         // Parse temperature
@@ -120,44 +181,165 @@ impl KotaConfig {
             config.temperature = Some(temp);
         }
 
+        // Parse top_p
+        if let Ok(top_p) = captured.get::<_, f64>("top_p") {
+            config.top_p = Some(top_p);
+        }
+
+        // Parse max_tokens
+        if let Ok(max_tokens) = captured.get::<_, u64>("max_tokens") {
+            config.max_tokens = Some(max_tokens);
+        }
+
+        // Parse max_turns
+        if let Ok(max_turns) = captured.get::<_, usize>("max_turns") {
+            config.max_turns = Some(max_turns);
+        }
+
+        // Parse context window
+        if let Ok(context_window) = captured.get::<_, usize>("context_window") {
+            config.context_window = Some(context_window);
+        }
+
+        // Parse compaction: `compaction = false` or a table of settings
+        match captured.get::<_, LuaValue>("compaction") {
+            Ok(LuaValue::Boolean(auto)) => config.compaction.auto = auto,
+            Ok(LuaValue::Table(compaction)) => {
+                if let Ok(Some(auto)) = compaction.get::<_, Option<bool>>("auto") {
+                    config.compaction.auto = auto;
+                }
+                if let Ok(threshold) = compaction.get::<_, f64>("threshold") {
+                    config.compaction.threshold = threshold;
+                }
+                if let Ok(keep_recent) = compaction.get::<_, f64>("keep_recent") {
+                    config.compaction.keep_recent = keep_recent;
+                }
+            }
+            _ => {}
+        }
+
+        // Parse session store: `session_store = "sqlite"` or `{ backend = "sqlite", path = "..." }`
+        match captured.get::<_, LuaValue>("session_store") {
+            Ok(LuaValue::String(backend)) => {
+                config.session_store = StoreConfig::new(&backend.to_string_lossy(), None)?;
+            }
+            Ok(LuaValue::Table(store)) => {
+                let backend = store
+                    .get::<_, String>("backend")
+                    .unwrap_or_else(|_| "file".to_string());
+                let path = store.get::<_, String>("path").ok().map(PathBuf::from);
+                config.session_store = StoreConfig::new(&backend, path)?;
+            }
+            _ => {}
+        }
+
+        // Parse retry policy
+        if let Ok(retry) = captured.get::<_, LuaTable>("retry") {
+            if let Ok(max_retries) = retry.get::<_, u32>("max_retries") {
+                config.retry.max_retries = max_retries;
+            }
+            if let Ok(ms) = retry.get::<_, u64>("initial_backoff_ms") {
+                config.retry.initial_backoff = Duration::from_millis(ms);
+            }
+            if let Ok(ms) = retry.get::<_, u64>("max_backoff_ms") {
+                config.retry.max_backoff = Duration::from_millis(ms);
+            }
+        }
+
+        // Parse fallback models
+        if let Ok(models) = captured.get::<_, LuaTable>("fallback_models") {
+            // pairs 不保证顺序, fallback 必须按写下的顺序尝试
+            for model in models.sequence_values::<String>().flatten() {
+                config.fallback_models.push(model);
+            }
+        }
+
+        // Parse price table
+        if let Ok(prices) = captured.get::<_, LuaTable>("prices") {
+            for (model, price) in prices.pairs::<String, LuaTable>().flatten() {
+                config.prices.insert(
+                    model,
+                    ModelPrice {
+                        input: price.get::<_, f64>("input").unwrap_or(0.0),
+                        output: price.get::<_, f64>("output").unwrap_or(0.0),
+                        cached_input: price.get::<_, f64>("cached_input").ok(),
+                    },
+                );
+            }
+        }
+
+        // Parse budget
+        if let Ok(budget) = captured.get::<_, LuaTable>("budget") {
+            let mut limits = Budget {
+                max_cost: budget.get::<_, f64>("max_cost").ok(),
+                max_tokens: budget.get::<_, u64>("max_tokens").ok(),
+                ..Budget::default()
+            };
+            if let Ok(warn_at) = budget.get::<_, f64>("warn_at") {
+                limits.warn_at = warn_at;
+            }
+            config.budget = Some(limits);
+        }
+
+        // Parse automatic skill selection: `auto_skills = true` or a table of settings
+        match captured.get::<_, LuaValue>("auto_skills") {
+            Ok(LuaValue::Boolean(true)) => config.auto_skills = Some(AutoSelect::default()),
+            Ok(LuaValue::Table(auto)) => {
+                let mut selection = AutoSelect::default();
+                if let Ok(max_skills) = auto.get::<_, usize>("max_skills") {
+                    selection.max_skills = max_skills;
+                }
+                if let Ok(min_score) = auto.get::<_, f64>("min_score") {
+                    selection.min_score = min_score;
+                }
+                config.auto_skills = Some(selection);
+            }
+            _ => {}
+        }
+
+        // Parse extra skill directories, `~/` is expanded to the home directory
+        if let Ok(dirs) = captured.get::<_, LuaTable>("skill_dirs") {
+            for (_, dir) in dirs.pairs::<LuaValue, String>().flatten() {
+                let path = match (dir.strip_prefix("~/"), home_dir()) {
+                    (Some(rest), Some(home)) => home.join(rest),
+                    _ => PathBuf::from(dir),
+                };
+                config.skill_dirs.push(path);
+            }
+        }
+
         // Parse tools configuration
         if let Ok(tools) = captured.get::<_, LuaTable>("tools") {
             if let Ok(enabled) = tools.get::<&str, LuaTable>("enabled") {
-                for pair in enabled.pairs::<LuaValue, String>() {
-                    if let Ok((_, tool)) = pair {
-                        config.enabled_tools.push(tool);
-                    }
+                for (_, tool) in enabled.pairs::<LuaValue, String>().flatten() {
+                    config.enabled_tools.push(tool);
                 }
             }
 
             if let Ok(disabled) = tools.get::<&str, LuaTable>("disabled") {
-                for pair in disabled.pairs::<LuaValue, String>() {
-                    if let Ok((_, tool)) = pair {
-                        config.disabled_tools.push(tool);
-                    }
+                for (_, tool) in disabled.pairs::<LuaValue, String>().flatten() {
+                    config.disabled_tools.push(tool);
                 }
             }
         }
 
         // Parse commands configuration
         if let Ok(commands) = captured.get::<_, LuaTable>("commands") {
-            for pair in commands.pairs::<String, LuaValue>() {
-                if let Ok((name, value)) = pair {
-                    match value {
-                        LuaValue::String(s) => {
-                            // Simple string command
-                            config
-                                .commands
-                                .insert(name, CommandDef::String(s.to_str()?.to_string()));
-                        }
-                        LuaValue::Function(func) => {
-                            // Function command - dump to bytecode
-                            let bytecode = func.dump(false);
-                            config.commands.insert(name, CommandDef::Function(bytecode));
-                        }
-                        _ => {
-                            // Ignore other types
-                        }
+            for (name, value) in commands.pairs::<String, LuaValue>().flatten() {
+                match value {
+                    LuaValue::String(s) => {
+                        // Simple string command
+                        config
+                            .commands
+                            .insert(name, CommandDef::String(s.to_str()?.to_string()));
+                    }
+                    LuaValue::Function(func) => {
+                        // Function command - dump to bytecode
+                        let bytecode = func.dump(false);
+                        config.commands.insert(name, CommandDef::Function(bytecode));
+                    }
+                    _ => {
+                        // Ignore other types
                     }
                 }
             }
@@ -166,6 +348,23 @@ impl KotaConfig {
         Ok(())
     }
 
+    /// Generation settings from this configuration, using defaults for unset keys
+    pub fn generation_settings(&self) -> GenerationSettings {
+        let defaults = GenerationSettings::default();
+        GenerationSettings {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p,
+            max_tokens: self.max_tokens.unwrap_or(defaults.max_tokens),
+            max_turns: self.max_turns.unwrap_or(defaults.max_turns),
+        }
+    }
+
+    /// Context window of the configured model in tokens
+    pub fn context_window(&self) -> usize {
+        self.context_window
+            .unwrap_or_else(|| context_window(&self.model))
+    }
+
     /// Load configuration from .kota/config.lua
     ///
     /// # Returns
@@ -12,9 +12,14 @@ pub struct LuaToolLoader;
 impl LuaToolLoader {
     /// Load all Lua tools from .kota/tools/mod.lua
     pub fn load_tools() -> Result<Vec<LuaDynTool>> {
-        let tools_path = ".kota/tools/init.lua";
+        Self::load_tools_from(".kota/tools/init.lua")
+    }
+
+    /// Load the Lua tools registered by the file at `tools_path`
+    pub fn load_tools_from<P: AsRef<Path>>(tools_path: P) -> Result<Vec<LuaDynTool>> {
+        let tools_path = tools_path.as_ref();
 
-        if !Path::new(tools_path).exists() {
+        if !tools_path.exists() {
             // No tools file, return empty vec
             return Ok(Vec::new());
         }
@@ -1,16 +1,35 @@
-use rig::agent::StreamingPromptHook;
-use rig::completion::CompletionModel;
+use rig::agent::{HookAction, StreamingPromptHook};
+use rig::completion::{CompletionModel, Message};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 /// Session-aware hook that logs tool calls and completions with session context
+///
+/// Also counts the completion calls of a turn, so streamed events can be
+/// grouped into the messages the model produced.
 #[derive(Clone)]
 pub struct SessionIdHook {
     pub session_id: String,
+    completion_calls: Arc<AtomicUsize>,
 }
 
 impl SessionIdHook {
     pub fn new(session_id: String) -> Self {
-        Self { session_id }
+        Self {
+            session_id,
+            completion_calls: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Number of completion calls made so far, shared by all clones
+    pub fn completion_calls(&self) -> usize {
+        self.completion_calls.load(Ordering::SeqCst)
     }
 }
 
-impl<M: CompletionModel> StreamingPromptHook<M> for SessionIdHook {}
+impl<M: CompletionModel> StreamingPromptHook<M> for SessionIdHook {
+    async fn on_completion_call(&self, _prompt: &Message, _history: &[Message]) -> HookAction {
+        self.completion_calls.fetch_add(1, Ordering::SeqCst);
+        HookAction::cont()
+    }
+}
@@ -1,8 +1,12 @@
+pub mod cancel;
 pub mod config;
 pub mod dyn_tools_loader;
 pub mod hooks;
+pub mod prompts;
 pub mod tool_registry;
 
+pub use cancel::CancelSignal;
 pub use config::{CommandDef, KotaConfig};
 pub use hooks::SessionIdHook;
+pub use prompts::{PromptLoader, PromptVariables};
 pub use tool_registry::ToolRegistry;
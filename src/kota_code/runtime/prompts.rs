@@ -0,0 +1,189 @@
+//! System prompt loaded from `.kota/prompts`
+//!
+//! The preamble is taken from the first source that provides one:
+//!
+//! 1. `init.lua` returning a string, or a function that receives the prompt
+//!    variables as a table and returns a string
+//! 2. `system.md`, used as is
+//! 3. the built-in [`DEFAULT_PREAMBLE`]
+//!
+//! Every preamble is a template: `{{cwd}}`, `{{date}}`, `{{os}}`, `{{git_branch}}`
+//! and `{{tools}}` are replaced with the current values.
+
+use anyhow::{Context, Result};
+use mlua::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory searched for custom prompts
+pub const DEFAULT_PROMPTS_DIR: &str = ".kota/prompts";
+
+/// Preamble used when `.kota/prompts` provides none
+pub const DEFAULT_PREAMBLE: &str = r#"Your name is Kota. You are a helpful AI code assistant with comprehensive file system and command execution access.
+You can read, write, edit (with patches), and delete files, execute bash commands, scan codebase structures, search text in the codebase and create directories.
+Use the edit_file tool for making small, targeted changes to existing files - it's more efficient than rewriting entire files.
+
+You also have access to Plan Mode via the update_plan tool. Use it to:
+- Create structured execution plans for complex tasks
+- Break down work into manageable tasks with dependencies
+- Track progress and update task status (pending, in_progress, completed, blocked)
+- Show current plan and identify next available tasks
+
+Please provide clear and concise responses and be careful when modifying files or executing commands.
+
+Environment:
+- Working directory: {{cwd}}
+- Date: {{date}}
+- OS: {{os}}
+- Git branch: {{git_branch}}
+- Available tools: {{tools}}"#;
+
+/// Values substituted into the preamble template
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PromptVariables {
+    pub cwd: String,
+    pub date: String,
+    pub os: String,
+    /// Current branch, `unknown` outside a git repository
+    pub git_branch: String,
+    /// Names of the tools enabled for the agent
+    pub tools: Vec<String>,
+}
+
+impl PromptVariables {
+    /// Collect the variables for the current process
+    pub fn collect(tools: Vec<String>) -> Self {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        Self {
+            cwd: cwd.display().to_string(),
+            date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+            os: std::env::consts::OS.to_string(),
+            git_branch: git_branch(&cwd).unwrap_or_else(|| "unknown".to_string()),
+            tools,
+        }
+    }
+
+    /// Replace the `{{name}}` placeholders in `template`
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{{cwd}}", &self.cwd)
+            .replace("{{date}}", &self.date)
+            .replace("{{os}}", &self.os)
+            .replace("{{git_branch}}", &self.git_branch)
+            .replace("{{tools}}", &self.tools.join(", "))
+    }
+
+    fn to_lua_table<'lua>(&self, lua: &'lua Lua) -> LuaResult<LuaTable<'lua>> {
+        let table = lua.create_table()?;
+        table.set("cwd", self.cwd.as_str())?;
+        table.set("date", self.date.as_str())?;
+        table.set("os", self.os.as_str())?;
+        table.set("git_branch", self.git_branch.as_str())?;
+        table.set("tools", self.tools.clone())?;
+        Ok(table)
+    }
+}
+
+/// Loads the preamble from a prompts directory
+#[derive(Debug, Clone)]
+pub struct PromptLoader {
+    dir: PathBuf,
+}
+
+impl Default for PromptLoader {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROMPTS_DIR)
+    }
+}
+
+impl PromptLoader {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Load and render the custom preamble, `None` when the directory provides none
+    pub fn load(&self, variables: &PromptVariables) -> Result<Option<String>> {
+        let lua_path = self.dir.join("init.lua");
+        if lua_path.exists() {
+            if let Some(template) = Self::eval_lua(&lua_path, variables)? {
+                return Ok(Some(variables.render(&template)));
+            }
+        }
+
+        let markdown_path = self.dir.join("system.md");
+        if markdown_path.exists() {
+            let template = fs::read_to_string(&markdown_path)
+                .with_context(|| format!("Failed to read prompt file: {:?}", markdown_path))?;
+            if !template.trim().is_empty() {
+                return Ok(Some(variables.render(&template)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Load the custom preamble, falling back to [`DEFAULT_PREAMBLE`]
+    pub fn load_or_default(&self, variables: &PromptVariables) -> String {
+        match self.load(variables) {
+            Ok(Some(preamble)) => preamble,
+            Ok(None) => variables.render(DEFAULT_PREAMBLE),
+            Err(e) => {
+                eprintln!("Warning: Failed to load custom prompt: {:#}", e);
+                variables.render(DEFAULT_PREAMBLE)
+            }
+        }
+    }
+
+    /// Run `init.lua` and take the string it returns or its function produces
+    fn eval_lua(path: &Path, variables: &PromptVariables) -> Result<Option<String>> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read prompt file: {:?}", path))?;
+        let lua = Lua::new();
+        let value: LuaValue = lua
+            .load(&content)
+            .set_name("init.lua")
+            .eval()
+            .map_err(|e| anyhow::anyhow!("Failed to run prompt file {:?}: {}", path, e))?;
+
+        let prompt = match value {
+            LuaValue::Nil => None,
+            LuaValue::String(s) => Some(s.to_str()?.to_string()),
+            LuaValue::Function(f) => f
+                .call::<_, Option<String>>(variables.to_lua_table(&lua)?)
+                .map_err(|e| anyhow::anyhow!("Prompt function in {:?} failed: {}", path, e))?,
+            other => anyhow::bail!(
+                "{:?} must return a string or a function, got {}",
+                path,
+                other.type_name()
+            ),
+        };
+        Ok(prompt.filter(|p| !p.trim().is_empty()))
+    }
+}
+
+/// Name of the branch checked out in the repository containing `start`
+///
+/// Returns the short commit hash for a detached HEAD.
+pub fn git_branch(start: &Path) -> Option<String> {
+    let git_dir = start
+        .ancestors()
+        .map(|dir| dir.join(".git"))
+        .find(|path| path.exists())?;
+    // worktree 和子模块里 .git 是一个指向真实目录的文件
+    let git_dir = if git_dir.is_file() {
+        let content = fs::read_to_string(&git_dir).ok()?;
+        let target = content.trim().strip_prefix("gitdir:")?.trim();
+        git_dir.parent()?.join(target)
+    } else {
+        git_dir
+    };
+
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref: refs/heads/") {
+        Some(branch) => Some(branch.to_string()),
+        None => Some(head.chars().take(7).collect()),
+    }
+}
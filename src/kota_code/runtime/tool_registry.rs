@@ -1,4 +1,6 @@
+use crate::kota_code::agent::events::ToolOutcomes;
 use rig::tool::ToolDyn;
+use std::sync::Arc;
 
 pub struct ToolRegistry {
     tools: Vec<Box<dyn ToolDyn>>,
@@ -22,6 +24,19 @@ impl ToolRegistry {
         }
     }
 
+    /// Keep only the tools for which `keep` returns true
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&dyn ToolDyn) -> bool,
+    {
+        self.tools.retain(|tool| keep(tool.as_ref()));
+    }
+
+    /// Get the names of all registered tools
+    pub fn names(&self) -> Vec<String> {
+        self.tools.iter().map(|tool| tool.name()).collect()
+    }
+
     /// Get all tools by taking ownership (empties the registry)
     pub fn take_all(&mut self) -> Vec<Box<dyn ToolDyn>> {
         std::mem::take(&mut self.tools)
@@ -58,3 +73,35 @@ impl Default for ToolRegistry {
         Self::new()
     }
 }
+
+/// Shared tool usable where rig expects `impl ToolDyn`
+///
+/// The agent keeps its own handle so the tool can be registered again after
+/// a skill removed it from the tool server. Every call records whether it
+/// succeeded in the agent's [`ToolOutcomes`].
+#[derive(Clone)]
+pub(crate) struct SharedTool(pub Arc<dyn ToolDyn>, pub ToolOutcomes);
+
+impl ToolDyn for SharedTool {
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn definition<'a>(
+        &'a self,
+        prompt: String,
+    ) -> rig::wasm_compat::WasmBoxedFuture<'a, rig::completion::ToolDefinition> {
+        self.0.definition(prompt)
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: String,
+    ) -> rig::wasm_compat::WasmBoxedFuture<'a, Result<String, rig::tool::ToolError>> {
+        Box::pin(async move {
+            let result = self.0.call(args).await;
+            self.1.record(result.is_ok());
+            result
+        })
+    }
+}
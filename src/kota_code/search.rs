@@ -0,0 +1,237 @@
+//! Full-text search over saved sessions
+//!
+//! A query is split into case-insensitive terms. A message matches when it
+//! contains at least one term, sessions are ranked by the number of term
+//! occurrences, with a bonus for messages containing every term.
+//!
+//! [`SessionFilter`] selects sessions by their metadata instead.
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rig::completion::Message;
+use serde::Serialize;
+
+use super::context::{SerializableMessage, SessionMetadata};
+
+/// Characters shown on each side of the first match in a snippet
+const SNIPPET_CONTEXT_CHARS: usize = 60;
+
+/// Score added for a message that contains every term of the query
+const ALL_TERMS_BONUS: usize = 5;
+
+/// Matching messages kept per session
+const MAX_HITS_PER_SESSION: usize = 5;
+
+/// A session matching a search query
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionMatch {
+    pub session_id: String,
+    pub last_updated: String,
+    pub score: usize,
+    /// Best matching messages, best first
+    pub hits: Vec<MessageHit>,
+    /// Number of matching messages, including those not in `hits`
+    pub matching_messages: usize,
+}
+
+/// A message matching a search query
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageHit {
+    /// Index of the message in the session history, starting at 0
+    pub index: usize,
+    pub role: String,
+    pub score: usize,
+    /// Text around the first match, on one line
+    pub snippet: String,
+}
+
+/// A parsed search query
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    terms: Vec<String>,
+}
+
+impl SearchQuery {
+    pub fn new(query: &str) -> Self {
+        let mut terms: Vec<String> = Vec::new();
+        for term in query.split_whitespace().map(str::to_lowercase) {
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+        Self { terms }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Lowercase terms of the query, without duplicates
+    pub fn terms(&self) -> &[String] {
+        &self.terms
+    }
+
+    /// Match the messages of one session, `None` if no message matches
+    pub fn match_session(
+        &self,
+        metadata: &SessionMetadata,
+        messages: &[Message],
+    ) -> Option<SessionMatch> {
+        let mut hits: Vec<MessageHit> = messages
+            .iter()
+            .enumerate()
+            .filter_map(|(index, message)| self.match_message(index, message))
+            .collect();
+        if hits.is_empty() {
+            return None;
+        }
+
+        let score = hits.iter().map(|hit| hit.score).sum();
+        let matching_messages = hits.len();
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then(a.index.cmp(&b.index)));
+        hits.truncate(MAX_HITS_PER_SESSION);
+        Some(SessionMatch {
+            session_id: metadata.session_id.clone(),
+            last_updated: metadata.last_updated.clone(),
+            score,
+            hits,
+            matching_messages,
+        })
+    }
+
+    fn match_message(&self, index: usize, message: &Message) -> Option<MessageHit> {
+        let message = SerializableMessage::from(message);
+        let text = message.content.to_lowercase();
+
+        let mut score = 0;
+        let mut matched_terms = 0;
+        let mut first_match: Option<usize> = None;
+        for term in &self.terms {
+            let mut occurrences = text.match_indices(term.as_str()).peekable();
+            if let Some((position, _)) = occurrences.peek() {
+                first_match = Some(first_match.map_or(*position, |first| first.min(*position)));
+                matched_terms += 1;
+            }
+            score += occurrences.count();
+        }
+        if score == 0 {
+            return None;
+        }
+        if matched_terms == self.terms.len() && self.terms.len() > 1 {
+            score += ALL_TERMS_BONUS;
+        }
+
+        Some(MessageHit {
+            index,
+            role: message.role,
+            score,
+            snippet: snippet(&text, &message.content, first_match.unwrap_or(0)),
+        })
+    }
+}
+
+/// One line of `content` around the byte `position` of its lowercase form `lower`
+fn snippet(lower: &str, content: &str, position: usize) -> String {
+    // 小写后字节位置可能变化, 按字符位置截取原文
+    let match_char = lower[..position].chars().count();
+    let chars: Vec<char> = content.chars().collect();
+    let start = match_char.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let end = (match_char + SNIPPET_CONTEXT_CHARS).min(chars.len());
+
+    let text: String = chars[start.min(end)..end].iter().collect();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!(
+        "{}{}{}",
+        if start > 0 { "…" } else { "" },
+        text,
+        if end < chars.len() { "…" } else { "" }
+    )
+}
+
+/// Filter on session metadata, used by `/sessions`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionFilter {
+    /// Sessions must have every tag
+    pub tags: Vec<String>,
+    /// Sessions updated at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Case-insensitive part of the model name
+    pub model: Option<String>,
+    /// Case-insensitive part of the git branch
+    pub branch: Option<String>,
+}
+
+impl SessionFilter {
+    /// Parse command arguments such as `--tag bug --since 7d --model gpt`
+    pub fn parse(args: &str) -> Result<Self> {
+        let mut filter = Self::default();
+        let mut args = args.split_whitespace();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("Missing value after {}", flag))
+            };
+            match flag {
+                "--tag" => filter.tags.push(value()?.to_string()),
+                "--since" => filter.since = Some(parse_since(value()?, Utc::now())?),
+                "--model" => filter.model = Some(value()?.to_lowercase()),
+                "--branch" => filter.branch = Some(value()?.to_lowercase()),
+                _ => bail!("Unknown filter: {}", flag),
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn matches(&self, metadata: &SessionMetadata) -> bool {
+        if !self.tags.iter().all(|tag| metadata.tags.contains(tag)) {
+            return false;
+        }
+        if let Some(since) = self.since {
+            // 无法解析的时间视为不匹配
+            let updated = DateTime::parse_from_rfc3339(&metadata.last_updated);
+            if !updated.is_ok_and(|updated| updated >= since) {
+                return false;
+            }
+        }
+        contains(&self.model, metadata.model.as_deref())
+            && contains(&self.branch, metadata.git_branch.as_deref())
+    }
+}
+
+fn contains(needle: &Option<String>, value: Option<&str>) -> bool {
+    match needle {
+        Some(needle) => value.is_some_and(|value| value.to_lowercase().contains(needle)),
+        None => true,
+    }
+}
+
+/// Parse `2024-05-01`, an RFC 3339 time or a duration before `now` such as `12h`, `7d` or `2w`
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+
+    let invalid = || anyhow!("Invalid time: {} (use 2024-05-01, 12h, 7d or 2w)", value);
+    let unit = value.chars().last().ok_or_else(invalid)?;
+    let amount: i64 = value[..value.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    let duration = match unit {
+        'm' => Duration::try_minutes(amount),
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => return Err(invalid()),
+    };
+    // 过大的时长会溢出, 报错而不是 panic
+    duration
+        .and_then(|duration| now.checked_sub_signed(duration))
+        .ok_or_else(|| anyhow!("Time out of range: {}", value))
+}
@@ -0,0 +1,171 @@
+//! Files shipped next to a skill's `SKILL.md`
+//!
+//! A skill directory may contain, besides `SKILL.md`:
+//!
+//! - resource files such as `REFERENCE.md` or `templates/`, read on demand
+//!   with the `read_skill_resource` tool
+//! - `scripts/`, each script exposed as a tool named `<skill>_<script>`
+//! - `tools/init.lua`, registering Lua tools with `kota.register_tool`
+//!
+//! Scripts and Lua tools are only available while the skill is active.
+
+use super::Skill;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Directory holding the scripts of a skill
+pub const SCRIPTS_DIR: &str = "scripts";
+/// Directory holding the Lua tools of a skill
+pub const TOOLS_DIR: &str = "tools";
+
+/// A script of a skill exposed as a tool
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkillScript {
+    /// Tool name, `<skill>_<file stem>`
+    pub tool_name: String,
+    pub path: PathBuf,
+    /// First comment of the script, or a generic description
+    pub description: String,
+}
+
+impl Skill {
+    /// Files of the skill relative to its directory, without `SKILL.md` and Lua tools
+    pub fn resources(&self) -> Vec<String> {
+        let Some(dir) = &self.dir else {
+            return Vec::new();
+        };
+        let mut files = Vec::new();
+        collect_files(dir, dir, &mut files);
+        files.retain(|file| file != "SKILL.md" && !file.starts_with(&format!("{}/", TOOLS_DIR)));
+        files.sort();
+        files
+    }
+
+    /// Read a resource file of the skill
+    ///
+    /// `path` is relative to the skill directory and may not leave it.
+    pub fn read_resource(&self, path: &str) -> Result<String> {
+        let dir = self
+            .dir
+            .as_ref()
+            .with_context(|| format!("Skill '{}' has no directory", self.name))?;
+        let relative = Path::new(path);
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            anyhow::bail!(
+                "Resource path must stay inside the skill directory: {}",
+                path
+            );
+        }
+        let full_path = dir.join(relative);
+        if !full_path.is_file() {
+            anyhow::bail!(
+                "Skill '{}' has no resource '{}', available: {}",
+                self.name,
+                path,
+                self.resources().join(", ")
+            );
+        }
+        fs::read_to_string(&full_path)
+            .with_context(|| format!("Failed to read skill resource: {:?}", full_path))
+    }
+
+    /// Scripts in the `scripts/` directory of the skill
+    pub fn scripts(&self) -> Vec<SkillScript> {
+        let Some(dir) = &self.dir else {
+            return Vec::new();
+        };
+        let Ok(entries) = fs::read_dir(dir.join(SCRIPTS_DIR)) else {
+            return Vec::new();
+        };
+        let mut scripts: Vec<SkillScript> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .map(|path| {
+                let stem = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let file_name = path
+                    .file_name()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let description = script_comment(&path).unwrap_or_else(|| {
+                    format!(
+                        "Run {}/{} of the {} skill",
+                        SCRIPTS_DIR, file_name, self.name
+                    )
+                });
+                SkillScript {
+                    tool_name: tool_name(&format!("{}_{}", self.name, stem)),
+                    path,
+                    description,
+                }
+            })
+            .collect();
+        scripts.sort_by(|a, b| a.tool_name.cmp(&b.tool_name));
+        scripts
+    }
+
+    /// `tools/init.lua` of the skill, if it exists
+    pub fn tools_file(&self) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(TOOLS_DIR).join("init.lua"))
+            .filter(|path| path.is_file())
+    }
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(root, &path, files);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            // 统一使用 `/` 作为分隔符, 方便模型引用
+            let parts: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+            files.push(parts.join("/"));
+        }
+    }
+}
+
+/// First comment line of a script, skipping the shebang
+fn script_comment(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    content
+        .lines()
+        .filter(|line| !line.starts_with("#!"))
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .and_then(|line| {
+            ["#", "//", "--", "REM ", "::"]
+                .iter()
+                .find_map(|prefix| line.strip_prefix(prefix))
+        })
+        .map(|comment| comment.trim().to_string())
+        .filter(|comment| !comment.is_empty())
+}
+
+/// Tool names may only contain letters, digits, `_` and `-`
+fn tool_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect()
+}
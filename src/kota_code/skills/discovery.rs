@@ -0,0 +1,203 @@
+//! Directories skills are loaded from
+//!
+//! Skills are merged from these directories, later ones taking precedence
+//! when two skills share a name:
+//!
+//! 1. `~/.kota/skills` (global)
+//! 2. the directories listed in `skill_dirs` of `config.lua`, in order
+//! 3. `.kota/skills` of the project
+//!
+//! Skills added with [`SkillManager::add_skill`] take precedence over all of
+//! them. Nothing is written to these directories unless a skill is created.
+
+use super::{Skill, SkillManager};
+use crate::kota_code::memory::home_dir;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// File describing a skill inside its directory
+pub const SKILL_FILE_NAME: &str = "SKILL.md";
+
+/// Where a skill was loaded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkillScope {
+    /// `~/.kota/skills`, shared by all projects
+    Global,
+    /// A directory listed in `skill_dirs` of the configuration
+    Config,
+    /// `.kota/skills` of the project
+    Project,
+}
+
+impl SkillScope {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SkillScope::Global => "global",
+            SkillScope::Config => "config",
+            SkillScope::Project => "project",
+        }
+    }
+}
+
+/// A directory holding one sub-directory per skill
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkillDir {
+    pub path: PathBuf,
+    pub scope: SkillScope,
+}
+
+/// Modification time and size of every skill directory and `SKILL.md`
+pub(super) type Fingerprint = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+impl SkillManager {
+    /// Load the skills of `project_dir` and `global_dir`
+    ///
+    /// # Arguments
+    ///
+    /// * `project_dir` - Project skills, usually `.kota/skills`
+    /// * `global_dir` - Skills shared by all projects, usually `~/.kota/skills`
+    pub fn discover<P: AsRef<Path>>(project_dir: P, global_dir: Option<PathBuf>) -> Self {
+        let mut dirs = Vec::new();
+        if let Some(path) = global_dir {
+            dirs.push(SkillDir {
+                path,
+                scope: SkillScope::Global,
+            });
+        }
+        dirs.push(SkillDir {
+            path: project_dir.as_ref().to_path_buf(),
+            scope: SkillScope::Project,
+        });
+
+        let mut manager = Self::empty(dirs);
+        manager.reload();
+        manager
+    }
+
+    /// Also load the skills of `dirs`, taking precedence over global skills
+    /// and giving way to project skills
+    pub fn with_skill_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        let position = self
+            .dirs
+            .iter()
+            .position(|dir| dir.scope == SkillScope::Project)
+            .unwrap_or(self.dirs.len());
+        self.dirs.splice(
+            position..position,
+            dirs.into_iter().map(|path| SkillDir {
+                path,
+                scope: SkillScope::Config,
+            }),
+        );
+        self.reload();
+        self
+    }
+
+    /// Directories skills are loaded from, lowest precedence first
+    pub fn skill_dirs(&self) -> &[SkillDir] {
+        &self.dirs
+    }
+
+    /// Read all skill directories again
+    ///
+    /// Skills added with [`SkillManager::add_skill`] are kept. Active skills
+    /// that no longer exist are ignored until they come back.
+    pub fn reload(&mut self) {
+        let mut loaded: HashMap<String, Skill> = HashMap::new();
+        for dir in &self.dirs {
+            for skill in load_dir(dir) {
+                loaded.insert(skill.name.clone(), skill);
+            }
+        }
+        self.skills.retain(|_, skill| skill.scope.is_none());
+        for (name, skill) in loaded {
+            self.skills.entry(name).or_insert(skill);
+        }
+        self.fingerprint = fingerprint(&self.dirs);
+    }
+
+    /// Reload the skills when a skill directory or `SKILL.md` changed
+    ///
+    /// Returns whether the skills were reloaded.
+    pub fn reload_if_changed(&mut self) -> bool {
+        if fingerprint(&self.dirs) == self.fingerprint {
+            return false;
+        }
+        self.reload();
+        true
+    }
+
+    /// Directory new skills are created in
+    pub(super) fn project_dir(&self) -> Result<&Path> {
+        match self
+            .dirs
+            .iter()
+            .find(|dir| dir.scope == SkillScope::Project)
+        {
+            Some(dir) => Ok(&dir.path),
+            None => bail!("No project skill directory"),
+        }
+    }
+}
+
+/// `~/.kota/skills`, `None` when the home directory is unknown
+pub(super) fn global_skills_dir() -> Option<PathBuf> {
+    home_dir().map(|home| home.join(".kota").join("skills"))
+}
+
+/// Skills of one directory, invalid ones are reported and skipped
+fn load_dir(dir: &SkillDir) -> Vec<Skill> {
+    let Ok(entries) = fs::read_dir(&dir.path) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+
+    let mut skills = Vec::new();
+    for path in paths {
+        let skill_file = path.join(SKILL_FILE_NAME);
+        if !skill_file.is_file() {
+            continue;
+        }
+        match parse_skill_file(&skill_file) {
+            Ok(mut skill) => {
+                skill.dir = Some(path);
+                skill.scope = Some(dir.scope);
+                skills.push(skill);
+            }
+            Err(e) => eprintln!("Warning: {:#}", e),
+        }
+    }
+    skills
+}
+
+fn parse_skill_file(path: &Path) -> Result<Skill> {
+    let content = fs::read_to_string(path)?;
+    Skill::parse(&content).with_context(|| format!("Failed to parse {:?}", path))
+}
+
+fn fingerprint(dirs: &[SkillDir]) -> Fingerprint {
+    let mut entries = Vec::new();
+    for dir in dirs {
+        push_entry(&mut entries, &dir.path);
+        let Ok(children) = fs::read_dir(&dir.path) else {
+            continue;
+        };
+        let mut children: Vec<PathBuf> = children.flatten().map(|entry| entry.path()).collect();
+        children.sort();
+        for child in children {
+            push_entry(&mut entries, &child);
+            push_entry(&mut entries, &child.join(SKILL_FILE_NAME));
+        }
+    }
+    entries
+}
+
+fn push_entry(entries: &mut Fingerprint, path: &Path) {
+    if let Ok(metadata) = fs::metadata(path) {
+        entries.push((path.to_path_buf(), metadata.modified().ok(), metadata.len()));
+    }
+}
@@ -0,0 +1,114 @@
+//! YAML frontmatter of `SKILL.md`
+//!
+//! A `SKILL.md` starts with a YAML block between two `---` lines, followed by
+//! the Markdown instructions:
+//!
+//! ```markdown
+//! ---
+//! name: reviewer
+//! description: >
+//!   Review code for bugs
+//!   and style issues
+//! version: 1.2
+//! model: gpt-4o
+//! allowed-tools: [read_file, grep_find]
+//! ---
+//!
+//! Review the changes...
+//! ```
+//!
+//! Lists may also be written as a comma separated string, `allowed-tools` and
+//! `disallowed-tools` are accepted as aliases of the snake case keys. Unknown
+//! keys are ignored.
+
+use super::Skill;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+
+impl Skill {
+    /// Parse the content of a `SKILL.md` file
+    pub fn parse(content: &str) -> Result<Skill> {
+        let content = content.trim_start_matches('\u{feff}');
+        let mut lines = content.split_inclusive('\n');
+        if lines.next().map(str::trim_end) != Some("---") {
+            bail!("SKILL.md must start with a '---' line");
+        }
+
+        // frontmatter 在下一行单独的 `---` 处结束, 正文中的 `---` 不受影响
+        let mut yaml = String::new();
+        let mut closed = false;
+        for line in lines.by_ref() {
+            if line.trim_end() == "---" {
+                closed = true;
+                break;
+            }
+            yaml.push_str(line);
+        }
+        if !closed {
+            bail!("SKILL.md frontmatter is not closed with '---'");
+        }
+        let body: String = lines.collect();
+
+        let mut skill: Skill = if yaml.trim().is_empty() {
+            Skill::default()
+        } else {
+            serde_yaml::from_str(&yaml).context("Invalid SKILL.md frontmatter")?
+        };
+        if skill.name.trim().is_empty() {
+            bail!("SKILL.md frontmatter has no name");
+        }
+        skill.instructions = body.trim().to_string();
+        Ok(skill)
+    }
+}
+
+/// Content of a new `SKILL.md` with a name and a description
+pub(super) fn render(name: &str, description: &str, instructions: &str) -> Result<String> {
+    #[derive(Serialize)]
+    struct Frontmatter<'a> {
+        name: &'a str,
+        description: &'a str,
+    }
+
+    let yaml = serde_yaml::to_string(&Frontmatter { name, description })?;
+    Ok(format!("---\n{}---\n\n{}\n", yaml, instructions))
+}
+
+/// A list written as a YAML sequence or as a comma separated string
+pub(super) fn string_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Items(Vec<String>),
+        Text(String),
+    }
+
+    let items = match Option::<List>::deserialize(deserializer)? {
+        Some(List::Items(items)) => items,
+        Some(List::Text(text)) => text.split(',').map(str::to_string).collect(),
+        None => Vec::new(),
+    };
+    Ok(items
+        .into_iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect())
+}
+
+/// A version written as a string or a number, `version: 1.2` is read as `"1.2"`
+pub(super) fn version<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<serde_yaml::Value>::deserialize(deserializer)? {
+        None | Some(serde_yaml::Value::Null) => Ok(None),
+        Some(serde_yaml::Value::String(version)) => Ok(Some(version)),
+        Some(serde_yaml::Value::Number(version)) => Ok(Some(version.to_string())),
+        Some(_) => Err(serde::de::Error::custom(
+            "version must be a string or a number",
+        )),
+    }
+}
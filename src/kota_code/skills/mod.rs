@@ -0,0 +1,352 @@
+mod bundle;
+mod discovery;
+mod frontmatter;
+mod ranking;
+
+pub use bundle::{SkillScript, SCRIPTS_DIR, TOOLS_DIR};
+pub use discovery::{SkillDir, SkillScope, SKILL_FILE_NAME};
+pub use ranking::{AutoSelect, SkillMatch, TRIGGER_SCORE};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Skill 定义 (SKILL.md 格式)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Skill {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub instructions: String, // Markdown body
+    #[serde(default, deserialize_with = "frontmatter::version")]
+    pub version: Option<String>,
+    #[serde(default, deserialize_with = "frontmatter::string_list")]
+    pub dependencies: Vec<String>,
+    /// 出现在用户输入中时自动选择该 skill 的短语
+    #[serde(default, deserialize_with = "frontmatter::string_list")]
+    pub triggers: Vec<String>,
+    /// 激活时只保留这些工具, 为空表示不限制 (skill 自带的工具不受影响)
+    #[serde(
+        default,
+        alias = "allowed-tools",
+        deserialize_with = "frontmatter::string_list"
+    )]
+    pub allowed_tools: Vec<String>,
+    /// 激活时移除这些工具
+    #[serde(
+        default,
+        alias = "disallowed-tools",
+        deserialize_with = "frontmatter::string_list"
+    )]
+    pub disallowed_tools: Vec<String>,
+    /// 激活时替换模型, 由同一个 provider 提供
+    #[serde(default)]
+    pub model: Option<String>,
+    /// 覆盖默认生成参数 (frontmatter: temperature / top_p / max_tokens / max_turns)
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub top_p: Option<f64>,
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    #[serde(default)]
+    pub max_turns: Option<usize>,
+    /// SKILL.md 所在目录, 存放参考文件、脚本和 Lua 工具
+    #[serde(skip)]
+    pub dir: Option<PathBuf>,
+    /// 从哪个 skill 目录加载, 通过 `add_skill` 添加的为 `None`
+    #[serde(skip)]
+    pub scope: Option<SkillScope>,
+}
+
+impl Skill {
+    pub fn disallows_tool(&self, name: &str) -> bool {
+        self.disallowed_tools.iter().any(|t| t == name)
+    }
+}
+
+/// Skill 管理器
+#[derive(Clone)]
+pub struct SkillManager {
+    skills: HashMap<String, Skill>,
+    /// 用户显式激活的 skill, 按激活顺序; 依赖在查询时展开
+    active_skills: Vec<String>,
+    /// 仅对当前这一轮生效的自动选择的 skill
+    turn_skills: Vec<String>,
+    auto_select: Option<AutoSelect>,
+    /// 加载 skill 的目录, 优先级从低到高
+    dirs: Vec<SkillDir>,
+    /// 上次加载时各目录的状态, 用于检测修改
+    fingerprint: discovery::Fingerprint,
+}
+
+impl Default for SkillManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SkillManager {
+    /// Load the skills of `.kota/skills` and `~/.kota/skills`
+    pub fn new() -> Self {
+        Self::discover(".kota/skills", discovery::global_skills_dir())
+    }
+
+    fn empty(dirs: Vec<SkillDir>) -> Self {
+        Self {
+            skills: HashMap::new(),
+            active_skills: Vec::new(),
+            turn_skills: Vec::new(),
+            auto_select: None,
+            dirs,
+            fingerprint: Vec::new(),
+        }
+    }
+
+    /// 添加技能
+    pub fn add_skill(&mut self, skill: Skill) {
+        self.skills.insert(skill.name.clone(), skill);
+    }
+
+    /// 获取增强的 preamble, 依次附加所有激活的 skill
+    pub fn get_enhanced_preamble(&self, base_preamble: &str) -> String {
+        let mut preamble = base_preamble.to_string();
+        for skill in self.get_active_skills() {
+            preamble.push_str(&format!(
+                "\n\n[ACTIVE SKILL: {}]\n{}\n\n{}",
+                skill.name, skill.description, skill.instructions
+            ));
+            let resources: Vec<String> = skill
+                .resources()
+                .into_iter()
+                .filter(|file| !file.starts_with(&format!("{}/", SCRIPTS_DIR)))
+                .collect();
+            if !resources.is_empty() {
+                preamble.push_str(&format!(
+                    "\n\nResources of this skill, read them with read_skill_resource when needed: {}",
+                    resources.join(", ")
+                ));
+            }
+            let scripts: Vec<String> = skill.scripts().into_iter().map(|s| s.tool_name).collect();
+            if !scripts.is_empty() {
+                preamble.push_str(&format!(
+                    "\n\nScripts of this skill, available as tools: {}",
+                    scripts.join(", ")
+                ));
+            }
+        }
+        preamble
+    }
+
+    /// 在项目的 skill 目录中创建新技能
+    pub fn create_skill(
+        &mut self,
+        name: &str,
+        description: &str,
+        instructions: &str,
+    ) -> Result<()> {
+        let dir = self.project_dir()?.join(name);
+        fs::create_dir_all(&dir)?;
+        fs::write(
+            dir.join(SKILL_FILE_NAME),
+            frontmatter::render(name, description, instructions)?,
+        )?;
+        self.reload();
+        Ok(())
+    }
+
+    /// 删除项目中的技能, 全局和配置目录中的技能需要手动删除
+    pub fn remove_skill(&mut self, name: &str) -> Result<()> {
+        let Some(skill) = self.skills.get(name) else {
+            bail!("Skill '{}' not found", name);
+        };
+        match (skill.scope, &skill.dir) {
+            (Some(SkillScope::Project), Some(dir)) => fs::remove_dir_all(dir)?,
+            (Some(scope), Some(dir)) => bail!(
+                "Skill '{}' is a {} skill, remove {:?} to delete it",
+                name,
+                scope.label(),
+                dir
+            ),
+            _ => {}
+        }
+        self.skills.remove(name);
+        self.active_skills.retain(|active| active != name);
+        self.turn_skills.retain(|active| active != name);
+        // 项目中的 skill 可能覆盖了同名的全局 skill
+        self.reload();
+        Ok(())
+    }
+
+    pub fn get_skill(&self, name: &str) -> Option<&Skill> {
+        self.skills.get(name)
+    }
+
+    pub fn list_skills(&self) -> Vec<&Skill> {
+        self.skills.values().collect()
+    }
+
+    /// Activate a skill together with the skills it depends on
+    ///
+    /// Returns the names of the skills that were not active before,
+    /// dependencies first.
+    pub fn activate_skill(&mut self, name: &str) -> Result<Vec<String>> {
+        let resolved = self.resolve_dependencies(name)?;
+        let active = self.active_skill_names();
+        if !self.active_skills.iter().any(|active| active == name) {
+            self.active_skills.push(name.to_string());
+        }
+        Ok(resolved
+            .into_iter()
+            .filter(|skill| !active.contains(skill))
+            .collect())
+    }
+
+    /// Deactivate a skill activated with [`SkillManager::activate_skill`]
+    ///
+    /// Dependencies that no other active skill requires are deactivated with it.
+    pub fn deactivate_skill(&mut self, name: &str) -> Result<()> {
+        if let Some(index) = self.active_skills.iter().position(|active| active == name) {
+            self.active_skills.remove(index);
+            return Ok(());
+        }
+        match self.active_skills.iter().find(|active| {
+            self.resolve_dependencies(active)
+                .is_ok_and(|deps| deps.iter().any(|dep| dep == name))
+        }) {
+            Some(dependent) => bail!("Skill '{}' is required by '{}'", name, dependent),
+            None => bail!("Skill '{}' is not active", name),
+        }
+    }
+
+    pub fn deactivate_all_skills(&mut self) {
+        self.active_skills.clear();
+    }
+
+    /// Enable automatic skill selection, `None` turns it off
+    pub fn with_auto_select(mut self, auto_select: Option<AutoSelect>) -> Self {
+        self.auto_select = auto_select;
+        self
+    }
+
+    pub fn set_auto_select(&mut self, auto_select: Option<AutoSelect>) {
+        self.auto_select = auto_select;
+    }
+
+    pub fn auto_select(&self) -> Option<&AutoSelect> {
+        self.auto_select.as_ref()
+    }
+
+    /// Rank all skills against `prompt`, best match first
+    pub fn rank_skills(&self, prompt: &str) -> Vec<SkillMatch> {
+        ranking::rank(self.skills.values(), prompt)
+    }
+
+    /// Activate the best matching skills for the next turn only
+    ///
+    /// Does nothing unless automatic selection is enabled. Skills that are
+    /// already active or whose dependencies cannot be resolved are skipped.
+    /// The selection lasts until [`SkillManager::clear_turn_skills`].
+    pub fn select_for_turn(&mut self, prompt: &str) -> Vec<SkillMatch> {
+        self.turn_skills.clear();
+        let Some(auto_select) = &self.auto_select else {
+            return Vec::new();
+        };
+        let selected: Vec<SkillMatch> = self
+            .rank_skills(prompt)
+            .into_iter()
+            .filter(|m| m.score >= auto_select.min_score)
+            .filter(|m| !self.is_active(&m.name) && self.resolve_dependencies(&m.name).is_ok())
+            .take(auto_select.max_skills)
+            .collect();
+        self.turn_skills = selected.iter().map(|m| m.name.clone()).collect();
+        selected
+    }
+
+    pub fn clear_turn_skills(&mut self) {
+        self.turn_skills.clear();
+    }
+
+    /// Active skills including dependencies, each dependency before the skills requiring it
+    pub fn get_active_skills(&self) -> Vec<&Skill> {
+        self.active_skill_names()
+            .iter()
+            .filter_map(|name| self.skills.get(name))
+            .collect()
+    }
+
+    pub fn is_active(&self, name: &str) -> bool {
+        self.active_skill_names()
+            .iter()
+            .any(|active| active == name)
+    }
+
+    /// Whether the active skills leave the agent's tool `name` enabled
+    ///
+    /// A tool must be listed by every active skill with `allowed_tools` and
+    /// may not be listed in the `disallowed_tools` of any active skill.
+    pub fn allows_tool(&self, name: &str) -> bool {
+        self.get_active_skills().iter().all(|skill| {
+            (skill.allowed_tools.is_empty() || skill.allowed_tools.iter().any(|t| t == name))
+                && !skill.disallows_tool(name)
+        })
+    }
+
+    /// Model requested by the active skills, the skill activated last wins
+    pub fn active_model(&self) -> Option<&str> {
+        self.get_active_skills()
+            .into_iter()
+            .rev()
+            .find_map(|skill| skill.model.as_deref())
+    }
+
+    /// Names of the active skills including dependencies
+    pub fn active_skill_names(&self) -> Vec<String> {
+        let mut order = Vec::new();
+        for name in self.active_skills.iter().chain(&self.turn_skills) {
+            // 已删除或依赖缺失的 skill 直接跳过
+            let _ = self.visit(name, &mut Vec::new(), &mut order);
+        }
+        order
+    }
+
+    /// `name` and all skills it depends on, dependencies first
+    pub fn resolve_dependencies(&self, name: &str) -> Result<Vec<String>> {
+        let mut order = Vec::new();
+        self.visit(name, &mut Vec::new(), &mut order)?;
+        Ok(order)
+    }
+
+    fn visit(&self, name: &str, path: &mut Vec<String>, order: &mut Vec<String>) -> Result<()> {
+        if order.iter().any(|done| done == name) {
+            return Ok(());
+        }
+        if path.iter().any(|visiting| visiting == name) {
+            bail!(
+                "Circular skill dependency: {} -> {}",
+                path.join(" -> "),
+                name
+            );
+        }
+        let Some(skill) = self.skills.get(name) else {
+            match path.last() {
+                Some(dependent) => {
+                    bail!("Skill '{}' required by '{}' not found", name, dependent)
+                }
+                None => bail!("Skill '{}' not found", name),
+            }
+        };
+
+        path.push(name.to_string());
+        for dependency in &skill.dependencies {
+            self.visit(dependency, path, order)?;
+        }
+        path.pop();
+        order.push(name.to_string());
+        Ok(())
+    }
+}
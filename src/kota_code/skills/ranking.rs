@@ -0,0 +1,191 @@
+//! Ranking of skills against a user prompt
+//!
+//! Each skill is scored with BM25 over its name, description and triggers.
+//! A trigger phrase found verbatim in the prompt adds [`TRIGGER_SCORE`] on top.
+
+use super::Skill;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Score added for every trigger phrase found in the prompt
+pub const TRIGGER_SCORE: f64 = 5.0;
+
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "can", "could", "do", "does", "for",
+    "from", "has", "have", "how", "in", "into", "is", "it", "its", "me", "my", "of", "on", "or",
+    "our", "please", "should", "so", "that", "the", "their", "them", "then", "there", "these",
+    "this", "to", "us", "was", "we", "what", "when", "where", "which", "why", "will", "with",
+    "would", "you", "your",
+];
+
+/// Settings of automatic skill selection
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoSelect {
+    /// Maximum number of skills applied to one turn
+    pub max_skills: usize,
+    /// Minimum score a skill needs to be applied
+    pub min_score: f64,
+}
+
+impl Default for AutoSelect {
+    fn default() -> Self {
+        Self {
+            max_skills: 2,
+            min_score: 1.0,
+        }
+    }
+}
+
+/// A skill that matched a prompt
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkillMatch {
+    pub name: String,
+    pub score: f64,
+    /// Trigger phrases found in the prompt
+    pub triggers: Vec<String>,
+    /// Prompt keywords that also occur in the skill
+    pub keywords: Vec<String>,
+}
+
+impl SkillMatch {
+    /// Human readable explanation of the match
+    pub fn reason(&self) -> String {
+        let mut reasons = Vec::new();
+        if !self.triggers.is_empty() {
+            let triggers: Vec<_> = self.triggers.iter().map(|t| format!("\"{}\"", t)).collect();
+            reasons.push(format!("trigger {}", triggers.join(", ")));
+        }
+        if !self.keywords.is_empty() {
+            reasons.push(format!("keywords {}", self.keywords.join(", ")));
+        }
+        format!("{} (score {:.2})", reasons.join("; "), self.score)
+    }
+}
+
+/// Rank `skills` against `prompt`, best match first
+///
+/// Skills without any match are left out.
+pub fn rank<'a, I>(skills: I, prompt: &str) -> Vec<SkillMatch>
+where
+    I: IntoIterator<Item = &'a Skill>,
+{
+    let documents: Vec<(&Skill, Vec<String>)> = skills
+        .into_iter()
+        .map(|skill| (skill, document_terms(skill)))
+        .collect();
+    if documents.is_empty() {
+        return Vec::new();
+    }
+
+    let total = documents.len() as f64;
+    let avg_len = documents
+        .iter()
+        .map(|(_, terms)| terms.len())
+        .sum::<usize>() as f64
+        / total;
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for (_, terms) in &documents {
+        for term in terms.iter().map(String::as_str).collect::<HashSet<_>>() {
+            *document_frequency.entry(term).or_default() += 1;
+        }
+    }
+
+    let mut query: Vec<String> = Vec::new();
+    for term in tokenize(prompt) {
+        if !query.contains(&term) {
+            query.push(term);
+        }
+    }
+    let normalized_prompt = normalize(prompt);
+
+    let mut matches: Vec<SkillMatch> = documents
+        .iter()
+        .filter_map(|(skill, terms)| {
+            let len = terms.len() as f64;
+            let mut score = 0.0;
+            let mut keywords = Vec::new();
+            for term in &query {
+                let tf = terms.iter().filter(|t| *t == term).count() as f64;
+                if tf == 0.0 {
+                    continue;
+                }
+                let n = document_frequency[term.as_str()] as f64;
+                let idf = ((total - n + 0.5) / (n + 0.5) + 1.0).ln();
+                score += idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * len / avg_len));
+                keywords.push(term.clone());
+            }
+
+            let triggers: Vec<String> = skill
+                .triggers
+                .iter()
+                .filter(|trigger| {
+                    let trigger = normalize(trigger);
+                    !trigger.trim().is_empty() && normalized_prompt.contains(&trigger)
+                })
+                .cloned()
+                .collect();
+            score += TRIGGER_SCORE * triggers.len() as f64;
+
+            (score > 0.0).then(|| SkillMatch {
+                name: skill.name.clone(),
+                score,
+                triggers,
+                keywords,
+            })
+        })
+        .collect();
+
+    matches.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    matches
+}
+
+fn document_terms(skill: &Skill) -> Vec<String> {
+    let mut text = format!("{} {}", skill.name, skill.description);
+    for trigger in &skill.triggers {
+        text.push(' ');
+        text.push_str(trigger);
+    }
+    tokenize(&text)
+}
+
+/// Lowercase words without stopwords, lightly stemmed
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(|word| word.to_lowercase())
+        .filter(|word| word.chars().count() > 1 && !STOPWORDS.contains(&word.as_str()))
+        .map(|word| stem(&word))
+        .collect()
+}
+
+/// 去掉常见的英文词尾, 让 debugging / debug, reviews / review 能互相匹配
+fn stem(word: &str) -> String {
+    if let Some(base) = word.strip_suffix("ing").filter(|base| base.len() >= 3) {
+        let mut base = base.to_string();
+        let bytes = base.as_bytes();
+        if bytes.len() >= 2 && bytes[bytes.len() - 1] == bytes[bytes.len() - 2] {
+            base.pop();
+        }
+        return base;
+    }
+    match word.strip_suffix('s') {
+        Some(base) if base.len() >= 3 && !base.ends_with('s') => base.to_string(),
+        _ => word.to_string(),
+    }
+}
+
+/// Lowercase words separated by single spaces, padded so phrases match whole words
+fn normalize(text: &str) -> String {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+    format!(" {} ", words.join(" "))
+}
@@ -0,0 +1,253 @@
+use anyhow::{Context, Result};
+use rig::completion::Message;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::{check_revision, decode_messages, encode_messages, SessionStore, StoredSession};
+use crate::kota_code::context::{SessionMetadata, SESSION_VERSION};
+use crate::kota_code::search::{SearchQuery, SessionMatch};
+
+/// Sessions saved as `<session_id>.json` files in a directory
+///
+/// Several processes may share the directory: a session file is replaced by
+/// renaming a complete new file over it, and writers of a session take its
+/// `.<session_id>.lock` file first.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+/// 持久化的会话数据
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionData {
+    #[serde(default = "legacy_version")]
+    version: u32,
+    metadata: SessionMetadata,
+    messages: Vec<serde_json::Value>,
+    /// 被压缩为摘要的原始消息
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    archived: Vec<serde_json::Value>,
+}
+
+fn legacy_version() -> u32 {
+    1
+}
+
+impl SessionData {
+    fn new(metadata: &SessionMetadata, messages: &[Message], archived: &[Message]) -> Result<Self> {
+        Ok(Self {
+            version: SESSION_VERSION,
+            metadata: metadata.clone(),
+            messages: encode_messages(messages)?,
+            archived: encode_messages(archived)?,
+        })
+    }
+
+    fn decode(self) -> Result<StoredSession> {
+        Ok(StoredSession {
+            messages: decode_messages(self.version, &self.messages)?,
+            archived: decode_messages(self.version, &self.archived)?,
+            metadata: self.metadata,
+        })
+    }
+}
+
+fn read_session_file(path: &Path) -> Result<SessionData> {
+    let json_data = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read session file: {:?}", path))?;
+    serde_json::from_str(&json_data).context("Failed to deserialize session data")
+}
+
+/// Only the metadata of a session file
+#[derive(Deserialize)]
+struct SessionHeader {
+    metadata: SessionMetadata,
+}
+
+/// Write the file next to `path` and rename it over `path`
+///
+/// Readers see either the previous or the new session, never a partial file.
+fn write_session_file(path: &Path, session_data: &SessionData) -> Result<()> {
+    let json_data =
+        serde_json::to_string_pretty(session_data).context("Failed to serialize session data")?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    let written = fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(json_data.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written.with_context(|| format!("Failed to write session file: {:?}", path))
+}
+
+impl FileStore {
+    /// Use `dir` for the session files, it is created if missing
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create storage directory: {:?}", dir))?;
+        }
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, session_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", session_id))
+    }
+
+    /// Take the advisory lock of a session, released when the file is dropped
+    ///
+    /// Writers of the same session wait for each other, across processes.
+    /// Lock files are kept after the session is deleted, so every writer
+    /// always locks the same file.
+    fn lock(&self, session_id: &str) -> Result<fs::File> {
+        let path = self.dir.join(format!(".{}.lock", session_id));
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open session lock: {:?}", path))?;
+        file.lock()
+            .with_context(|| format!("Failed to lock session: {:?}", path))?;
+        Ok(file)
+    }
+
+    fn session_files(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        if !self.dir.exists() {
+            return Ok(paths);
+        }
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|s| s.to_str()) == Some("json") {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+}
+
+impl SessionStore for FileStore {
+    fn load(&self, session_id: &str) -> Result<Option<StoredSession>> {
+        let path = self.path(session_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        read_session_file(&path)?.decode().map(Some)
+    }
+
+    fn save(
+        &self,
+        metadata: &SessionMetadata,
+        messages: &[Message],
+        archived: &[Message],
+    ) -> Result<()> {
+        let session_data = SessionData::new(metadata, messages, archived)?;
+        let _lock = self.lock(&metadata.session_id)?;
+        write_session_file(&self.path(&metadata.session_id), &session_data)
+    }
+
+    fn save_if(
+        &self,
+        expected: Option<u64>,
+        metadata: &SessionMetadata,
+        messages: &[Message],
+        archived: &[Message],
+    ) -> Result<()> {
+        let session_data = SessionData::new(metadata, messages, archived)?;
+        let _lock = self.lock(&metadata.session_id)?;
+        check_revision(
+            &metadata.session_id,
+            expected,
+            self.revision(&metadata.session_id)?,
+        )?;
+        write_session_file(&self.path(&metadata.session_id), &session_data)
+    }
+
+    /// Reads the metadata without decoding the messages
+    fn revision(&self, session_id: &str) -> Result<Option<u64>> {
+        let path = self.path(session_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json_data = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read session file: {:?}", path))?;
+        let header: SessionHeader =
+            serde_json::from_str(&json_data).context("Failed to deserialize session data")?;
+        Ok(Some(header.metadata.revision))
+    }
+
+    fn delete(&self, session_id: &str) -> Result<bool> {
+        let _lock = self.lock(session_id)?;
+        let path = self.path(session_id);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to delete session file: {:?}", path))?;
+        Ok(true)
+    }
+
+    fn exists(&self, session_id: &str) -> Result<bool> {
+        Ok(self.path(session_id).exists())
+    }
+
+    /// Files that cannot be read are skipped
+    fn list(&self) -> Result<Vec<SessionMetadata>> {
+        Ok(self
+            .session_files()?
+            .iter()
+            .filter_map(|path| read_session_file(path).ok())
+            .map(|session_data| session_data.metadata)
+            .collect())
+    }
+
+    /// Files that cannot be read are skipped
+    fn search(&self, query: &SearchQuery) -> Result<Vec<SessionMatch>> {
+        let mut matches = Vec::new();
+        for path in self.session_files()? {
+            let Ok(session) = read_session_file(&path).and_then(SessionData::decode) else {
+                continue;
+            };
+            if let Some(session) = query.match_session(&session.metadata, &session.messages) {
+                matches.push(session);
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Metadata is kept as is. Files that cannot be read are skipped.
+    fn migrate(&self) -> Result<usize> {
+        let mut migrated = 0;
+        for path in self.session_files()? {
+            let Some(session_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let _lock = self.lock(session_id)?;
+            let Ok(session_data) = read_session_file(&path) else {
+                continue;
+            };
+            if session_data.version >= SESSION_VERSION {
+                continue;
+            }
+            let session = session_data.decode()?;
+            write_session_file(
+                &path,
+                &SessionData::new(&session.metadata, &session.messages, &session.archived)?,
+            )?;
+            migrated += 1;
+        }
+        Ok(migrated)
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::Result;
+use rig::completion::Message;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::{check_revision, SessionStore, StoredSession};
+use crate::kota_code::context::SessionMetadata;
+
+/// Sessions kept in memory only, lost when the store is dropped
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<String, StoredSession>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, session_id: &str) -> Result<Option<StoredSession>> {
+        Ok(self.sessions.lock().unwrap().get(session_id).cloned())
+    }
+
+    fn save(
+        &self,
+        metadata: &SessionMetadata,
+        messages: &[Message],
+        archived: &[Message],
+    ) -> Result<()> {
+        self.sessions.lock().unwrap().insert(
+            metadata.session_id.clone(),
+            StoredSession::new(metadata, messages, archived),
+        );
+        Ok(())
+    }
+
+    fn save_if(
+        &self,
+        expected: Option<u64>,
+        metadata: &SessionMetadata,
+        messages: &[Message],
+        archived: &[Message],
+    ) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let found = sessions
+            .get(&metadata.session_id)
+            .map(|session| session.metadata.revision);
+        check_revision(&metadata.session_id, expected, found)?;
+        sessions.insert(
+            metadata.session_id.clone(),
+            StoredSession::new(metadata, messages, archived),
+        );
+        Ok(())
+    }
+
+    fn delete(&self, session_id: &str) -> Result<bool> {
+        Ok(self.sessions.lock().unwrap().remove(session_id).is_some())
+    }
+
+    fn exists(&self, session_id: &str) -> Result<bool> {
+        Ok(self.sessions.lock().unwrap().contains_key(session_id))
+    }
+
+    fn list(&self) -> Result<Vec<SessionMetadata>> {
+        Ok(self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|session| session.metadata.clone())
+            .collect())
+    }
+}
@@ -0,0 +1,225 @@
+//! Storage backends for saved sessions
+//!
+//! [`ContextManager`](super::context::ContextManager) reads and writes sessions
+//! through a [`SessionStore`]:
+//!
+//! - [`FileStore`]: one JSON file per session in a directory, the default
+//! - [`SqliteStore`]: one SQLite database with indexed listing and search, for
+//!   many concurrent sessions
+//! - [`MemoryStore`]: nothing is written to disk, for tests and embedding
+
+use anyhow::{bail, Context, Result};
+use rig::completion::Message;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::context::{SerializableMessage, SessionMetadata, SESSION_VERSION};
+use super::search::{SearchQuery, SessionMatch};
+
+pub mod file;
+pub mod memory;
+pub mod sqlite;
+
+pub use file::FileStore;
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+/// A session as read from a store
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredSession {
+    pub metadata: SessionMetadata,
+    pub messages: Vec<Message>,
+    /// Messages removed from the history by trimming or compaction
+    pub archived: Vec<Message>,
+}
+
+impl StoredSession {
+    pub fn new(metadata: &SessionMetadata, messages: &[Message], archived: &[Message]) -> Self {
+        Self {
+            metadata: metadata.clone(),
+            messages: messages.to_vec(),
+            archived: archived.to_vec(),
+        }
+    }
+}
+
+/// Where sessions are saved
+///
+/// Implementations must be safe to share between agents, sub-agents of one
+/// agent save their sessions in the store of their parent.
+pub trait SessionStore: Debug + Send + Sync {
+    /// Read a session, `None` if it does not exist
+    fn load(&self, session_id: &str) -> Result<Option<StoredSession>>;
+
+    /// Create or replace the session `metadata.session_id`
+    fn save(
+        &self,
+        metadata: &SessionMetadata,
+        messages: &[Message],
+        archived: &[Message],
+    ) -> Result<()>;
+
+    /// Delete a session, returns `false` if it did not exist
+    fn delete(&self, session_id: &str) -> Result<bool>;
+
+    fn exists(&self, session_id: &str) -> Result<bool> {
+        Ok(self.load(session_id)?.is_some())
+    }
+
+    /// [`SessionMetadata::revision`] of the saved session, `None` if it does not exist
+    fn revision(&self, session_id: &str) -> Result<Option<u64>> {
+        Ok(self
+            .load(session_id)?
+            .map(|session| session.metadata.revision))
+    }
+
+    /// Save the session only if its saved revision is still `expected`
+    ///
+    /// `expected` is `None` for a session that was never saved. Fails with
+    /// [`SessionConflict`] when another writer saved, created or deleted the
+    /// session in the meantime. The default checks and writes in two steps;
+    /// stores shared between processes do both under one lock.
+    fn save_if(
+        &self,
+        expected: Option<u64>,
+        metadata: &SessionMetadata,
+        messages: &[Message],
+        archived: &[Message],
+    ) -> Result<()> {
+        check_revision(
+            &metadata.session_id,
+            expected,
+            self.revision(&metadata.session_id)?,
+        )?;
+        self.save(metadata, messages, archived)
+    }
+
+    /// Metadata of every readable session, in no particular order
+    fn list(&self) -> Result<Vec<SessionMetadata>>;
+
+    /// Sessions with messages matching `query`, in no particular order
+    fn search(&self, query: &SearchQuery) -> Result<Vec<SessionMatch>> {
+        let mut matches = Vec::new();
+        for metadata in self.list()? {
+            let Ok(Some(session)) = self.load(&metadata.session_id) else {
+                continue;
+            };
+            if let Some(session) = query.match_session(&session.metadata, &session.messages) {
+                matches.push(session);
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Rewrite sessions saved in an older format, returns how many were rewritten
+    fn migrate(&self) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+/// Error returned when a session changed in its store since it was loaded
+#[derive(Debug, thiserror::Error)]
+#[error("Session '{session_id}' was changed by another process since it was loaded")]
+pub struct SessionConflict {
+    pub session_id: String,
+    /// Revision the session was loaded or last saved with
+    pub expected: Option<u64>,
+    /// Revision found in the store, `None` if the session was deleted
+    pub found: Option<u64>,
+}
+
+/// Fail with [`SessionConflict`] unless `found` is the `expected` revision
+pub(crate) fn check_revision(
+    session_id: &str,
+    expected: Option<u64>,
+    found: Option<u64>,
+) -> Result<()> {
+    if expected != found {
+        return Err(SessionConflict {
+            session_id: session_id.to_string(),
+            expected,
+            found,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Session storage selected with `session_store` in `kota.setup`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreConfig {
+    /// JSON files in a directory
+    File(PathBuf),
+    /// A SQLite database file
+    Sqlite(PathBuf),
+    Memory,
+}
+
+/// Directory of the default file store
+pub const DEFAULT_SESSION_DIR: &str = "./.chat_sessions";
+
+/// Database of the SQLite store when no path is configured
+pub const DEFAULT_SQLITE_PATH: &str = "./.chat_sessions/sessions.db";
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self::File(PathBuf::from(DEFAULT_SESSION_DIR))
+    }
+}
+
+impl StoreConfig {
+    /// Parse a `backend` name, `path` replaces its default location
+    pub fn new(backend: &str, path: Option<PathBuf>) -> Result<Self> {
+        Ok(match backend {
+            "file" | "json" => Self::File(path.unwrap_or_else(|| DEFAULT_SESSION_DIR.into())),
+            "sqlite" => Self::Sqlite(path.unwrap_or_else(|| DEFAULT_SQLITE_PATH.into())),
+            "memory" => Self::Memory,
+            _ => bail!(
+                "Unknown session store '{}', expected file, sqlite or memory",
+                backend
+            ),
+        })
+    }
+
+    /// Open the configured store
+    pub fn open(&self) -> Result<Arc<dyn SessionStore>> {
+        Ok(match self {
+            Self::File(dir) => Arc::new(FileStore::new(dir)?),
+            Self::Sqlite(path) => Arc::new(SqliteStore::open(path)?),
+            Self::Memory => Arc::new(MemoryStore::new()),
+        })
+    }
+}
+
+/// Encode messages in the current [`SESSION_VERSION`]
+pub(crate) fn encode_messages(messages: &[Message]) -> Result<Vec<serde_json::Value>> {
+    messages
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<serde_json::Result<Vec<_>>>()
+        .context("Failed to serialize session messages")
+}
+
+/// 按会话版本解析消息, 旧版本的消息被转换为当前格式
+pub(crate) fn decode_messages(version: u32, values: &[serde_json::Value]) -> Result<Vec<Message>> {
+    match version {
+        1 => values
+            .iter()
+            .map(|value| {
+                serde_json::from_value::<SerializableMessage>(value.clone()).map(Message::from)
+            })
+            .collect::<serde_json::Result<_>>()
+            .context("Failed to deserialize version 1 session messages"),
+        SESSION_VERSION => values
+            .iter()
+            .map(|value| serde_json::from_value(value.clone()))
+            .collect::<serde_json::Result<_>>()
+            .context("Failed to deserialize session messages"),
+        version => bail!(
+            "Session file version {} is newer than the supported version {}",
+            version,
+            SESSION_VERSION
+        ),
+    }
+}
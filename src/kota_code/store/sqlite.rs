@@ -0,0 +1,284 @@
+use anyhow::{Context, Result};
+use rig::completion::Message;
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::{check_revision, decode_messages, encode_messages, SessionStore, StoredSession};
+use crate::kota_code::context::{SerializableMessage, SessionMetadata, SESSION_VERSION};
+use crate::kota_code::search::{SearchQuery, SessionMatch};
+
+/// How long a write waits for another process holding the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+PRAGMA foreign_keys = ON;
+CREATE TABLE IF NOT EXISTS sessions (
+    session_id TEXT PRIMARY KEY,
+    version INTEGER NOT NULL,
+    last_updated TEXT NOT NULL,
+    parent_session_id TEXT,
+    metadata TEXT NOT NULL,
+    archived TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sessions_last_updated ON sessions (last_updated);
+CREATE INDEX IF NOT EXISTS sessions_parent ON sessions (parent_session_id);
+CREATE TABLE IF NOT EXISTS messages (
+    session_id TEXT NOT NULL REFERENCES sessions (session_id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    message TEXT NOT NULL,
+    search_text TEXT NOT NULL,
+    PRIMARY KEY (session_id, position)
+);
+";
+
+/// Sessions saved in one SQLite database
+///
+/// Listing reads the metadata column only, ordered by an index on the update
+/// time. Search narrows the sessions down in SQL before scoring the messages
+/// of the candidates. Several processes may share the database file.
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Open or create the database at `path`, creating its directory if missing
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create storage directory: {:?}", parent))?;
+        }
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open session database: {:?}", path))?;
+        // WAL 模式下读写互不阻塞, 适合多个进程共用一个数据库
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .context("Failed to enable WAL mode")?;
+        Self::init(connection)
+    }
+
+    /// A database that lives in memory only
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self> {
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection
+            .execute_batch(SCHEMA)
+            .context("Failed to create the session tables")?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Sessions with a message containing at least one term of `query`
+    fn candidates(&self, query: &SearchQuery) -> Result<Vec<String>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT DISTINCT session_id FROM messages WHERE search_text LIKE ?1 ESCAPE '\\'",
+        )?;
+        let mut sessions = HashSet::new();
+        for term in query.terms() {
+            let pattern = format!("%{}%", escape_like(term));
+            for session_id in statement.query_map([pattern], |row| row.get::<_, String>(0))? {
+                sessions.insert(session_id?);
+            }
+        }
+        Ok(sessions.into_iter().collect())
+    }
+}
+
+/// Escape the wildcards of `LIKE`
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Replace the session row and the messages of `metadata.session_id`
+fn write_session(
+    transaction: &Transaction,
+    metadata: &SessionMetadata,
+    messages: &[Message],
+    archived: &[Message],
+) -> Result<()> {
+    transaction.execute(
+        "INSERT INTO sessions (session_id, version, last_updated, parent_session_id, metadata, archived)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT (session_id) DO UPDATE SET
+             version = excluded.version,
+             last_updated = excluded.last_updated,
+             parent_session_id = excluded.parent_session_id,
+             metadata = excluded.metadata,
+             archived = excluded.archived",
+        params![
+            metadata.session_id,
+            SESSION_VERSION,
+            metadata.last_updated,
+            metadata.parent_session_id,
+            serde_json::to_string(metadata).context("Failed to serialize session metadata")?,
+            serde_json::to_string(&encode_messages(archived)?)?,
+        ],
+    )?;
+    transaction.execute(
+        "DELETE FROM messages WHERE session_id = ?1",
+        [&metadata.session_id],
+    )?;
+    let mut insert = transaction.prepare(
+        "INSERT INTO messages (session_id, position, message, search_text) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (position, (message, value)) in messages.iter().zip(encode_messages(messages)?).enumerate()
+    {
+        insert.execute(params![
+            metadata.session_id,
+            position,
+            value.to_string(),
+            SerializableMessage::from(message).content.to_lowercase(),
+        ])?;
+    }
+    Ok(())
+}
+
+/// Revision in the saved metadata, sessions saved before revisions existed are at 0
+fn read_revision(connection: &Connection, session_id: &str) -> Result<Option<u64>> {
+    Ok(connection
+        .query_row(
+            "SELECT json_extract(metadata, '$.revision') FROM sessions WHERE session_id = ?1",
+            [session_id],
+            |row| row.get::<_, Option<u64>>(0),
+        )
+        .optional()?
+        .map(|revision| revision.unwrap_or(0)))
+}
+
+impl SessionStore for SqliteStore {
+    fn load(&self, session_id: &str) -> Result<Option<StoredSession>> {
+        let connection = self.connection.lock().unwrap();
+        let Some((version, metadata, archived)) = connection
+            .query_row(
+                "SELECT version, metadata, archived FROM sessions WHERE session_id = ?1",
+                [session_id],
+                |row| {
+                    Ok((
+                        row.get::<_, u32>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                },
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let mut statement = connection
+            .prepare("SELECT message FROM messages WHERE session_id = ?1 ORDER BY position")?;
+        let messages = statement
+            .query_map([session_id], |row| row.get::<_, String>(0))?
+            .map(|message| Ok(serde_json::from_str(&message?)?))
+            .collect::<Result<Vec<serde_json::Value>>>()
+            .context("Failed to deserialize session messages")?;
+        let archived: Vec<serde_json::Value> =
+            serde_json::from_str(&archived).context("Failed to deserialize archived messages")?;
+
+        Ok(Some(StoredSession {
+            metadata: serde_json::from_str(&metadata)
+                .context("Failed to deserialize session metadata")?,
+            messages: decode_messages(version, &messages)?,
+            archived: decode_messages(version, &archived)?,
+        }))
+    }
+
+    fn save(
+        &self,
+        metadata: &SessionMetadata,
+        messages: &[Message],
+        archived: &[Message],
+    ) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        write_session(&transaction, metadata, messages, archived)?;
+        transaction
+            .commit()
+            .context("Failed to save session to the database")
+    }
+
+    fn save_if(
+        &self,
+        expected: Option<u64>,
+        metadata: &SessionMetadata,
+        messages: &[Message],
+        archived: &[Message],
+    ) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        // IMMEDIATE 事务在读取版本前就拿到写锁, 检查和写入之间不会有其他进程写入
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        check_revision(
+            &metadata.session_id,
+            expected,
+            read_revision(&transaction, &metadata.session_id)?,
+        )?;
+        write_session(&transaction, metadata, messages, archived)?;
+        transaction
+            .commit()
+            .context("Failed to save session to the database")
+    }
+
+    fn revision(&self, session_id: &str) -> Result<Option<u64>> {
+        read_revision(&self.connection.lock().unwrap(), session_id)
+    }
+
+    fn delete(&self, session_id: &str) -> Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        let deleted =
+            connection.execute("DELETE FROM sessions WHERE session_id = ?1", [session_id])?;
+        Ok(deleted > 0)
+    }
+
+    fn exists(&self, session_id: &str) -> Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection
+            .query_row(
+                "SELECT 1 FROM sessions WHERE session_id = ?1",
+                [session_id],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    /// Rows with unreadable metadata are skipped
+    fn list(&self) -> Result<Vec<SessionMetadata>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT metadata FROM sessions ORDER BY last_updated DESC")?;
+        let sessions = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .filter_map(|metadata| serde_json::from_str(&metadata.ok()?).ok())
+            .collect();
+        Ok(sessions)
+    }
+
+    /// Sessions that cannot be read are skipped
+    fn search(&self, query: &SearchQuery) -> Result<Vec<SessionMatch>> {
+        let mut matches = Vec::new();
+        for session_id in self.candidates(query)? {
+            let Ok(Some(session)) = self.load(&session_id) else {
+                continue;
+            };
+            if let Some(session) = query.match_session(&session.metadata, &session.messages) {
+                matches.push(session);
+            }
+        }
+        Ok(matches)
+    }
+}
@@ -0,0 +1,101 @@
+//! Token estimates and model context windows
+//!
+//! Providers tokenize differently and none of their tokenizers is bundled, so
+//! sizes are estimated at about four characters per token. The estimate is only
+//! used to decide how much history fits into the context window.
+
+use rig::completion::message::{AssistantContent, ToolResultContent, UserContent};
+use rig::completion::Message;
+
+/// Context window of models missing from [`context_window`]
+pub const DEFAULT_CONTEXT_WINDOW: usize = 32_000;
+
+/// Rough number of characters per token
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens added by the provider around every message (role, separators)
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Tokens counted for an image, audio or document, whatever its size
+const MEDIA_TOKENS: usize = 1_000;
+
+/// Context window sizes by model name prefix, more specific prefixes first
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-5", 400_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("claude", 200_000),
+    ("deepseek", 128_000),
+    ("command-r", 128_000),
+    ("command-a", 256_000),
+    ("llama3.1", 128_000),
+    ("qwen2.5", 32_768),
+];
+
+/// Context window of `model` in tokens
+///
+/// Unknown models get [`DEFAULT_CONTEXT_WINDOW`], set `context_window` in
+/// `kota.setup` to override it.
+pub fn context_window(model: &str) -> usize {
+    let model = model.to_lowercase();
+    // 去掉 `openai/gpt-4o` 这类路由前缀
+    let name = model.rsplit('/').next().unwrap_or(&model);
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, window)| *window)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+/// Estimated number of tokens of `text`
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Estimated number of tokens `message` takes in a request
+pub fn estimate_message_tokens(message: &Message) -> usize {
+    let content: usize = match message {
+        Message::User { content } => content
+            .iter()
+            .map(|content| match content {
+                UserContent::Text(text) => estimate_tokens(&text.text),
+                UserContent::ToolResult(result) => {
+                    let output: usize = result
+                        .content
+                        .iter()
+                        .map(|content| match content {
+                            ToolResultContent::Text(text) => estimate_tokens(&text.text),
+                            ToolResultContent::Image(_) => MEDIA_TOKENS,
+                        })
+                        .sum();
+                    output + estimate_tokens(&result.id)
+                }
+                _ => MEDIA_TOKENS,
+            })
+            .sum(),
+        Message::Assistant { content, .. } => content
+            .iter()
+            .map(|content| match content {
+                AssistantContent::Text(text) => estimate_tokens(&text.text),
+                AssistantContent::ToolCall(call) => {
+                    estimate_tokens(&call.id)
+                        + estimate_tokens(&call.function.name)
+                        + estimate_tokens(&call.function.arguments.to_string())
+                }
+                AssistantContent::Reasoning(reasoning) => reasoning
+                    .reasoning
+                    .iter()
+                    .map(|text| estimate_tokens(text))
+                    .sum(),
+                AssistantContent::Image(_) => MEDIA_TOKENS,
+            })
+            .sum(),
+    };
+    content + MESSAGE_OVERHEAD
+}
@@ -0,0 +1,134 @@
+use super::super::agent::delegate::{SubAgentFactory, SubAgentTask};
+use super::super::agent::TurnCancelled;
+use super::FileToolError;
+use colored::*;
+use rig::{completion::ToolDefinition, tool::Tool};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct DelegateTaskArgs {
+    pub task: String,
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+    #[serde(default)]
+    pub skill: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DelegateTaskOutput {
+    pub summary: String,
+    pub session_id: Option<String>,
+}
+
+pub struct DelegateTaskTool {
+    factory: SubAgentFactory,
+}
+
+impl DelegateTaskTool {
+    pub fn new(factory: SubAgentFactory) -> Self {
+        Self { factory }
+    }
+}
+
+impl Tool for DelegateTaskTool {
+    const NAME: &'static str = "delegate_task";
+    type Error = FileToolError;
+    type Args = DelegateTaskArgs;
+    type Output = DelegateTaskOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "delegate_task".to_string(),
+            description: "Delegate a self-contained task to a sub-agent with a fresh conversation. The sub-agent works on its own and only its final summary is returned, which keeps exploration out of this conversation.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "task": {
+                        "type": "string",
+                        "description": "Complete description of the task, the sub-agent cannot see this conversation"
+                    },
+                    "tools": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Names of the tools the sub-agent may use (optional, defaults to all built-in tools)"
+                    },
+                    "skill": {
+                        "type": "string",
+                        "description": "Skill to activate for the sub-agent (optional)"
+                    }
+                },
+                "required": ["task"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        if args.task.trim().is_empty() {
+            return Err(FileToolError::InvalidInput(
+                "Task must not be empty".to_string(),
+            ));
+        }
+
+        let task = SubAgentTask {
+            task: args.task,
+            tools: args.tools,
+            skill: args.skill,
+        };
+        match self.factory.run(&task).await {
+            Ok(result) => Ok(DelegateTaskOutput {
+                summary: result.summary,
+                session_id: result.session_id,
+            }),
+            Err(e) if e.is::<TurnCancelled>() => Err(FileToolError::Cancelled(
+                "Sub-agent was cancelled".to_string(),
+            )),
+            Err(e) => Err(FileToolError::SubAgent(format!("{:#}", e))),
+        }
+    }
+}
+
+pub struct WrappedDelegateTaskTool {
+    inner: DelegateTaskTool,
+}
+
+impl WrappedDelegateTaskTool {
+    pub fn new(factory: SubAgentFactory) -> Self {
+        Self {
+            inner: DelegateTaskTool::new(factory),
+        }
+    }
+}
+
+impl Tool for WrappedDelegateTaskTool {
+    const NAME: &'static str = "delegate_task";
+    type Error = FileToolError;
+    type Args = <DelegateTaskTool as Tool>::Args;
+    type Output = <DelegateTaskTool as Tool>::Output;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let first_line = args.task.lines().next().unwrap_or_default();
+        println!("\n{} Delegate({})", "●".bright_magenta(), first_line);
+
+        let result = self.inner.call(args).await;
+
+        match &result {
+            Ok(output) => match &output.session_id {
+                Some(session_id) => println!(
+                    "  └─ {} (session: {})",
+                    "Sub-agent finished".dimmed(),
+                    session_id.bright_cyan()
+                ),
+                None => println!("  └─ {}", "Sub-agent finished".dimmed()),
+            },
+            Err(e) => {
+                println!("  └─ {}", format!("Error: {}", e).red());
+            }
+        }
+        println!();
+        result
+    }
+}
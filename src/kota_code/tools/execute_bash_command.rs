@@ -1,8 +1,10 @@
 use super::FileToolError;
+use crate::kota_code::runtime::CancelSignal;
 use colored::*;
 use rig::{completion::ToolDefinition, tool::Tool};
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use std::process::Stdio;
+use tokio::process::Command;
 
 #[derive(Deserialize)]
 pub struct ExecuteBashCommandArgs {
@@ -19,7 +21,17 @@ pub struct ExecuteBashCommandOutput {
 }
 
 #[derive(Deserialize, Serialize, Default)]
-pub struct ExecuteBashCommandTool;
+pub struct ExecuteBashCommandTool {
+    /// Kills the running command when the turn is cancelled
+    #[serde(skip)]
+    cancel: CancelSignal,
+}
+
+impl ExecuteBashCommandTool {
+    pub fn new(cancel: CancelSignal) -> Self {
+        Self { cancel }
+    }
+}
 
 impl Tool for ExecuteBashCommandTool {
     const NAME: &'static str = "exec_cmd";
@@ -49,41 +61,68 @@ impl Tool for ExecuteBashCommandTool {
         let command = &args.command;
 
         // Execute the command using cmd on Windows or bash on Unix
-        let output = if cfg!(target_os = "windows") {
-            Command::new("cmd").args(["/C", command]).output()
+        let mut cmd = if cfg!(target_os = "windows") {
+            let mut cmd = Command::new("cmd");
+            cmd.args(["/C", command]);
+            cmd
         } else {
-            Command::new("bash").args(["-c", command]).output()
+            let mut cmd = Command::new("bash");
+            cmd.args(["-c", command]);
+            cmd
         };
-
-        match output {
-            Ok(output) => {
-                let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                let success = output.status.success();
-                let exit_code = output.status.code();
-
-                Ok(ExecuteBashCommandOutput {
-                    command: command.clone(),
-                    success,
-                    stdout,
-                    stderr,
-                    exit_code,
-                })
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // 放到独立的进程组, 取消时连同子进程一起结束
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let token = self.cancel.token();
+        let child = cmd.spawn().map_err(FileToolError::Io)?;
+        let pid = child.id();
+
+        let output = tokio::select! {
+            output = child.wait_with_output() => output.map_err(FileToolError::Io)?,
+            _ = token.cancelled() => {
+                // Dropping the output future kills bash, the group kill takes its children
+                kill_process_group(pid);
+                return Err(FileToolError::Cancelled(command.clone()));
             }
-            Err(e) => Err(FileToolError::Io(e)),
+        };
+
+        Ok(ExecuteBashCommandOutput {
+            command: command.clone(),
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            exit_code: output.status.code(),
+        })
+    }
+}
+
+/// Kill every process in the group led by `pid`
+#[cfg(unix)]
+pub(crate) fn kill_process_group(pid: Option<u32>) {
+    if let Some(pid) = pid {
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGKILL);
         }
     }
 }
 
+#[cfg(not(unix))]
+pub(crate) fn kill_process_group(_pid: Option<u32>) {}
+
 #[derive(Deserialize, Serialize, Default)]
 pub struct WrappedExecuteBashCommandTool {
     inner: ExecuteBashCommandTool,
 }
 
 impl WrappedExecuteBashCommandTool {
-    pub fn new() -> Self {
+    pub fn new(cancel: CancelSignal) -> Self {
         Self {
-            inner: ExecuteBashCommandTool,
+            inner: ExecuteBashCommandTool::new(cancel),
         }
     }
 }
@@ -12,24 +12,38 @@ pub enum FileToolError {
     NotAFile(String),
     #[error("Input is invalid: {0}")]
     InvalidInput(String),
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+    #[error("Sub-agent failed: {0}")]
+    SubAgent(String),
 }
 
 pub mod create_directory;
+pub mod delegate_task;
 pub mod delete_file;
 pub mod edit_file;
 pub mod execute_bash_command;
 pub mod grep_search;
 pub mod read_file;
+pub mod read_skill_resource;
+pub mod remember;
 pub mod scan_codebase;
+pub mod skill_script;
+pub mod snapshot;
 pub mod update_plan;
 pub mod write_file;
 
 pub use create_directory::WrappedCreateDirectoryTool;
+pub use delegate_task::WrappedDelegateTaskTool;
 pub use delete_file::WrappedDeleteFileTool;
 pub use edit_file::WrappedEditFileTool;
 pub use execute_bash_command::WrappedExecuteBashCommandTool;
 pub use grep_search::WrappedGrepSearchTool;
 pub use read_file::WrappedReadFileTool;
+pub use read_skill_resource::WrappedReadSkillResourceTool;
+pub use remember::WrappedRememberTool;
 pub use scan_codebase::WrappedScanCodebaseTool;
+pub use skill_script::SkillScriptTool;
+pub use snapshot::{ChangedPath, SnapshotPath, SnapshotTool};
 pub use update_plan::WrappedUpdatePlanTool;
 pub use write_file::WrappedWriteFileTool;
@@ -0,0 +1,151 @@
+use super::FileToolError;
+use crate::kota_code::skills::Skill;
+use colored::*;
+use rig::{completion::ToolDefinition, tool::Tool};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+/// Skills the tool reads from, replaced by the agent when the skills are reloaded
+pub type SharedSkills = Arc<RwLock<Vec<Skill>>>;
+
+#[derive(Deserialize)]
+pub struct ReadSkillResourceArgs {
+    pub skill: String,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReadSkillResourceOutput {
+    pub skill: String,
+    pub path: Option<String>,
+    /// Content of the resource, `None` when listing
+    pub content: Option<String>,
+    /// Resources of the skill, filled when no path was given
+    pub resources: Vec<String>,
+}
+
+/// Reads the files shipped next to a skill's SKILL.md
+pub struct ReadSkillResourceTool {
+    skills: SharedSkills,
+}
+
+impl ReadSkillResourceTool {
+    pub fn new(skills: SharedSkills) -> Self {
+        Self { skills }
+    }
+}
+
+impl Tool for ReadSkillResourceTool {
+    const NAME: &'static str = "read_skill_resource";
+
+    type Error = FileToolError;
+    type Args = ReadSkillResourceArgs;
+    type Output = ReadSkillResourceOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "read_skill_resource".to_string(),
+            description: "Read a reference file, template or script shipped with a skill. Call it without a path to list the skill's resources.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "skill": {
+                        "type": "string",
+                        "description": "Name of the skill"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Path relative to the skill directory, e.g. 'REFERENCE.md' or 'templates/report.md'"
+                    }
+                },
+                "required": ["skill"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let skill = self
+            .skills
+            .read()
+            .unwrap()
+            .iter()
+            .find(|skill| skill.name == args.skill)
+            .cloned()
+            .ok_or_else(|| {
+                FileToolError::InvalidInput(format!("Skill '{}' not found", args.skill))
+            })?;
+
+        let Some(path) = args.path.filter(|path| !path.trim().is_empty()) else {
+            return Ok(ReadSkillResourceOutput {
+                skill: skill.name.clone(),
+                path: None,
+                content: None,
+                resources: skill.resources(),
+            });
+        };
+
+        let content = skill
+            .read_resource(&path)
+            .map_err(|e| FileToolError::InvalidInput(format!("{:#}", e)))?;
+        Ok(ReadSkillResourceOutput {
+            skill: skill.name.clone(),
+            path: Some(path),
+            content: Some(content),
+            resources: Vec::new(),
+        })
+    }
+}
+
+pub struct WrappedReadSkillResourceTool {
+    inner: ReadSkillResourceTool,
+}
+
+impl WrappedReadSkillResourceTool {
+    pub fn new(skills: SharedSkills) -> Self {
+        Self {
+            inner: ReadSkillResourceTool::new(skills),
+        }
+    }
+}
+
+impl Tool for WrappedReadSkillResourceTool {
+    const NAME: &'static str = "read_skill_resource";
+
+    type Error = FileToolError;
+    type Args = <ReadSkillResourceTool as Tool>::Args;
+    type Output = <ReadSkillResourceTool as Tool>::Output;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        println!(
+            "\n{} SkillResource({}/{})",
+            "●".bright_green(),
+            args.skill,
+            args.path.as_deref().unwrap_or("")
+        );
+
+        let result = self.inner.call(args).await;
+
+        match &result {
+            Ok(output) => match &output.content {
+                Some(content) => println!(
+                    "  └─ {}",
+                    format!("Read {} lines", content.lines().count()).dimmed()
+                ),
+                None => println!(
+                    "  └─ {}",
+                    format!("{} resources", output.resources.len()).dimmed()
+                ),
+            },
+            Err(e) => {
+                println!("  └─ {}", format!("Error: {}", e).red());
+            }
+        }
+        println!();
+        result
+    }
+}
@@ -0,0 +1,148 @@
+use super::super::memory::append_fact;
+use super::snapshot::SnapshotPath;
+use super::FileToolError;
+use colored::*;
+use rig::{completion::ToolDefinition, tool::Tool};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Deserialize)]
+pub struct RememberArgs {
+    pub fact: String,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RememberOutput {
+    pub success: bool,
+    pub message: String,
+    pub path: String,
+}
+
+pub struct RememberTool {
+    project_file: PathBuf,
+    global_file: Option<PathBuf>,
+}
+
+impl RememberTool {
+    pub fn new(project_file: PathBuf, global_file: Option<PathBuf>) -> Self {
+        Self {
+            project_file,
+            global_file,
+        }
+    }
+
+    /// `KOTA.md` the fact is written to for `scope`
+    fn target(&self, scope: Option<&str>) -> Result<PathBuf, FileToolError> {
+        match scope.unwrap_or("project") {
+            "project" => Ok(self.project_file.clone()),
+            "global" => self.global_file.clone().ok_or_else(|| {
+                FileToolError::InvalidInput("Home directory is unknown".to_string())
+            }),
+            other => Err(FileToolError::InvalidInput(format!(
+                "Invalid scope: {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl Tool for RememberTool {
+    const NAME: &'static str = "remember";
+    type Error = FileToolError;
+    type Args = RememberArgs;
+    type Output = RememberOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "remember".to_string(),
+            description: "Save a durable fact or convention to KOTA.md so it is part of every future conversation. Use it for stable project knowledge, not for temporary task state.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "fact": {
+                        "type": "string",
+                        "description": "The fact to remember, as one self-contained sentence"
+                    },
+                    "scope": {
+                        "type": "string",
+                        "enum": ["project", "global"],
+                        "description": "project (default) saves to the project's KOTA.md, global to ~/.kota/KOTA.md"
+                    }
+                },
+                "required": ["fact"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        if args.fact.trim().is_empty() {
+            return Err(FileToolError::InvalidInput(
+                "Fact must not be empty".to_string(),
+            ));
+        }
+        let path = self.target(args.scope.as_deref())?;
+
+        append_fact(&path, &args.fact)
+            .map_err(|e| FileToolError::Io(std::io::Error::other(format!("{:#}", e))))?;
+
+        Ok(RememberOutput {
+            success: true,
+            message: format!("Remembered in {}", path.display()),
+            path: path.to_string_lossy().to_string(),
+        })
+    }
+}
+
+impl SnapshotPath for RememberTool {
+    fn snapshot_path(&self, args: &Self::Args) -> Option<PathBuf> {
+        self.target(args.scope.as_deref()).ok()
+    }
+}
+
+pub struct WrappedRememberTool {
+    inner: RememberTool,
+}
+
+impl WrappedRememberTool {
+    pub fn new(project_file: PathBuf, global_file: Option<PathBuf>) -> Self {
+        Self {
+            inner: RememberTool::new(project_file, global_file),
+        }
+    }
+}
+
+impl Tool for WrappedRememberTool {
+    const NAME: &'static str = "remember";
+    type Error = FileToolError;
+    type Args = <RememberTool as Tool>::Args;
+    type Output = <RememberTool as Tool>::Output;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        println!("\n{} Remember({})", "●".bright_blue(), args.fact.trim());
+
+        let result = self.inner.call(args).await;
+
+        match &result {
+            Ok(output) => {
+                println!("  └─ {}", output.message.dimmed());
+            }
+            Err(e) => {
+                println!("  └─ {}", format!("Error: {}", e).red());
+            }
+        }
+        println!();
+        result
+    }
+}
+
+impl SnapshotPath for WrappedRememberTool {
+    fn snapshot_path(&self, args: &Self::Args) -> Option<PathBuf> {
+        self.inner.snapshot_path(args)
+    }
+}
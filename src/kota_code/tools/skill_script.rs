@@ -0,0 +1,204 @@
+use super::execute_bash_command::{kill_process_group, ExecuteBashCommandOutput};
+use super::FileToolError;
+use crate::kota_code::runtime::CancelSignal;
+use crate::kota_code::skills::SkillScript;
+use colored::*;
+use rig::{completion::ToolDefinition, tool::ToolDyn};
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+#[derive(Deserialize, Default)]
+pub struct SkillScriptArgs {
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub stdin: Option<String>,
+}
+
+/// Runs a script from a skill's `scripts/` directory
+///
+/// The script runs in the current directory with `KOTA_SKILL_DIR` set to the
+/// skill directory. Its interpreter is chosen from the file extension.
+pub struct SkillScriptTool {
+    script: SkillScript,
+    skill_dir: PathBuf,
+    cancel: CancelSignal,
+    progress: bool,
+}
+
+impl SkillScriptTool {
+    pub fn new(script: SkillScript, skill_dir: PathBuf, cancel: CancelSignal) -> Self {
+        Self {
+            script,
+            skill_dir,
+            cancel,
+            progress: false,
+        }
+    }
+
+    /// Print the progress of the script to the terminal
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    async fn run(&self, args: SkillScriptArgs) -> Result<ExecuteBashCommandOutput, FileToolError> {
+        let mut cmd = interpreter_command(&self.script.path);
+        cmd.args(&args.args)
+            .env("KOTA_SKILL_DIR", &self.skill_dir)
+            .stdin(if args.stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let command = format!("{} {}", self.script.path.display(), args.args.join(" "))
+            .trim_end()
+            .to_string();
+        let token = self.cancel.token();
+        let mut child = cmd.spawn().map_err(FileToolError::Io)?;
+        let pid = child.id();
+        // 在单独的任务中写入输入, 脚本先写满输出管道再读输入时不会死锁
+        let writer = match (args.stdin, child.stdin.take()) {
+            (Some(input), Some(mut stdin)) => Some(tokio::spawn(async move {
+                match stdin.write_all(input.as_bytes()).await {
+                    // 脚本没有读完输入就退出
+                    Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+                    result => result,
+                }
+            })),
+            _ => None,
+        };
+
+        let output = tokio::select! {
+            output = child.wait_with_output() => output.map_err(FileToolError::Io)?,
+            _ = token.cancelled() => {
+                kill_process_group(pid);
+                return Err(FileToolError::Cancelled(command));
+            }
+        };
+        if let Some(writer) = writer {
+            // 脚本的子进程可能仍持有 stdin, 不再等待写入
+            if !writer.is_finished() {
+                writer.abort();
+            } else if let Ok(Err(e)) = writer.await {
+                return Err(FileToolError::Io(e));
+            }
+        }
+
+        Ok(ExecuteBashCommandOutput {
+            command,
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            exit_code: output.status.code(),
+        })
+    }
+}
+
+/// Command running `path` with the interpreter matching its extension
+fn interpreter_command(path: &Path) -> Command {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let interpreter: Option<&[&str]> = match extension.as_str() {
+        "sh" | "bash" => Some(&["bash"]),
+        "py" if cfg!(target_os = "windows") => Some(&["python"]),
+        "py" => Some(&["python3"]),
+        "js" | "mjs" => Some(&["node"]),
+        "rb" => Some(&["ruby"]),
+        "lua" => Some(&["lua"]),
+        "ps1" => Some(&["powershell", "-ExecutionPolicy", "Bypass", "-File"]),
+        _ => None,
+    };
+    match interpreter {
+        Some([program, rest @ ..]) => {
+            let mut cmd = Command::new(program);
+            cmd.args(rest).arg(path);
+            cmd
+        }
+        _ => Command::new(path),
+    }
+}
+
+impl ToolDyn for SkillScriptTool {
+    fn name(&self) -> String {
+        self.script.tool_name.clone()
+    }
+
+    fn definition<'a>(
+        &'a self,
+        _prompt: String,
+    ) -> rig::wasm_compat::WasmBoxedFuture<'a, ToolDefinition> {
+        Box::pin(async move {
+            ToolDefinition {
+                name: self.script.tool_name.clone(),
+                description: self.script.description.clone(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "args": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Command line arguments passed to the script"
+                        },
+                        "stdin": {
+                            "type": "string",
+                            "description": "Text written to the script's standard input"
+                        }
+                    }
+                }),
+            }
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: String,
+    ) -> rig::wasm_compat::WasmBoxedFuture<'a, Result<String, rig::tool::ToolError>> {
+        Box::pin(async move {
+            let args: SkillScriptArgs = if args.trim().is_empty() {
+                SkillScriptArgs::default()
+            } else {
+                serde_json::from_str(&args).map_err(rig::tool::ToolError::JsonError)?
+            };
+            if self.progress {
+                println!(
+                    "\n{} Script({} {})",
+                    "●".bright_green(),
+                    self.script.tool_name,
+                    args.args.join(" ")
+                );
+            }
+
+            let result = self.run(args).await;
+
+            if self.progress {
+                match &result {
+                    Ok(output) if output.success => {
+                        println!("  └─ {}", "Script succeeded".dimmed())
+                    }
+                    Ok(output) => println!(
+                        "  └─ {}",
+                        format!("Script failed (exit: {})", output.exit_code.unwrap_or(-1)).red()
+                    ),
+                    Err(e) => println!("  └─ {}", format!("Error: {}", e).red()),
+                }
+                println!();
+            }
+
+            let output = result.map_err(|e| rig::tool::ToolError::ToolCallError(Box::new(e)))?;
+            serde_json::to_string(&output).map_err(rig::tool::ToolError::JsonError)
+        })
+    }
+}
@@ -0,0 +1,89 @@
+use super::super::file_history::FileHistory;
+use super::create_directory::CreateDirectoryArgs;
+use super::delete_file::DeleteFileArgs;
+use super::edit_file::EditFileArgs;
+use super::write_file::WriteFileArgs;
+use rig::{completion::ToolDefinition, tool::Tool};
+use std::path::{Path, PathBuf};
+
+/// Arguments of a tool that changes one path
+pub trait ChangedPath {
+    fn changed_path(&self) -> &Path;
+}
+
+impl ChangedPath for WriteFileArgs {
+    fn changed_path(&self) -> &Path {
+        Path::new(&self.file_path)
+    }
+}
+
+impl ChangedPath for EditFileArgs {
+    fn changed_path(&self) -> &Path {
+        Path::new(&self.file_path)
+    }
+}
+
+impl ChangedPath for DeleteFileArgs {
+    fn changed_path(&self) -> &Path {
+        Path::new(&self.file_path)
+    }
+}
+
+impl ChangedPath for CreateDirectoryArgs {
+    fn changed_path(&self) -> &Path {
+        Path::new(&self.dir_path)
+    }
+}
+
+/// A tool that changes at most one path per call
+///
+/// Implemented for every tool whose arguments name the path. Tools that work out
+/// the path themselves implement it directly.
+pub trait SnapshotPath: Tool {
+    /// Path the call is about to change, `None` when the call will fail anyway
+    fn snapshot_path(&self, args: &Self::Args) -> Option<PathBuf>;
+}
+
+impl<T> SnapshotPath for T
+where
+    T: Tool,
+    T::Args: ChangedPath,
+{
+    fn snapshot_path(&self, args: &Self::Args) -> Option<PathBuf> {
+        Some(args.changed_path().to_path_buf())
+    }
+}
+
+/// Records the path a file tool is about to change in a [`FileHistory`]
+pub struct SnapshotTool<T> {
+    inner: T,
+    history: FileHistory,
+}
+
+impl<T> SnapshotTool<T> {
+    pub fn new(inner: T, history: FileHistory) -> Self {
+        Self { inner, history }
+    }
+}
+
+impl<T> Tool for SnapshotTool<T>
+where
+    T: SnapshotPath,
+{
+    const NAME: &'static str = T::NAME;
+
+    type Error = T::Error;
+    type Args = T::Args;
+    type Output = T::Output;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        if let Some(path) = self.inner.snapshot_path(&args) {
+            self.history.record(&path);
+        }
+        self.inner.call(args).await
+    }
+}
@@ -0,0 +1,223 @@
+use rig::completion::Usage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::iter::Sum;
+use std::ops::AddAssign;
+
+/// Token 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Input tokens served from the provider's prompt cache, included in `input_tokens`
+    #[serde(default)]
+    pub cached_input_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// Usage left after removing `other`, never below zero
+    pub fn saturating_sub(&self, other: &TokenUsage) -> TokenUsage {
+        TokenUsage {
+            input_tokens: self.input_tokens.saturating_sub(other.input_tokens),
+            output_tokens: self.output_tokens.saturating_sub(other.output_tokens),
+            cached_input_tokens: self
+                .cached_input_tokens
+                .saturating_sub(other.cached_input_tokens),
+        }
+    }
+}
+
+impl From<Usage> for TokenUsage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cached_input_tokens: usage.cached_input_tokens,
+        }
+    }
+}
+
+impl Sum for TokenUsage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(TokenUsage::default(), |mut total, usage| {
+            total += usage;
+            total
+        })
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cached_input_tokens += other.cached_input_tokens;
+    }
+}
+
+/// 单轮对话的用量
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TurnUsage {
+    pub model: String,
+    pub usage: TokenUsage,
+    pub timestamp: String,
+}
+
+/// 会话累计用量
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub total: TokenUsage,
+    #[serde(default)]
+    pub turns: Vec<TurnUsage>,
+}
+
+impl SessionUsage {
+    /// Record the usage of one turn
+    pub fn record(&mut self, model: &str, usage: TokenUsage) {
+        self.total += usage;
+        self.turns.push(TurnUsage {
+            model: model.to_string(),
+            usage,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        });
+    }
+
+    /// Estimated cost in USD, `None` if a turn used a model without a price
+    pub fn cost(&self, prices: &PriceTable) -> Option<f64> {
+        self.turns
+            .iter()
+            .map(|turn| prices.cost(&turn.model, &turn.usage))
+            .sum()
+    }
+}
+
+/// Price of a model in USD per million tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Price of cached input tokens, defaults to the input price
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+}
+
+/// Per-model price table, configured with `prices` in `kota.setup`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PriceTable {
+    pub models: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn insert(&mut self, model: impl Into<String>, price: ModelPrice) {
+        self.models.insert(model.into(), price);
+    }
+
+    pub fn get(&self, model: &str) -> Option<&ModelPrice> {
+        self.models.get(model)
+    }
+
+    /// Cost of `usage` on `model` in USD, `None` if the model has no price
+    pub fn cost(&self, model: &str, usage: &TokenUsage) -> Option<f64> {
+        let price = self.get(model)?;
+        let cached = usage.cached_input_tokens.min(usage.input_tokens);
+        let uncached = usage.input_tokens - cached;
+        let per_token =
+            |price_per_million: f64, tokens: u64| price_per_million * tokens as f64 / 1e6;
+        Some(
+            per_token(price.input, uncached)
+                + per_token(price.cached_input.unwrap_or(price.input), cached)
+                + per_token(price.output, usage.output_tokens),
+        )
+    }
+}
+
+/// Spending limits for one session, configured with `budget` in `kota.setup`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    /// Maximum estimated cost in USD
+    pub max_cost: Option<f64>,
+    /// Maximum total tokens
+    pub max_tokens: Option<u64>,
+    /// Fraction of a limit at which to start warning
+    pub warn_at: f64,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            max_cost: None,
+            max_tokens: None,
+            warn_at: 0.8,
+        }
+    }
+}
+
+/// Where a session stands relative to its [`Budget`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetStatus {
+    /// No budget or well below it
+    Ok,
+    /// `warn_at` of a limit has been used, `used` is the highest fraction
+    Warning { used: f64 },
+    /// A limit has been reached, new turns are refused
+    Exceeded { used: f64 },
+}
+
+impl Budget {
+    /// Compare the session usage against the limits
+    pub fn status(&self, usage: &SessionUsage, prices: &PriceTable) -> BudgetStatus {
+        let mut used: f64 = 0.0;
+        if let Some(max_tokens) = self.max_tokens {
+            let tokens = usage.total.total_tokens() as f64;
+            used = used.max(if max_tokens > 0 {
+                tokens / max_tokens as f64
+            } else {
+                1.0
+            });
+        }
+        if let Some(max_cost) = self.max_cost {
+            // Turns on unpriced models count as free
+            let cost: f64 = usage
+                .turns
+                .iter()
+                .filter_map(|turn| prices.cost(&turn.model, &turn.usage))
+                .sum();
+            used = used.max(if max_cost > 0.0 { cost / max_cost } else { 1.0 });
+        }
+
+        if used >= 1.0 {
+            BudgetStatus::Exceeded { used }
+        } else if used >= self.warn_at {
+            BudgetStatus::Warning { used }
+        } else {
+            BudgetStatus::Ok
+        }
+    }
+
+    /// Limits left after `usage`, handed to sub-agents so they share the session budget
+    pub fn remaining(&self, usage: &SessionUsage, prices: &PriceTable) -> Budget {
+        let cost: f64 = usage
+            .turns
+            .iter()
+            .filter_map(|turn| prices.cost(&turn.model, &turn.usage))
+            .sum();
+        Budget {
+            max_cost: self.max_cost.map(|max_cost| (max_cost - cost).max(0.0)),
+            max_tokens: self
+                .max_tokens
+                .map(|max_tokens| max_tokens.saturating_sub(usage.total.total_tokens())),
+            warn_at: self.warn_at,
+        }
+    }
+}
+
+/// Error returned when a new turn is refused because the session budget is used up
+#[derive(Debug, thiserror::Error)]
+#[error("Session budget exceeded ({:.0}% used); start a new session or raise `budget` in kota.setup", .used * 100.0)]
+pub struct BudgetExceeded {
+    pub used: f64,
+}
@@ -12,9 +12,11 @@ pub mod kota_code;
 
 // Re-export commonly used types for convenience
 pub use kota_code::{
-    create_agent, AgentBuilder, AgentInstance, AgentType, CommandDef, ContextManager, KotaConfig,
-    McpClient, McpManager, Plan, PlanManager, Provider, SerializableMessage, SessionIdHook,
-    SessionMetadata, Skill, SkillManager, Task, TaskStatus, ToolRegistry,
+    create_agent, AgentBuilder, AgentEvent, AgentEventStream, AgentHook, AgentInstance, AgentType,
+    Budget, BudgetExceeded, BudgetStatus, CancelSignal, CommandDef, ContextManager,
+    GenerationSettings, KotaConfig, McpClient, McpManager, ModelPrice, Plan, PlanManager,
+    PriceTable, ProjectMemory, Provider, SerializableMessage, SessionIdHook, SessionMetadata,
+    SessionUsage, Skill, SkillManager, Task, TaskStatus, TokenUsage, ToolRegistry, TurnCancelled,
 };
 
 // Re-export CLI components for testing
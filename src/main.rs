@@ -1,8 +1,10 @@
 use anyhow::{Ok, Result};
 use colored::Colorize;
-use kota::{CommandRegistry, ContextManager, KotaConfig, SkillManager};
+use kota::{AgentBuilder, CommandRegistry, ContextManager, KotaConfig, SkillManager};
 use names::Generator;
+use std::io::Read;
 
+use kota::kota_cli::headless::{self, CliArgs};
 use kota::kota_cli::KotaCli;
 
 fn load_session_id() -> String {
@@ -14,7 +16,7 @@ fn load_session_id() -> String {
 
 fn load_command_registry(config: &KotaConfig) -> Option<CommandRegistry> {
     if !config.commands.is_empty() {
-        match CommandRegistry::new(&config) {
+        match CommandRegistry::new(config) {
             std::result::Result::Ok(registry) => {
                 println!(
                     "{} {} custom commands loaded",
@@ -37,8 +39,77 @@ fn load_command_registry(config: &KotaConfig) -> Option<CommandRegistry> {
     }
 }
 
+/// Run a single prompt without the REPL and return the process exit code
+async fn run_headless(args: CliArgs) -> Result<i32> {
+    let prompt = match args.prompt {
+        Some(prompt) => prompt,
+        None => {
+            let mut prompt = String::new();
+            std::io::stdin().read_to_string(&mut prompt)?;
+            prompt
+        }
+    };
+    if prompt.trim().is_empty() {
+        eprintln!("Error: no prompt given on the command line or stdin");
+        return Ok(headless::EXIT_USAGE);
+    }
+
+    let config = KotaConfig::load()?;
+    let context = ContextManager::from_store(config.session_store.open()?, load_session_id())
+        .with_context_window(config.context_window())
+        .with_compaction(config.compaction);
+    // stdout 只输出结果, 关闭工具的进度打印
+    let mut agent = AgentBuilder::from_config(&config)?
+        .with_context(context)
+        .with_skill_manager(
+            SkillManager::new()
+                .with_skill_dirs(config.skill_dirs.clone())
+                .with_auto_select(config.auto_skills.clone()),
+        )
+        .with_tool_progress(false)
+        .build()?;
+
+    let cancel = agent.cancel_signal();
+    cancel.reset();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancel.cancel();
+        }
+    });
+
+    let mut stdout = std::io::stdout();
+    let report =
+        headless::run_headless(&mut agent, prompt.trim(), args.output_format, &mut stdout).await?;
+    if let Some(error) = &report.error {
+        eprintln!("Error: {}", error);
+    }
+    Ok(report.exit_code())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = match CliArgs::parse(std::env::args().skip(1)) {
+        std::result::Result::Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, CliArgs::usage());
+            std::process::exit(headless::EXIT_USAGE);
+        }
+    };
+    if args.help {
+        println!("{}", CliArgs::usage());
+        return Ok(());
+    }
+    if args.headless {
+        let code = match run_headless(args).await {
+            std::result::Result::Ok(code) => code,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                headless::EXIT_FAILURE
+            }
+        };
+        std::process::exit(code);
+    }
+
     // Load configuration from .kota/config.lua
     let config = KotaConfig::load()?;
 
@@ -59,16 +130,13 @@ async fn main() -> Result<()> {
     // Initialize command registry if commands are defined
     let command_registry = load_command_registry(&config);
 
-    let context = ContextManager::new("./.chat_sessions", session_id)?.with_max_messages(100);
-    let skill_manager = SkillManager::new();
-    let mut cli = KotaCli::new(
-        config.api_key,
-        config.api_base,
-        config.model,
-        context,
-        skill_manager,
-        command_registry,
-    )?;
+    let context = ContextManager::from_store(config.session_store.open()?, session_id)
+        .with_context_window(config.context_window())
+        .with_compaction(config.compaction);
+    let skill_manager = SkillManager::new()
+        .with_skill_dirs(config.skill_dirs.clone())
+        .with_auto_select(config.auto_skills.clone());
+    let mut cli = KotaCli::new(&config, context, skill_manager, command_registry)?;
     cli.run().await?;
 
     Ok(())
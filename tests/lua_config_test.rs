@@ -1,4 +1,4 @@
-use kota::KotaConfig;
+use kota::{KotaConfig, Provider};
 use std::fs;
 use tempfile::TempDir;
 
@@ -82,3 +82,41 @@ fn test_missing_config_file() {
     // Restore original directory
     std::env::set_current_dir(original_dir).unwrap();
 }
+
+#[test]
+fn test_lua_config_explicit_provider() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.lua");
+    let config_content = r#"
+kota.setup({
+  provider = "openai-compatible",
+  model = "meta-llama/Llama-3.1-70B-Instruct",
+  api_key = "sk-local",
+  api_base = "http://localhost:8000/v1",
+})
+"#;
+
+    fs::write(&config_path, config_content).unwrap();
+
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+
+    assert_eq!(config.provider, Some(Provider::OpenAICompatible));
+    assert_eq!(config.model, "meta-llama/Llama-3.1-70B-Instruct");
+    assert_eq!(config.api_base.as_deref(), Some("http://localhost:8000/v1"));
+}
+
+#[test]
+fn test_lua_config_unknown_provider() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.lua");
+    fs::write(
+        &config_path,
+        r#"kota.setup({ provider = "not-a-provider", model = "gpt-4o" })"#,
+    )
+    .unwrap();
+
+    let result = KotaConfig::from_lua_file(&config_path);
+
+    assert!(result.is_err());
+    assert!(result.unwrap_err().to_string().contains("Unknown provider"));
+}
@@ -0,0 +1,94 @@
+use kota::{AgentBuilder, AgentType, KotaConfig, Provider};
+
+#[tokio::test]
+async fn test_openai_compatible_accepts_any_model_name() {
+    let config = KotaConfig {
+        provider: Some(Provider::OpenAICompatible),
+        model: "Qwen/Qwen2.5-Coder-32B-Instruct".to_string(),
+        api_key: "sk-local".to_string(),
+        api_base: Some("http://localhost:8000/v1".to_string()),
+        ..KotaConfig::default()
+    };
+
+    let instance = AgentBuilder::from_config(&config).unwrap().build().unwrap();
+
+    assert!(matches!(instance.agent, AgentType::OpenAICompatible(_)));
+}
+
+#[tokio::test]
+async fn test_openai_compatible_requires_api_base() {
+    let result = AgentBuilder::for_provider(
+        Provider::OpenAICompatible,
+        "sk-local".to_string(),
+        "llama3".to_string(),
+    )
+    .build();
+
+    assert!(result.is_err());
+
+    let config = KotaConfig {
+        provider: Some(Provider::OpenAICompatible),
+        model: "llama3".to_string(),
+        api_key: "sk-local".to_string(),
+        ..KotaConfig::default()
+    };
+    let error = AgentBuilder::from_config(&config)
+        .unwrap()
+        .build()
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("api_base is required"));
+}
+
+#[tokio::test]
+async fn test_provider_inferred_from_model_without_explicit_provider() {
+    let config = KotaConfig {
+        model: "claude-3-5-sonnet".to_string(),
+        api_key: "test".to_string(),
+        ..KotaConfig::default()
+    };
+
+    let instance = AgentBuilder::from_config(&config).unwrap().build().unwrap();
+
+    assert!(matches!(instance.agent, AgentType::Anthropic(_)));
+}
+
+#[tokio::test]
+async fn test_unknown_model_without_provider_fails() {
+    let config = KotaConfig {
+        model: "llama3".to_string(),
+        ..KotaConfig::default()
+    };
+
+    assert!(AgentBuilder::from_config(&config).is_err());
+}
+
+#[tokio::test]
+async fn test_explicit_ollama_accepts_unprefixed_model() {
+    let config = KotaConfig {
+        provider: Some(Provider::Ollama),
+        model: "llama3".to_string(),
+        ..KotaConfig::default()
+    };
+
+    let instance = AgentBuilder::from_config(&config).unwrap().build().unwrap();
+
+    assert!(matches!(instance.agent, AgentType::Ollama(_)));
+}
+
+#[tokio::test]
+async fn test_explicit_deepseek_uses_configured_model() {
+    let config = KotaConfig {
+        provider: Some(Provider::DeepSeek),
+        model: "deepseek-reasoner".to_string(),
+        api_key: "test".to_string(),
+        ..KotaConfig::default()
+    };
+
+    let instance = AgentBuilder::from_config(&config).unwrap().build().unwrap();
+
+    match instance.agent {
+        AgentType::DeepSeek(agent) => assert_eq!(agent.model.model, "deepseek-reasoner"),
+        _ => panic!("expected a DeepSeek agent"),
+    }
+}
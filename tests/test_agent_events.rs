@@ -0,0 +1,172 @@
+use futures::StreamExt;
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn, MockUsage};
+use kota::{AgentBuilder, AgentEvent, Provider, SessionIdHook};
+use serde_json::json;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_stream_events_reports_tool_loop() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("notes.txt");
+
+    let script = MockScript::new(vec![
+        MockTurn {
+            tool_calls: vec![MockToolCall {
+                id: Some("call_1".to_string()),
+                name: "write_file".to_string(),
+                arguments: json!({
+                    "file_path": file_path.to_string_lossy(),
+                    "content": "remember this"
+                }),
+                expect_result: None,
+            }],
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("All done now".to_string()),
+            usage: Some(MockUsage {
+                input_tokens: 12,
+                output_tokens: 3,
+                cached_input_tokens: 0,
+            }),
+            ..MockTurn::default()
+        },
+    ]);
+
+    let agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .build()
+        .unwrap();
+
+    let events: Vec<AgentEvent> = agent
+        .stream_events(
+            "write a note",
+            SessionIdHook::new("test".to_string()),
+            vec![],
+        )
+        .await
+        .unwrap()
+        .map(|event| event.unwrap())
+        .collect()
+        .await;
+
+    assert!(matches!(
+        &events[0],
+        AgentEvent::ToolCallStart { id, name, .. } if id == "call_1" && name == "write_file"
+    ));
+    assert!(matches!(
+        &events[1],
+        AgentEvent::ToolResult { name, content, .. }
+            if name == "write_file" && content.contains("Successfully wrote")
+    ));
+
+    let text: String = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::TextDelta { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(text, "All done now");
+
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, AgentEvent::Usage(usage) if usage.input_tokens == 12)));
+
+    let Some(AgentEvent::FinalResponse(response)) = events.last() else {
+        panic!("stream should end with the final response");
+    };
+    assert_eq!(response.response(), "All done now");
+    assert_eq!(response.usage().output_tokens, 3);
+}
+
+#[tokio::test]
+async fn test_chat_with_events_surfaces_stream_errors() {
+    let script = MockScript::new(vec![
+        MockTurn {
+            tool_calls: vec![MockToolCall {
+                id: None,
+                name: "read_file".to_string(),
+                arguments: json!({ "file_path": "definitely/missing/file.txt" }),
+                expect_result: Some("this text is never returned".to_string()),
+            }],
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("unreachable".to_string()),
+            ..MockTurn::default()
+        },
+    ]);
+
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .build()
+        .unwrap();
+
+    let mut seen = Vec::new();
+    let result = agent
+        .chat_with_events("read it", |event| seen.push(event.clone()))
+        .await;
+
+    assert!(result.is_err());
+    assert!(seen.iter().any(
+        |event| matches!(event, AgentEvent::ToolCallStart { name, .. } if name == "read_file")
+    ));
+}
+
+#[tokio::test]
+async fn test_tool_result_error_flag_comes_from_the_tool_call() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("notes.txt");
+
+    let script = MockScript::new(vec![
+        MockTurn {
+            tool_calls: vec![
+                MockToolCall {
+                    id: Some("call_1".to_string()),
+                    name: "no_such_tool".to_string(),
+                    arguments: json!({}),
+                    expect_result: None,
+                },
+                MockToolCall {
+                    id: Some("call_2".to_string()),
+                    name: "write_file".to_string(),
+                    arguments: json!({
+                        "file_path": file_path.to_string_lossy(),
+                        "content": ""
+                    }),
+                    expect_result: None,
+                },
+            ],
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("Done".to_string()),
+            ..MockTurn::default()
+        },
+    ]);
+
+    let agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .with_tool_progress(false)
+        .build()
+        .unwrap();
+
+    let results: Vec<(String, bool)> = agent
+        .stream_events("do it", SessionIdHook::new("test".to_string()), vec![])
+        .await
+        .unwrap()
+        .filter_map(|event| async move {
+            match event.unwrap() {
+                AgentEvent::ToolResult { id, is_error, .. } => Some((id, is_error)),
+                _ => None,
+            }
+        })
+        .collect()
+        .await;
+
+    assert_eq!(
+        results,
+        vec![("call_1".to_string(), true), ("call_2".to_string(), false)]
+    );
+}
@@ -0,0 +1,204 @@
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn};
+use kota::kota_code::tools::execute_bash_command::{
+    ExecuteBashCommandArgs, ExecuteBashCommandTool,
+};
+use kota::kota_code::FileToolError;
+use kota::{AgentBuilder, AgentType, CancelSignal, ContextManager, Provider, TurnCancelled};
+use rig::completion::Message;
+use rig::tool::Tool;
+use serde_json::json;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn cancel_after(signal: CancelSignal, delay: Duration) {
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        signal.cancel();
+    });
+}
+
+#[tokio::test]
+async fn test_exec_cmd_killed_on_cancel() {
+    let signal = CancelSignal::new();
+    signal.reset();
+    let tool = ExecuteBashCommandTool::new(signal.clone());
+
+    cancel_after(signal, Duration::from_millis(200));
+    let started = Instant::now();
+    let result = tool
+        .call(ExecuteBashCommandArgs {
+            command: "sleep 30".to_string(),
+        })
+        .await;
+
+    assert!(matches!(result, Err(FileToolError::Cancelled(_))));
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn test_cancel_signal_resets_per_turn() {
+    let signal = CancelSignal::new();
+    let first = signal.reset();
+    signal.cancel();
+    assert!(first.is_cancelled());
+
+    let second = signal.reset();
+    assert!(!second.is_cancelled());
+    assert!(!signal.is_cancelled());
+}
+
+#[tokio::test]
+async fn test_prepared_token_is_kept_for_the_turn() {
+    let signal = CancelSignal::new();
+    let prepared = signal.reset();
+    signal.cancel();
+
+    // 轮次开始前的取消对这一轮生效, 之后的轮次重新开始
+    let turn = signal.begin_turn();
+    assert!(turn.is_cancelled());
+    assert!(prepared.is_cancelled());
+    assert!(!signal.begin_turn().is_cancelled());
+}
+
+#[tokio::test]
+async fn test_child_signal_follows_the_running_turn() {
+    let signal = CancelSignal::new();
+    let turn = signal.begin_turn();
+    let child = signal.child();
+
+    // 子代理开始自己的轮次不会替换父代理的令牌
+    let child_turn = child.begin_turn();
+    assert!(!child_turn.is_cancelled());
+    signal.cancel();
+    assert!(turn.is_cancelled());
+    assert!(child_turn.is_cancelled());
+    assert!(child.is_cancelled());
+}
+
+#[tokio::test]
+async fn test_cancel_before_turn_starts_stops_it() {
+    let script = MockScript::new(vec![MockTurn {
+        text: Some("Too late".to_string()),
+        ..MockTurn::default()
+    }]);
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .build()
+        .unwrap();
+
+    // Ctrl-C pressed after the handler was installed but before the turn began
+    let cancel = agent.cancel_signal();
+    cancel.reset();
+    cancel.cancel();
+    let error = agent.chat("hi").await.unwrap_err();
+    assert!(error.is::<TurnCancelled>());
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    assert_eq!(inner.model.remaining_turns(), 1);
+}
+
+#[tokio::test]
+async fn test_cancel_turn_records_partial_response() {
+    let temp_dir = TempDir::new().unwrap();
+    let script = MockScript::new(vec![
+        MockTurn {
+            text: Some("Running the slow command".to_string()),
+            tool_calls: vec![MockToolCall {
+                id: None,
+                name: "exec_cmd".to_string(),
+                arguments: json!({ "command": "sleep 30" }),
+                expect_result: None,
+            }],
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("Finished".to_string()),
+            ..MockTurn::default()
+        },
+    ]);
+    let context =
+        ContextManager::new(temp_dir.path().join("sessions"), "cancel".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .with_context(context)
+        .build()
+        .unwrap();
+
+    cancel_after(agent.cancel_signal(), Duration::from_millis(300));
+    let started = Instant::now();
+    let error = agent.chat("run it").await.unwrap_err();
+
+    assert!(started.elapsed() < Duration::from_secs(10));
+    let cancelled = error.downcast_ref::<TurnCancelled>().unwrap();
+    assert_eq!(cancelled.partial_response, "Running the slow command");
+
+    let messages = agent.context().unwrap().get_messages();
+    assert_eq!(messages.len(), 2);
+    let Message::Assistant { content, .. } = &messages[1] else {
+        panic!("expected an assistant message");
+    };
+    let rig::completion::AssistantContent::Text(text) = content.first() else {
+        panic!("expected text content");
+    };
+    assert!(text.text.starts_with("Running the slow command"));
+    assert!(text.text.contains("[Interrupted by user]"));
+}
+
+#[tokio::test]
+async fn test_turn_after_cancel_runs_normally() {
+    let script = MockScript::new(vec![MockTurn {
+        text: Some("Hello again".to_string()),
+        ..MockTurn::default()
+    }]);
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .build()
+        .unwrap();
+
+    // A cancel from a previous turn must not leak into the next one
+    agent.cancel_signal().cancel();
+    let response = agent.chat("hi").await.unwrap();
+    assert_eq!(response.response(), "Hello again");
+}
+
+#[tokio::test]
+async fn test_failed_turn_records_the_user_message_and_saves() {
+    let temp_dir = TempDir::new().unwrap();
+    let script = MockScript::new(vec![MockTurn {
+        text: Some("Listing".to_string()),
+        tool_calls: vec![MockToolCall {
+            id: None,
+            name: "list_dir".to_string(),
+            arguments: json!({ "dir_path": temp_dir.path().to_string_lossy() }),
+            expect_result: None,
+        }],
+        ..MockTurn::default()
+    }]);
+    let sessions = temp_dir.path().join("sessions");
+    let context = ContextManager::new(&sessions, "failed".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .with_context(context)
+        .with_tool_progress(false)
+        .build()
+        .unwrap();
+
+    // The script has no answer for the second completion call, so the turn fails
+    assert!(agent.chat("list the files").await.is_err());
+
+    let messages = agent.context().unwrap().get_messages().to_vec();
+    assert!(matches!(messages.first(), Some(Message::User { .. })));
+    let Some(Message::Assistant { content, .. }) = messages.last() else {
+        panic!("the failed turn should end with an assistant message");
+    };
+    let rig::completion::AssistantContent::Text(text) = content.first() else {
+        panic!("expected text content");
+    };
+    assert!(text.text.starts_with("[Turn failed: "));
+
+    let mut saved = ContextManager::new(&sessions, "failed".to_string()).unwrap();
+    assert!(saved.load().unwrap());
+    assert_eq!(saved.get_messages().len(), messages.len());
+}
@@ -0,0 +1,144 @@
+use kota::kota_code::agent::mock::{MockScript, MockTurn};
+use kota::kota_code::context::{CompactionPolicy, SUMMARY_HEADER};
+use kota::{
+    AgentBuilder, AgentEvent, AgentInstance, AgentType, ContextManager, KotaConfig, Provider,
+};
+use rig::completion::{CompletionRequest, Message};
+use tempfile::TempDir;
+
+fn text(text: &str) -> MockTurn {
+    MockTurn {
+        text: Some(text.to_string()),
+        ..MockTurn::default()
+    }
+}
+
+/// A message of about `tokens` estimated tokens
+fn sized(tokens: usize, label: &str) -> String {
+    format!("{}{}", label, "x".repeat(tokens * 4 - label.len()))
+}
+
+fn requests(agent: &AgentInstance) -> Vec<CompletionRequest> {
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    inner.model.requests()
+}
+
+fn long_session(dir: &TempDir) -> ContextManager {
+    let mut context = ContextManager::new(dir.path(), "long".to_string())
+        .unwrap()
+        .with_token_budget(1_000);
+    for turn in 1..=4 {
+        context.add_message(Message::user(sized(100, &format!("request {}", turn))));
+        context.add_message(Message::assistant(sized(100, &format!("answer {}", turn))));
+    }
+    context
+}
+
+#[test]
+fn test_compact_keeps_recent_and_pinned_messages() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut context = long_session(&temp_dir);
+
+    // 30% of the budget keeps the last request and its answer
+    let end = context.compaction_end().unwrap();
+    assert_eq!(end, 6);
+    assert_eq!(context.compact(end, "## Goal\nFinish the work"), 5);
+
+    let messages = context.get_messages();
+    assert_eq!(messages.len(), 4);
+    assert!(format!("{:?}", messages[0]).contains("request 1"));
+    assert!(format!("{:?}", messages[1]).contains(SUMMARY_HEADER));
+    assert!(format!("{:?}", messages[2]).contains("request 4"));
+    assert_eq!(context.archived_messages().len(), 5);
+
+    // Only the summary and pinned messages are left to compact
+    assert_eq!(context.compaction_end(), None);
+
+    context.save().unwrap();
+    let mut loaded = ContextManager::new(temp_dir.path(), "long".to_string()).unwrap();
+    assert!(loaded.load().unwrap());
+    assert_eq!(loaded.get_messages(), context.get_messages());
+    assert_eq!(loaded.archived_messages(), context.archived_messages());
+}
+
+#[tokio::test]
+async fn test_manual_compaction_asks_the_model() {
+    let temp_dir = TempDir::new().unwrap();
+    let context = long_session(&temp_dir).with_compaction(CompactionPolicy {
+        auto: false,
+        ..CompactionPolicy::default()
+    });
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![
+            text("## Goal\nShip requests one to three"),
+            text("Next answer"),
+        ]))
+        .with_context(context)
+        .build()
+        .unwrap();
+
+    let compaction = agent.compact().await.unwrap().unwrap();
+    assert_eq!(compaction.messages, 5);
+    assert!(compaction.tokens_after < compaction.tokens_before);
+
+    let summary_request = &requests(&agent)[0];
+    assert!(summary_request.tools.is_empty());
+    let prompt = format!("{:?}", summary_request.chat_history);
+    assert!(prompt.contains("answer 3"));
+    assert!(!prompt.contains("request 4"));
+
+    agent.chat("go on").await.unwrap();
+    let history = format!("{:?}", requests(&agent)[1].chat_history);
+    assert!(history.contains("Ship requests one to three"));
+    assert!(!history.contains("answer 2"));
+    assert!(history.contains("answer 4"));
+}
+
+#[tokio::test]
+async fn test_auto_compaction_before_turn() {
+    let temp_dir = TempDir::new().unwrap();
+    let context = long_session(&temp_dir);
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![text("Summary"), text("Reply")]))
+        .with_context(context)
+        .build()
+        .unwrap();
+
+    let mut compacted = None;
+    agent
+        .chat_with_events("next", |event| {
+            if let AgentEvent::Compacted(compaction) = event {
+                compacted = Some(*compaction);
+            }
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(compacted.unwrap().messages, 5);
+    assert_eq!(requests(&agent).len(), 2);
+    let context = agent.context().unwrap();
+    assert!(!context.needs_compaction());
+    // request 1, summary, request 4, answer 4, next, reply
+    assert_eq!(context.get_messages().len(), 6);
+}
+
+#[test]
+fn test_lua_config_compaction() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.lua");
+    std::fs::write(
+        &config_path,
+        r#"kota.setup({ compaction = { threshold = 0.7, keep_recent = 0.25 } })"#,
+    )
+    .unwrap();
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+    assert!(config.compaction.auto);
+    assert_eq!(config.compaction.threshold, 0.7);
+    assert_eq!(config.compaction.keep_recent, 0.25);
+
+    std::fs::write(&config_path, r#"kota.setup({ compaction = false })"#).unwrap();
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+    assert!(!config.compaction.auto);
+}
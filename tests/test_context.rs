@@ -1,5 +1,12 @@
-use kota::kota_code::context::ContextManager;
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn};
+use kota::kota_code::context::{ContextManager, SerializableMessage, SESSION_VERSION};
+use kota::kota_code::tokens::{context_window, estimate_tokens, DEFAULT_CONTEXT_WINDOW};
+use kota::{AgentBuilder, AgentType, KotaConfig, Provider};
+use rig::completion::message::{AssistantContent, Reasoning, ToolResultContent, UserContent};
 use rig::completion::Message;
+use rig::OneOrMany;
+use serde_json::json;
+use std::fs;
 use tempfile::TempDir;
 
 #[test]
@@ -21,17 +28,374 @@ fn test_context_manager_basic_operations() {
     assert_eq!(new_manager.get_messages().len(), 2);
 }
 
+/// A message of about `tokens` estimated tokens
+fn sized(tokens: usize, label: &str) -> String {
+    format!("{}{}", label, "x".repeat(tokens * 4 - label.len()))
+}
+
 #[test]
-fn test_context_manager_max_messages() {
+fn test_trim_to_token_budget() {
     let temp_dir = TempDir::new().unwrap();
     let mut manager = ContextManager::new(temp_dir.path(), "test_session".to_string())
         .unwrap()
-        .with_max_messages(2);
+        .with_token_budget(350);
 
-    // 添加3条消息，应该只保留最后2条
-    manager.add_message(Message::user("Message 1"));
-    manager.add_message(Message::user("Message 2"));
-    manager.add_message(Message::user("Message 3"));
+    manager.add_message(Message::user(sized(100, "first request")));
+    manager.add_message(Message::assistant(sized(100, "answer 1")));
+    manager.add_message(Message::user(sized(100, "request 2")));
+    assert_eq!(manager.get_messages().len(), 3);
 
+    // 超出预算: 第一条请求被固定, 裁剪它之后最早的消息
+    manager.add_message(Message::assistant(sized(100, "answer 2")));
+    let history = format!("{:?}", manager.get_messages());
+    assert_eq!(manager.get_messages().len(), 3);
+    assert!(history.contains("first request"));
+    assert!(!history.contains("answer 1"));
+    assert!(manager.estimated_tokens() <= manager.token_budget());
+
+    // 最新的消息即使超出预算也会保留
+    manager.add_message(Message::user(sized(1000, "huge")));
+    let history = format!("{:?}", manager.get_messages());
     assert_eq!(manager.get_messages().len(), 2);
+    assert!(history.contains("first request"));
+    assert!(history.contains("huge"));
+}
+
+#[test]
+fn test_trim_keeps_tool_calls_with_results() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut manager = ContextManager::new(temp_dir.path(), "tools".to_string())
+        .unwrap()
+        .with_token_budget(150);
+    for message in tool_turn() {
+        manager.add_message(message);
+    }
+    manager.add_message(Message::user(sized(100, "next request")));
+
+    // 工具调用和结果一起被裁剪, 不会留下孤立的结果
+    let messages = manager.get_messages();
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[0], Message::user("List the files"));
+    assert!(!format!("{:?}", messages).contains("call_1"));
+}
+
+#[test]
+fn test_pinned_messages_survive_trimming_and_reload() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut manager = ContextManager::new(temp_dir.path(), "pins".to_string())
+        .unwrap()
+        .with_token_budget(250);
+
+    manager.add_message(Message::user("first request"));
+    manager.add_message(Message::assistant("Use tabs, never spaces"));
+    assert!(manager.pin_message(1));
+    assert!(!manager.pin_message(5));
+    assert!(manager.is_pinned(0));
+
+    manager.add_message(Message::user(sized(100, "request 2")));
+    manager.add_message(Message::assistant(sized(100, "answer 2")));
+    manager.add_message(Message::user(sized(100, "request 3")));
+
+    let history = format!("{:?}", manager.get_messages());
+    assert!(history.contains("Use tabs"));
+    assert!(!history.contains("request 2"));
+    manager.save().unwrap();
+
+    let mut loaded = ContextManager::new(temp_dir.path(), "pins".to_string()).unwrap();
+    assert!(loaded.load().unwrap());
+    assert_eq!(loaded.get_messages(), manager.get_messages());
+    assert!(loaded.is_pinned(1));
+    assert!(loaded.unpin_message(1));
+    assert!(!loaded.is_pinned(1));
+}
+
+#[test]
+fn test_context_window_budget() {
+    assert_eq!(context_window("gpt-4o-mini"), 128_000);
+    assert_eq!(context_window("gpt-4"), 8_192);
+    assert_eq!(context_window("claude-3-5-sonnet-latest"), 200_000);
+    assert_eq!(context_window("openrouter/deepseek-chat"), 128_000);
+    assert_eq!(context_window("my-local-model"), DEFAULT_CONTEXT_WINDOW);
+    assert_eq!(estimate_tokens("abcdefgh"), 2);
+    assert_eq!(estimate_tokens("abcdefghi"), 3);
+
+    let temp_dir = TempDir::new().unwrap();
+    let manager = ContextManager::new(temp_dir.path(), "window".to_string())
+        .unwrap()
+        .with_context_window(8_192);
+    assert_eq!(manager.token_budget(), 6_144);
+
+    let config_path = temp_dir.path().join("config.lua");
+    fs::write(
+        &config_path,
+        r#"kota.setup({ model = "my-local-model", context_window = 65536 })"#,
+    )
+    .unwrap();
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+    assert_eq!(config.context_window(), 65_536);
+    assert_eq!(KotaConfig::default().context_window(), 128_000);
+}
+
+fn tool_turn() -> Vec<Message> {
+    vec![
+        Message::user("List the files"),
+        Message::Assistant {
+            id: None,
+            content: OneOrMany::many(vec![
+                AssistantContent::Reasoning(
+                    Reasoning::new("Need a listing").with_signature(Some("sig".to_string())),
+                ),
+                AssistantContent::text("Let me look."),
+                AssistantContent::tool_call_with_call_id(
+                    "call_1",
+                    "fc_1".to_string(),
+                    "scan_codebase",
+                    json!({ "path": "." }),
+                ),
+            ])
+            .unwrap(),
+        },
+        Message::User {
+            content: OneOrMany::one(UserContent::tool_result_with_call_id(
+                "call_1",
+                "fc_1".to_string(),
+                OneOrMany::one(ToolResultContent::text("src/\nCargo.toml")),
+            )),
+        },
+        Message::assistant("There is a src directory and a Cargo.toml."),
+    ]
+}
+
+#[test]
+fn test_tool_calls_survive_save_and_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut manager = ContextManager::new(temp_dir.path(), "tools".to_string()).unwrap();
+    for message in tool_turn() {
+        manager.add_message(message);
+    }
+    manager.save().unwrap();
+
+    let saved: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(temp_dir.path().join("tools.json")).unwrap())
+            .unwrap();
+    assert_eq!(saved["version"], SESSION_VERSION);
+
+    let mut loaded = ContextManager::new(temp_dir.path(), "tools".to_string()).unwrap();
+    assert!(loaded.load().unwrap());
+    assert_eq!(loaded.get_messages(), tool_turn().as_slice());
+
+    let summary = SerializableMessage::from(&loaded.get_messages()[2]);
+    assert_eq!(summary.role, "tool");
+    assert_eq!(summary.content, "src/\nCargo.toml");
+}
+
+#[test]
+fn test_migrate_version_1_sessions() {
+    let temp_dir = TempDir::new().unwrap();
+    let legacy = json!({
+        "metadata": {
+            "session_id": "old",
+            "created_at": "2024-01-01T00:00:00+00:00",
+            "last_updated": "2024-01-02T00:00:00+00:00",
+            "message_count": 2
+        },
+        "messages": [
+            { "role": "user", "content": "Hello" },
+            { "role": "assistant", "content": "Hi! [tool call]" }
+        ]
+    });
+    let path = temp_dir.path().join("old.json");
+    fs::write(&path, legacy.to_string()).unwrap();
+
+    let mut manager = ContextManager::new(temp_dir.path(), "old".to_string()).unwrap();
+    assert!(manager.load().unwrap());
+    assert_eq!(
+        manager.get_messages(),
+        &[
+            Message::user("Hello"),
+            Message::assistant("Hi! [tool call]")
+        ]
+    );
+
+    assert_eq!(manager.migrate_sessions().unwrap(), 1);
+    let migrated: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(migrated["version"], SESSION_VERSION);
+    assert_eq!(
+        migrated["metadata"]["created_at"],
+        "2024-01-01T00:00:00+00:00"
+    );
+    assert_eq!(manager.migrate_sessions().unwrap(), 0);
+
+    let mut reloaded = ContextManager::new(temp_dir.path(), "old".to_string()).unwrap();
+    assert!(reloaded.load().unwrap());
+    assert_eq!(reloaded.get_messages(), manager.get_messages());
+}
+
+#[test]
+fn test_newer_session_version_is_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut manager = ContextManager::new(temp_dir.path(), "future".to_string()).unwrap();
+    manager.save().unwrap();
+
+    let path = temp_dir.path().join("future.json");
+    let mut data: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    data["version"] = json!(SESSION_VERSION + 1);
+    fs::write(&path, data.to_string()).unwrap();
+
+    let err = manager.load().unwrap_err();
+    assert!(err.to_string().contains("newer than the supported version"));
+    // The session is still listed
+    assert_eq!(manager.list_sessions().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_resumed_session_replays_tool_calls() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("hello.txt");
+    let script = MockScript::new(vec![
+        MockTurn {
+            text: Some("Writing it.".to_string()),
+            tool_calls: vec![MockToolCall {
+                id: Some("write-1".to_string()),
+                name: "write_file".to_string(),
+                arguments: json!({ "file_path": file_path, "content": "hi" }),
+                expect_result: None,
+            }],
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("Done".to_string()),
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("It says hi".to_string()),
+            ..MockTurn::default()
+        },
+    ]);
+    let sessions = temp_dir.path().join("sessions");
+    let context = ContextManager::new(&sessions, "resume".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script.clone())
+        .with_context(context)
+        .build()
+        .unwrap();
+    agent.chat("write hello.txt").await.unwrap();
+
+    // A new agent resumes the saved session
+    let mut context = ContextManager::new(&sessions, "resume".to_string()).unwrap();
+    assert!(context.load().unwrap());
+    assert_eq!(
+        context.get_messages(),
+        agent.context().unwrap().get_messages()
+    );
+    let remaining = MockScript::new(script.turns[2..].to_vec());
+    let mut resumed = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(remaining)
+        .with_context(context)
+        .build()
+        .unwrap();
+    resumed.chat("what does it say?").await.unwrap();
+
+    let AgentType::Mock(inner) = &resumed.agent else {
+        panic!("expected the mock provider");
+    };
+    let history = &inner.model.requests()[0].chat_history;
+    let tool_call = history.iter().find_map(|message| match message {
+        Message::Assistant { content, .. } => content.iter().find_map(|c| match c {
+            AssistantContent::ToolCall(call) => Some(call.clone()),
+            _ => None,
+        }),
+        _ => None,
+    });
+    let tool_call = tool_call.expect("tool call replayed");
+    assert_eq!(tool_call.id, "write-1");
+    assert_eq!(tool_call.function.name, "write_file");
+    assert!(format!("{:?}", history).contains("Successfully wrote"));
+}
+
+#[tokio::test]
+async fn test_streamed_reasoning_is_saved() {
+    let temp_dir = TempDir::new().unwrap();
+    let script = MockScript::new(vec![MockTurn {
+        reasoning: Some("The user wants a greeting".to_string()),
+        text: Some("Hello!".to_string()),
+        ..MockTurn::default()
+    }]);
+    let context = ContextManager::new(temp_dir.path(), "reasoning".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .with_context(context)
+        .build()
+        .unwrap();
+    agent.chat("say hi").await.unwrap();
+
+    let mut saved = ContextManager::new(temp_dir.path(), "reasoning".to_string()).unwrap();
+    assert!(saved.load().unwrap());
+    let Message::Assistant { content, .. } = &saved.get_messages()[1] else {
+        panic!("expected an assistant message");
+    };
+    let content: Vec<AssistantContent> = content.iter().cloned().collect();
+    assert_eq!(content.len(), 2);
+    let AssistantContent::Reasoning(reasoning) = &content[0] else {
+        panic!("expected the reasoning first");
+    };
+    assert_eq!(reasoning.reasoning.join(""), "The user wants a greeting");
+    assert_eq!(content[1], AssistantContent::text("Hello!"));
+}
+
+#[tokio::test]
+async fn test_prompt_is_sent_and_saved_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let script = MockScript::new(vec![
+        MockTurn {
+            text: Some("First answer".to_string()),
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("Second answer".to_string()),
+            ..MockTurn::default()
+        },
+    ]);
+    let context = ContextManager::new(temp_dir.path(), "once".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .with_context(context)
+        .build()
+        .unwrap();
+    agent.chat("first question").await.unwrap();
+    agent.chat("second question").await.unwrap();
+
+    // The history sent with a prompt holds the earlier turns and the prompt itself, once
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    let requests = inner.model.requests();
+    assert_eq!(requests[0].chat_history.len(), 1);
+    let history = &requests[1].chat_history;
+    assert_eq!(history.len(), 3);
+    let prompts = history
+        .iter()
+        .filter(|message| format!("{:?}", message).contains("second question"))
+        .count();
+    assert_eq!(prompts, 1);
+
+    // The saved session holds every prompt once
+    let mut saved = ContextManager::new(temp_dir.path(), "once".to_string()).unwrap();
+    assert!(saved.load().unwrap());
+    let messages: Vec<String> = saved
+        .get_messages()
+        .iter()
+        .map(|message| SerializableMessage::from(message).content)
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            "first question",
+            "First answer",
+            "second question",
+            "Second answer"
+        ]
+    );
 }
@@ -0,0 +1,286 @@
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn, MockUsage};
+use kota::{
+    AgentBuilder, AgentInstance, AgentType, Budget, BudgetExceeded, ContextManager, ModelPrice,
+    PriceTable, Provider, TurnCancelled,
+};
+use rig::completion::CompletionRequest;
+use serde_json::json;
+use std::fs;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn mock_agent(sessions_dir: &TempDir, turns: Vec<MockTurn>) -> AgentInstance {
+    let context = ContextManager::new(sessions_dir.path(), "parent".to_string()).unwrap();
+    AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(turns))
+        .with_context(context)
+        .with_tool_progress(false)
+        .build()
+        .unwrap()
+}
+
+fn tool_call(name: &str, arguments: serde_json::Value) -> MockTurn {
+    MockTurn {
+        tool_calls: vec![MockToolCall {
+            id: None,
+            name: name.to_string(),
+            arguments,
+            expect_result: None,
+        }],
+        ..MockTurn::default()
+    }
+}
+
+fn text(text: &str) -> MockTurn {
+    MockTurn {
+        text: Some(text.to_string()),
+        ..MockTurn::default()
+    }
+}
+
+fn requests(agent: &AgentInstance) -> Vec<CompletionRequest> {
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    inner.model.requests()
+}
+
+fn tool_names(request: &CompletionRequest) -> Vec<String> {
+    request.tools.iter().map(|tool| tool.name.clone()).collect()
+}
+
+#[tokio::test]
+async fn test_delegate_task_returns_only_the_summary() {
+    let sessions_dir = TempDir::new().unwrap();
+    let work_dir = TempDir::new().unwrap();
+    let notes = work_dir.path().join("notes.txt");
+    let notes_str = notes.to_string_lossy().to_string();
+
+    let mut agent = mock_agent(
+        &sessions_dir,
+        vec![
+            // parent
+            tool_call(
+                "delegate_task",
+                json!({ "task": "Write the notes file", "tools": ["write_file"] }),
+            ),
+            // child
+            tool_call(
+                "write_file",
+                json!({ "file_path": notes_str, "content": "explored everything" }),
+            ),
+            text("Wrote notes.txt"),
+            // parent
+            text("The sub-agent wrote the notes"),
+        ],
+    );
+
+    let response = agent.chat("Take notes").await.unwrap();
+    assert_eq!(response.response(), "The sub-agent wrote the notes");
+    assert_eq!(fs::read_to_string(&notes).unwrap(), "explored everything");
+
+    let requests = requests(&agent);
+    assert_eq!(requests.len(), 4);
+    assert!(tool_names(&requests[0]).contains(&"delegate_task".to_string()));
+    assert_eq!(tool_names(&requests[1]), vec!["write_file"]);
+
+    // The parent only sees the child's summary, not its tool calls
+    let parent_history = format!("{:?}", requests[3].chat_history);
+    assert!(parent_history.contains("Wrote notes.txt"));
+    assert!(parent_history.contains("parent-task-1"));
+    assert!(!parent_history.contains("explored everything"));
+
+    // The child transcript is saved as a session linked to the parent
+    let mut child = ContextManager::new(sessions_dir.path(), "parent-task-1".to_string()).unwrap();
+    assert!(child.load().unwrap());
+    assert_eq!(child.parent_session_id(), Some("parent"));
+    // task, tool call, tool result and summary
+    assert_eq!(child.get_messages().len(), 4);
+    assert!(format!("{:?}", child.get_messages()[2]).contains("Successfully wrote"));
+
+    let sessions = child.list_sessions().unwrap();
+    let linked = sessions
+        .iter()
+        .find(|s| s.session_id == "parent-task-1")
+        .unwrap();
+    assert_eq!(linked.parent_session_id.as_deref(), Some("parent"));
+}
+
+#[tokio::test]
+async fn test_delegate_task_reports_child_errors() {
+    let sessions_dir = TempDir::new().unwrap();
+    let mut agent = mock_agent(
+        &sessions_dir,
+        vec![
+            tool_call(
+                "delegate_task",
+                json!({ "task": "Look around", "tools": ["teleport"] }),
+            ),
+            text("The sub-agent could not start"),
+        ],
+    );
+
+    agent.chat("Explore").await.unwrap();
+
+    let requests = requests(&agent);
+    assert_eq!(requests.len(), 2);
+    let history = format!("{:?}", requests[1].chat_history);
+    assert!(history.contains("Unknown tool"));
+}
+
+#[tokio::test]
+async fn test_sub_agents_can_be_disabled() {
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![text("done")]))
+        .with_sub_agents(false)
+        .with_tool_progress(false)
+        .build()
+        .unwrap();
+    assert!(agent.sub_agents.is_none());
+
+    agent.chat("hello").await.unwrap();
+    let tools = tool_names(&requests(&agent)[0]);
+    assert!(tools.contains(&"read_file".to_string()));
+    assert!(!tools.contains(&"delegate_task".to_string()));
+
+    let result = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![]))
+        .with_allowed_tools(vec!["no_such_tool".to_string()])
+        .build();
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_cancelling_the_parent_cancels_the_sub_agent() {
+    let sessions_dir = TempDir::new().unwrap();
+    let mut agent = mock_agent(
+        &sessions_dir,
+        vec![
+            // parent
+            tool_call("delegate_task", json!({ "task": "Wait for the build" })),
+            // child
+            tool_call("exec_cmd", json!({ "command": "sleep 30" })),
+            text("unreachable"),
+        ],
+    );
+
+    let signal = agent.cancel_signal();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        signal.cancel();
+    });
+    let started = Instant::now();
+    let error = agent.chat("Build it").await.unwrap_err();
+    assert!(error.is::<TurnCancelled>());
+
+    // The child stops its command and records the interruption in its own session
+    let mut child = ContextManager::new(sessions_dir.path(), "parent-task-1".to_string()).unwrap();
+    while !(child.load().unwrap() && format!("{:?}", child.get_messages()).contains("Interrupted"))
+    {
+        assert!(started.elapsed() < Duration::from_secs(10));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_delegated_usage_counts_against_the_parent_budget() {
+    let sessions_dir = TempDir::new().unwrap();
+    let with_usage = |turn: MockTurn, input_tokens: u64| MockTurn {
+        usage: Some(MockUsage {
+            input_tokens,
+            output_tokens: 0,
+            ..MockUsage::default()
+        }),
+        ..turn
+    };
+    let mut prices = PriceTable::default();
+    prices.insert(
+        "mock",
+        ModelPrice {
+            input: 1.0,
+            output: 1.0,
+            cached_input: None,
+        },
+    );
+    let context = ContextManager::new(sessions_dir.path(), "parent".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![
+            // parent
+            with_usage(
+                MockTurn {
+                    text: Some("Delegating".to_string()),
+                    ..tool_call("delegate_task", json!({ "task": "Read everything" }))
+                },
+                100,
+            ),
+            // child
+            with_usage(text("Read it all"), 900),
+            // parent
+            with_usage(text("Done"), 100),
+        ]))
+        .with_context(context)
+        .with_price_table(prices)
+        .with_budget(Budget {
+            max_tokens: Some(1000),
+            ..Budget::default()
+        })
+        .with_tool_progress(false)
+        .build()
+        .unwrap();
+
+    agent.chat("Read the code").await.unwrap();
+
+    // /cost shows the sub-agent's tokens in the parent session
+    let usage = agent.session_usage().unwrap();
+    assert_eq!(usage.total.input_tokens, 1100);
+    assert_eq!(agent.session_cost(), Some(0.0011));
+
+    let err = agent.chat("Again").await.unwrap_err();
+    assert!(err.is::<BudgetExceeded>());
+}
+
+#[tokio::test]
+async fn test_sub_agents_only_get_the_remaining_budget() {
+    let sessions_dir = TempDir::new().unwrap();
+    let delegate = |task: &str| MockToolCall {
+        id: None,
+        name: "delegate_task".to_string(),
+        arguments: json!({ "task": task }),
+        expect_result: None,
+    };
+    let context = ContextManager::new(sessions_dir.path(), "parent".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![
+            // parent
+            MockTurn {
+                tool_calls: vec![delegate("First task"), delegate("Second task")],
+                ..MockTurn::default()
+            },
+            // first child
+            MockTurn {
+                usage: Some(MockUsage {
+                    input_tokens: 1000,
+                    ..MockUsage::default()
+                }),
+                ..text("Used up the budget")
+            },
+            // parent
+            text("Done"),
+        ]))
+        .with_context(context)
+        .with_budget(Budget {
+            max_tokens: Some(1000),
+            ..Budget::default()
+        })
+        .with_tool_progress(false)
+        .build()
+        .unwrap();
+
+    agent.chat("Do both").await.unwrap();
+
+    // The second sub-agent is refused because the first one spent the rest
+    let requests = requests(&agent);
+    let history = format!("{:?}", requests.last().unwrap().chat_history);
+    assert!(history.contains("Session budget exceeded"));
+    assert!(!sessions_dir.path().join("parent-task-2.json").exists());
+}
@@ -0,0 +1,193 @@
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn};
+use kota::{AgentBuilder, AgentType, KotaConfig, Provider, Skill, SkillManager};
+use serde_json::json;
+use std::fs;
+use tempfile::TempDir;
+
+fn mock_builder(script: MockScript) -> AgentBuilder {
+    AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+}
+
+fn text_turn(text: &str) -> MockTurn {
+    MockTurn {
+        text: Some(text.to_string()),
+        ..MockTurn::default()
+    }
+}
+
+fn tool_turn() -> MockTurn {
+    MockTurn {
+        tool_calls: vec![MockToolCall {
+            id: None,
+            name: "read_file".to_string(),
+            arguments: json!({ "file_path": "Cargo.toml" }),
+            expect_result: None,
+        }],
+        ..MockTurn::default()
+    }
+}
+
+#[test]
+fn test_lua_config_generation_settings() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.lua");
+    fs::write(
+        &config_path,
+        r#"
+kota.setup({
+  model = "gpt-4o",
+  api_key = "test-key",
+  temperature = 0.2,
+  top_p = 0.9,
+  max_tokens = 8192,
+  max_turns = 50,
+})
+"#,
+    )
+    .unwrap();
+
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+    let settings = config.generation_settings();
+
+    assert_eq!(settings.temperature, Some(0.2));
+    assert_eq!(settings.top_p, Some(0.9));
+    assert_eq!(settings.max_tokens, 8192);
+    assert_eq!(settings.max_turns, 50);
+}
+
+#[test]
+fn test_generation_settings_defaults() {
+    assert_eq!(KotaConfig::default().temperature, Some(0.7));
+
+    let settings = KotaConfig::default().generation_settings();
+
+    assert_eq!(settings.temperature, Some(0.7));
+    assert_eq!(settings.top_p, None);
+    assert_eq!(settings.max_tokens, 4096);
+    assert_eq!(settings.max_turns, 20);
+}
+
+#[tokio::test]
+async fn test_builder_settings_reach_request() {
+    let mut agent = mock_builder(MockScript::new(vec![text_turn("ok")]))
+        .with_temperature(0.3)
+        .with_top_p(0.5)
+        .with_max_tokens(1000)
+        .build()
+        .unwrap();
+    agent.chat("hi").await.unwrap();
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected a mock agent");
+    };
+    let request = &inner.model.requests()[0];
+    assert_eq!(request.temperature, Some(0.3));
+    assert_eq!(request.max_tokens, Some(1000));
+    assert_eq!(request.additional_params, Some(json!({ "top_p": 0.5 })));
+}
+
+#[tokio::test]
+async fn test_max_turns_limits_tool_loop() {
+    let script = MockScript::new(vec![
+        tool_turn(),
+        tool_turn(),
+        tool_turn(),
+        tool_turn(),
+        tool_turn(),
+        text_turn("done"),
+    ]);
+    let mut agent = mock_builder(script).with_max_turns(1).build().unwrap();
+
+    assert!(agent.chat("loop").await.is_err());
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected a mock agent");
+    };
+    assert!(inner.model.remaining_turns() > 0);
+}
+
+#[tokio::test]
+async fn test_skill_overrides_settings() {
+    let script = MockScript::new(vec![
+        tool_turn(),
+        tool_turn(),
+        tool_turn(),
+        text_turn("refactored"),
+        text_turn("plain"),
+    ]);
+
+    let mut skill_manager = SkillManager::new();
+    skill_manager.add_skill(Skill {
+        name: "long-refactor".to_string(),
+        description: "Large refactors".to_string(),
+        instructions: "Refactor carefully".to_string(),
+        temperature: Some(0.1),
+        max_tokens: Some(16000),
+        max_turns: Some(100),
+        ..Skill::default()
+    });
+    skill_manager.activate_skill("long-refactor").unwrap();
+
+    let mut agent = mock_builder(script)
+        .with_temperature(0.7)
+        .with_max_turns(1)
+        .with_skill_manager(skill_manager)
+        .build()
+        .unwrap();
+
+    // The skill raises the turn limit, so all tool turns fit
+    let response = agent.chat("refactor").await.unwrap();
+    assert_eq!(response.response(), "refactored");
+
+    // Defaults are restored once the skill is no longer active
+    agent.skill_manager_mut().unwrap().deactivate_all_skills();
+    agent.chat("again").await.unwrap();
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected a mock agent");
+    };
+    let requests = inner.model.requests();
+    assert_eq!(requests[0].temperature, Some(0.1));
+    assert_eq!(requests[0].max_tokens, Some(16000));
+    assert_eq!(requests[4].temperature, Some(0.7));
+    assert_eq!(requests[4].max_tokens, Some(4096));
+}
+
+#[tokio::test]
+async fn test_top_p_is_merged_into_additional_params() {
+    let script = MockScript::new(vec![text_turn("focused"), text_turn("plain")]);
+    let mut skill_manager = SkillManager::new();
+    skill_manager.add_skill(Skill {
+        name: "focused".to_string(),
+        description: "Focused answers".to_string(),
+        instructions: "Be precise".to_string(),
+        top_p: Some(0.2),
+        ..Skill::default()
+    });
+    skill_manager.activate_skill("focused").unwrap();
+    let mut agent = mock_builder(script)
+        .with_skill_manager(skill_manager)
+        .build()
+        .unwrap();
+    let AgentType::Mock(inner) = &mut agent.agent else {
+        panic!("expected a mock agent");
+    };
+    inner.additional_params = Some(json!({ "seed": 7 }));
+
+    agent.chat("first").await.unwrap();
+    agent.skill_manager_mut().unwrap().deactivate_all_skills();
+    agent.chat("second").await.unwrap();
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected a mock agent");
+    };
+    let requests = inner.model.requests();
+    assert_eq!(
+        requests[0].additional_params,
+        Some(json!({ "seed": 7, "top_p": 0.2 }))
+    );
+    // Without top_p the other params are kept
+    assert_eq!(requests[1].additional_params, Some(json!({ "seed": 7 })));
+    assert_eq!(requests[1].temperature, Some(0.7));
+}
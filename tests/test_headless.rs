@@ -0,0 +1,189 @@
+use kota::kota_cli::headless::{run_headless, CliArgs, OutputFormat};
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn, MockUsage};
+use kota::{AgentBuilder, AgentInstance, Provider};
+use serde_json::{json, Value};
+use std::fs;
+use tempfile::TempDir;
+
+fn mock_agent(script: MockScript) -> AgentInstance {
+    AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .with_tool_progress(false)
+        .build()
+        .unwrap()
+}
+
+fn write_then_answer(file_path: &str) -> MockScript {
+    MockScript::new(vec![
+        MockTurn {
+            tool_calls: vec![MockToolCall {
+                id: None,
+                name: "write_file".to_string(),
+                arguments: json!({ "file_path": file_path, "content": "fn main() {}" }),
+                expect_result: Some("Successfully wrote".to_string()),
+            }],
+            usage: Some(MockUsage {
+                input_tokens: 100,
+                output_tokens: 20,
+                ..MockUsage::default()
+            }),
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("Wrote main.rs".to_string()),
+            usage: Some(MockUsage {
+                input_tokens: 150,
+                output_tokens: 5,
+                ..MockUsage::default()
+            }),
+            ..MockTurn::default()
+        },
+    ])
+}
+
+#[test]
+fn test_parse_args() {
+    let args = CliArgs::parse(Vec::<String>::new()).unwrap();
+    assert!(!args.headless);
+
+    let args = CliArgs::parse(["-p", "fix", "the", "build"]).unwrap();
+    assert!(args.headless);
+    assert_eq!(args.prompt.as_deref(), Some("fix the build"));
+    assert_eq!(args.output_format, OutputFormat::Text);
+
+    let args = CliArgs::parse(["--output-format", "stream-json", "-"]).unwrap();
+    assert!(args.headless);
+    assert_eq!(args.prompt, None);
+    assert_eq!(args.output_format, OutputFormat::StreamJson);
+
+    let args = CliArgs::parse(["--print", "--output-format=json"]).unwrap();
+    assert_eq!(args.output_format, OutputFormat::Json);
+
+    assert!(CliArgs::parse(["--output-format", "xml"]).is_err());
+    assert!(CliArgs::parse(["--verbose"]).is_err());
+    assert!(CliArgs::parse(["hello"]).is_err());
+}
+
+#[tokio::test]
+async fn test_headless_json_report() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("main.rs");
+    let file_path_str = file_path.to_string_lossy().to_string();
+    let mut agent = mock_agent(write_then_answer(&file_path_str));
+
+    let mut out = Vec::new();
+    let report = run_headless(&mut agent, "create main.rs", OutputFormat::Json, &mut out)
+        .await
+        .unwrap();
+
+    assert_eq!(report.exit_code(), 0);
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "fn main() {}");
+
+    let json: Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(json["type"], "result");
+    assert_eq!(json["success"], true);
+    assert_eq!(json["result"], "Wrote main.rs");
+    assert_eq!(json["tool_calls"][0]["name"], "write_file");
+    assert!(json["tool_calls"][0]["result"]
+        .as_str()
+        .unwrap()
+        .contains("Successfully wrote"));
+    assert_eq!(json["files_touched"], json!([file_path_str]));
+    assert_eq!(json["usage"]["input_tokens"], 250);
+    assert_eq!(json["usage"]["output_tokens"], 25);
+}
+
+#[tokio::test]
+async fn test_headless_failed_tool_calls_touch_no_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let missing = temp_dir
+        .path()
+        .join("missing.rs")
+        .to_string_lossy()
+        .to_string();
+    let written = temp_dir.path().join("lib.rs").to_string_lossy().to_string();
+    let mut agent = mock_agent(MockScript::new(vec![
+        MockTurn {
+            tool_calls: vec![
+                MockToolCall {
+                    id: None,
+                    name: "delete_file".to_string(),
+                    arguments: json!({ "file_path": missing }),
+                    expect_result: None,
+                },
+                MockToolCall {
+                    id: None,
+                    name: "write_file".to_string(),
+                    arguments: json!({ "file_path": written, "content": "" }),
+                    expect_result: None,
+                },
+            ],
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("Done".to_string()),
+            ..MockTurn::default()
+        },
+    ]));
+
+    let mut out = Vec::new();
+    let report = run_headless(&mut agent, "clean up", OutputFormat::Json, &mut out)
+        .await
+        .unwrap();
+
+    assert!(report.tool_calls[0].is_error);
+    assert!(!report.tool_calls[1].is_error);
+    assert_eq!(report.files_touched, vec![written]);
+}
+
+#[tokio::test]
+async fn test_headless_stream_json() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("main.rs");
+    let mut agent = mock_agent(write_then_answer(&file_path.to_string_lossy()));
+
+    let mut out = Vec::new();
+    run_headless(
+        &mut agent,
+        "create main.rs",
+        OutputFormat::StreamJson,
+        &mut out,
+    )
+    .await
+    .unwrap();
+
+    let lines: Vec<Value> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let types: Vec<&str> = lines.iter().map(|l| l["type"].as_str().unwrap()).collect();
+
+    assert!(types.contains(&"tool_call_start"));
+    assert!(types.contains(&"tool_result"));
+    assert!(types.contains(&"text_delta"));
+    assert_eq!(types.last(), Some(&"result"));
+}
+
+#[tokio::test]
+async fn test_headless_text_and_failure_exit_code() {
+    let mut agent = mock_agent(MockScript::new(vec![MockTurn {
+        text: Some("All good".to_string()),
+        ..MockTurn::default()
+    }]));
+    let mut out = Vec::new();
+    let report = run_headless(&mut agent, "check", OutputFormat::Text, &mut out)
+        .await
+        .unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "All good\n");
+    assert_eq!(report.exit_code(), 0);
+
+    // The script is exhausted, so the next run fails
+    let mut out = Vec::new();
+    let report = run_headless(&mut agent, "again", OutputFormat::Json, &mut out)
+        .await
+        .unwrap();
+    assert!(!report.success);
+    assert!(report.error.is_some());
+    assert_eq!(report.exit_code(), 1);
+}
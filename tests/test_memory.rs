@@ -0,0 +1,142 @@
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn};
+use kota::kota_code::memory::{append_fact, MemoryScope};
+use kota::{AgentBuilder, AgentType, ProjectMemory, Provider};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Creates `<tmp>/KOTA.md`, `<tmp>/work/project/KOTA.md` and `<tmp>/home/.kota/KOTA.md`
+fn memory_tree() -> TempDir {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("work/project")).unwrap();
+    fs::create_dir_all(root.join("home/.kota")).unwrap();
+    fs::write(root.join("KOTA.md"), "Use tabs").unwrap();
+    fs::write(root.join("work/project/KOTA.md"), "Use spaces").unwrap();
+    fs::write(root.join("home/.kota/KOTA.md"), "Answer in English").unwrap();
+    temp_dir
+}
+
+fn discover(root: &Path) -> ProjectMemory {
+    ProjectMemory::discover(root.join("work/project"), Some(root.join("home/.kota"))).unwrap()
+}
+
+#[test]
+fn test_discovery_order() {
+    let temp_dir = memory_tree();
+    let memory = discover(temp_dir.path());
+
+    let found: Vec<(&str, MemoryScope)> = memory
+        .files
+        .iter()
+        .filter(|f| f.path.starts_with(temp_dir.path()))
+        .map(|f| (f.content.as_str(), f.scope))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("Answer in English", MemoryScope::Global),
+            ("Use tabs", MemoryScope::Parent),
+            ("Use spaces", MemoryScope::Project),
+        ]
+    );
+
+    let rendered = memory.render().unwrap();
+    let english = rendered.find("Answer in English").unwrap();
+    let tabs = rendered.find("Use tabs").unwrap();
+    let spaces = rendered.find("Use spaces").unwrap();
+    assert!(english < tabs && tabs < spaces);
+    assert!(rendered.contains("later files take precedence"));
+}
+
+#[test]
+fn test_empty_memory_renders_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    fs::create_dir(&project).unwrap();
+    fs::write(project.join("KOTA.md"), "  \n").unwrap();
+
+    let memory = ProjectMemory::discover(&project, None).unwrap();
+    assert!(memory.is_empty());
+    assert_eq!(memory.render(), None);
+    assert_eq!(memory.global_file(), None);
+}
+
+#[test]
+fn test_append_fact() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("nested/KOTA.md");
+
+    append_fact(&path, "Run tests with\n`cargo test`").unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Memory\n\n- Run tests with `cargo test`\n"
+    );
+
+    fs::write(&path, "Existing notes").unwrap();
+    append_fact(&path, "New fact").unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "Existing notes\n- New fact\n"
+    );
+}
+
+#[tokio::test]
+async fn test_memory_in_preamble_and_remember_tool() {
+    let temp_dir = memory_tree();
+    let project_file = temp_dir.path().join("work/project/KOTA.md");
+
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![
+            MockTurn {
+                tool_calls: vec![MockToolCall {
+                    id: None,
+                    name: "remember".to_string(),
+                    arguments: json!({ "fact": "The CI runs on nightly" }),
+                    expect_result: Some("Remembered".to_string()),
+                }],
+                ..MockTurn::default()
+            },
+            MockTurn {
+                text: Some("Noted".to_string()),
+                ..MockTurn::default()
+            },
+        ]))
+        .with_memory(discover(temp_dir.path()))
+        .with_sub_agents(false)
+        .with_tool_progress(false)
+        .build()
+        .unwrap();
+
+    agent.chat("Remember that CI uses nightly").await.unwrap();
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    let preamble = inner.model.requests()[0].preamble.clone().unwrap();
+    assert!(preamble.contains("# Project Memory"));
+    assert!(preamble.contains("Use spaces"));
+
+    assert_eq!(
+        fs::read_to_string(&project_file).unwrap(),
+        "Use spaces\n- The CI runs on nightly\n"
+    );
+
+    agent.reload_memory().unwrap();
+    let project = agent
+        .memory
+        .files
+        .iter()
+        .find(|f| f.scope == MemoryScope::Project)
+        .unwrap();
+    assert!(project.content.contains("The CI runs on nightly"));
+    let AgentType::Mock(inner) = &agent.agent else {
+        unreachable!();
+    };
+    assert!(inner
+        .preamble
+        .as_deref()
+        .unwrap()
+        .contains("The CI runs on nightly"));
+}
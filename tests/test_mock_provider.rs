@@ -0,0 +1,160 @@
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn};
+use kota::{AgentBuilder, AgentType, ContextManager, KotaConfig, Provider};
+use serde_json::json;
+use std::fs;
+use tempfile::TempDir;
+
+fn mock_builder(script: MockScript) -> AgentBuilder {
+    AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+}
+
+#[tokio::test]
+async fn test_mock_plain_text_turn() {
+    let script = MockScript::new(vec![MockTurn {
+        text: Some("Hello from the mock".to_string()),
+        ..MockTurn::default()
+    }]);
+
+    let mut agent = mock_builder(script).build().unwrap();
+    let response = agent.chat("hi").await.unwrap();
+
+    assert_eq!(response.response(), "Hello from the mock");
+}
+
+#[tokio::test]
+async fn test_mock_runs_tool_loop() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("hello.txt");
+    let file_path_str = file_path.to_string_lossy().to_string();
+
+    let script = MockScript::new(vec![
+        MockTurn {
+            tool_calls: vec![MockToolCall {
+                id: None,
+                name: "write_file".to_string(),
+                arguments: json!({ "file_path": file_path_str, "content": "hi there" }),
+                expect_result: Some("Successfully wrote".to_string()),
+            }],
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("Created the file".to_string()),
+            ..MockTurn::default()
+        },
+    ]);
+
+    let context =
+        ContextManager::new(temp_dir.path().join("sessions"), "mock".to_string()).unwrap();
+    let mut agent = mock_builder(script).with_context(context).build().unwrap();
+
+    let response = agent.chat("create hello.txt").await.unwrap();
+
+    assert_eq!(response.response(), "Created the file");
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "hi there");
+    // prompt, tool call, tool result and answer
+    assert_eq!(agent.context().unwrap().get_messages().len(), 4);
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected a mock agent");
+    };
+    assert_eq!(inner.model.remaining_turns(), 0);
+    let requests = inner.model.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].tools.iter().any(|t| t.name == "write_file"));
+    // The prompt is sent once, not duplicated in the history
+    assert_eq!(requests[0].chat_history.len(), 1);
+}
+
+#[tokio::test]
+async fn test_mock_reports_unexpected_tool_result() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("notes.txt");
+    fs::write(&file_path, "actual contents").unwrap();
+
+    let script = MockScript::new(vec![
+        MockTurn {
+            tool_calls: vec![MockToolCall {
+                id: Some("read_notes".to_string()),
+                name: "read_file".to_string(),
+                arguments: json!({ "file_path": file_path.to_string_lossy() }),
+                expect_result: Some("expected contents".to_string()),
+            }],
+            ..MockTurn::default()
+        },
+        MockTurn {
+            text: Some("Never sent".to_string()),
+            ..MockTurn::default()
+        },
+    ]);
+    let mut agent = mock_builder(script)
+        .with_tool_progress(false)
+        .build()
+        .unwrap();
+
+    let error = agent.chat("read the notes").await.unwrap_err();
+    let message = format!("{:#}", error);
+    assert!(
+        message.contains("Mock tool result for 'read_notes' does not contain 'expected contents'"),
+        "{}",
+        message
+    );
+    assert!(message.contains("actual contents"), "{}", message);
+
+    // The mismatch is reported before the next turn is replayed
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected a mock agent");
+    };
+    assert_eq!(inner.model.remaining_turns(), 1);
+}
+
+#[test]
+fn test_mock_script_from_yaml() {
+    let script = MockScript::from_yaml_str(
+        r#"
+- text: "Let me look"
+  tool_calls:
+    - name: read_file
+      arguments: { file_path: "Cargo.toml" }
+      expect_result: "[package]"
+  usage: { input_tokens: 10, output_tokens: 5 }
+- text: "Done"
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(script.turns.len(), 2);
+    assert_eq!(script.turns[0].tool_calls[0].name, "read_file");
+    assert_eq!(
+        script.turns[0].tool_calls[0].expect_result.as_deref(),
+        Some("[package]")
+    );
+    assert_eq!(script.turns[0].usage.unwrap().input_tokens, 10);
+    assert_eq!(script.turns[1].text.as_deref(), Some("Done"));
+}
+
+#[tokio::test]
+async fn test_mock_provider_from_config() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_path = temp_dir.path().join("script.json");
+    fs::write(&script_path, r#"[{ "text": "scripted" }]"#).unwrap();
+
+    let config = KotaConfig {
+        provider: Some(Provider::Mock),
+        model: "mock".to_string(),
+        mock_script: Some(script_path.to_string_lossy().to_string()),
+        ..KotaConfig::default()
+    };
+
+    let mut agent = AgentBuilder::from_config(&config).unwrap().build().unwrap();
+    let response = agent.chat("hello").await.unwrap();
+
+    assert_eq!(response.response(), "scripted");
+}
+
+#[tokio::test]
+async fn test_mock_provider_requires_script() {
+    let result =
+        AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string()).build();
+    assert!(result.is_err());
+}
@@ -0,0 +1,153 @@
+use kota::kota_code::agent::mock::{MockScript, MockTurn};
+use kota::kota_code::runtime::prompts::{git_branch, DEFAULT_PREAMBLE};
+use kota::kota_code::runtime::{PromptLoader, PromptVariables};
+use kota::{AgentBuilder, AgentInstance, AgentType, ProjectMemory, Provider};
+use std::fs;
+use tempfile::TempDir;
+
+fn variables() -> PromptVariables {
+    PromptVariables {
+        cwd: "/work/project".to_string(),
+        date: "2026-01-02".to_string(),
+        os: "linux".to_string(),
+        git_branch: "main".to_string(),
+        tools: vec!["read_file".to_string(), "exec_cmd".to_string()],
+    }
+}
+
+fn sent_preamble(agent: &AgentInstance) -> String {
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    inner.model.requests()[0].preamble.clone().unwrap()
+}
+
+fn mock_builder() -> AgentBuilder {
+    AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![MockTurn {
+            text: Some("hi".to_string()),
+            ..MockTurn::default()
+        }]))
+        .with_memory(ProjectMemory::default())
+        .with_sub_agents(false)
+        .with_tool_progress(false)
+}
+
+#[test]
+fn test_markdown_prompt_with_variables() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("system.md"),
+        "You work in {{cwd}} on {{git_branch}} ({{os}}, {{date}}). Tools: {{tools}}",
+    )
+    .unwrap();
+
+    let preamble = PromptLoader::new(dir.path()).load(&variables()).unwrap();
+
+    assert_eq!(
+        preamble.as_deref(),
+        Some("You work in /work/project on main (linux, 2026-01-02). Tools: read_file, exec_cmd")
+    );
+}
+
+#[test]
+fn test_lua_prompt_function() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("init.lua"),
+        r#"
+return function(ctx)
+  return "Branch " .. ctx.git_branch .. " with " .. #ctx.tools .. " tools, first " .. ctx.tools[1] .. " in {{cwd}}"
+end
+"#,
+    )
+    .unwrap();
+    fs::write(dir.path().join("system.md"), "ignored").unwrap();
+
+    let preamble = PromptLoader::new(dir.path()).load(&variables()).unwrap();
+
+    assert_eq!(
+        preamble.as_deref(),
+        Some("Branch main with 2 tools, first read_file in /work/project")
+    );
+}
+
+#[test]
+fn test_prompt_fallbacks() {
+    let dir = TempDir::new().unwrap();
+    let loader = PromptLoader::new(dir.path());
+    assert_eq!(loader.load(&variables()).unwrap(), None);
+    let default = loader.load_or_default(&variables());
+    assert!(default.starts_with("Your name is Kota."));
+    assert!(default.contains("Available tools: read_file, exec_cmd"));
+    assert!(DEFAULT_PREAMBLE.contains("{{tools}}"));
+
+    // An empty init.lua does not hide system.md
+    fs::write(dir.path().join("init.lua"), "").unwrap();
+    fs::write(dir.path().join("system.md"), "From markdown").unwrap();
+    assert_eq!(
+        loader.load(&variables()).unwrap().as_deref(),
+        Some("From markdown")
+    );
+
+    fs::write(dir.path().join("init.lua"), "return 42").unwrap();
+    assert!(loader.load(&variables()).is_err());
+    assert!(loader
+        .load_or_default(&variables())
+        .starts_with("Your name is Kota."));
+}
+
+#[test]
+fn test_git_branch() {
+    let dir = TempDir::new().unwrap();
+    assert_eq!(git_branch(dir.path()), None);
+
+    fs::create_dir(dir.path().join(".git")).unwrap();
+    fs::write(dir.path().join(".git/HEAD"), "ref: refs/heads/feature/x\n").unwrap();
+    let nested = dir.path().join("src/bin");
+    fs::create_dir_all(&nested).unwrap();
+    assert_eq!(git_branch(&nested).as_deref(), Some("feature/x"));
+
+    fs::write(dir.path().join(".git/HEAD"), "0123456789abcdef\n").unwrap();
+    assert_eq!(git_branch(dir.path()).as_deref(), Some("0123456"));
+}
+
+#[tokio::test]
+async fn test_builder_uses_prompts_dir() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("system.md"),
+        "Custom prompt, tools: {{tools}}",
+    )
+    .unwrap();
+
+    let mut agent = mock_builder()
+        .with_prompts_dir(dir.path())
+        .with_allowed_tools(vec!["read_file".to_string(), "grep_find".to_string()])
+        .build()
+        .unwrap();
+    agent.chat("hello").await.unwrap();
+
+    assert_eq!(
+        sent_preamble(&agent),
+        "Custom prompt, tools: read_file, grep_find"
+    );
+}
+
+#[tokio::test]
+async fn test_builder_preamble_override() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("system.md"), "From the prompts directory").unwrap();
+
+    let mut agent = mock_builder()
+        .with_prompts_dir(dir.path())
+        .with_preamble("You are a test agent on {{os}}")
+        .build()
+        .unwrap();
+    agent.chat("hello").await.unwrap();
+
+    assert_eq!(
+        sent_preamble(&agent),
+        format!("You are a test agent on {}", std::env::consts::OS)
+    );
+}
@@ -0,0 +1,393 @@
+use kota::kota_code::agent::retry::{parse_retry_after, RetriesExhausted, RetryPolicy};
+use kota::{AgentBuilder, AgentInstance, ContextManager, KotaConfig, Provider};
+use rig::http_client::{HeaderMap, HeaderValue};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A canned HTTP response served by [`StubServer`]
+struct StubResponse {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl StubResponse {
+    fn error(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type", "application/json".to_string())],
+            body: r#"{"error":{"message":"try again later"}}"#.to_string(),
+        }
+    }
+
+    fn rate_limited(retry_after: &str) -> Self {
+        let mut response = Self::error(429);
+        response
+            .headers
+            .push(("Retry-After", retry_after.to_string()));
+        response
+    }
+
+    /// A streamed chat completion answering with `text`
+    fn completion(text: &str) -> Self {
+        let chunk = serde_json::json!({
+            "choices": [{ "index": 0, "delta": { "content": text }, "finish_reason": null }]
+        });
+        let last = serde_json::json!({
+            "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 10, "completion_tokens": 2, "total_tokens": 12 }
+        });
+        Self {
+            status: 200,
+            headers: vec![("Content-Type", "text/event-stream".to_string())],
+            body: format!("data: {}\n\ndata: {}\n\ndata: [DONE]\n\n", chunk, last),
+        }
+    }
+}
+
+/// Minimal HTTP/1.1 server replaying canned responses and recording request bodies
+struct StubServer {
+    base_url: String,
+    requests: Arc<Mutex<Vec<Value>>>,
+}
+
+impl StubServer {
+    async fn start(responses: Vec<StubResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    break;
+                };
+                let body = read_request_body(&mut socket).await;
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_slice(&body).unwrap_or(Value::Null));
+
+                let response = responses
+                    .lock()
+                    .unwrap()
+                    .pop_front()
+                    .unwrap_or_else(|| StubResponse::error(500));
+                let mut raw = format!("HTTP/1.1 {} Stub\r\n", response.status);
+                for (name, value) in &response.headers {
+                    raw.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                raw.push_str(&format!(
+                    "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.body.len(),
+                    response.body
+                ));
+                let _ = socket.write_all(raw.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        Self { base_url, requests }
+    }
+
+    fn models(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r["model"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+}
+
+async fn read_request_body(socket: &mut tokio::net::TcpStream) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = socket.read(&mut chunk).await.unwrap();
+        if n == 0 {
+            return Vec::new();
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+        let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+            continue;
+        };
+        let head = String::from_utf8_lossy(&buffer[..end]).to_lowercase();
+        let length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        while buffer.len() < end + 4 + length {
+            let n = socket.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+        }
+        return buffer[end + 4..].to_vec();
+    }
+}
+
+fn fast_retries(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_secs(2),
+        multiplier: 2.0,
+    }
+}
+
+fn agent_for(server: &StubServer, policy: RetryPolicy, fallbacks: Vec<&str>) -> AgentInstance {
+    AgentBuilder::for_provider(
+        Provider::OpenAICompatible,
+        "test-key".to_string(),
+        "primary-model".to_string(),
+    )
+    .with_api_base(server.base_url.clone())
+    .with_retry_policy(policy)
+    .with_fallback_models(fallbacks.into_iter().map(String::from).collect())
+    .with_tool_progress(false)
+    .build()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_retries_server_errors() {
+    let server = StubServer::start(vec![
+        StubResponse::error(500),
+        StubResponse::error(503),
+        StubResponse::completion("recovered"),
+    ])
+    .await;
+    let mut agent = agent_for(&server, fast_retries(3), vec![]);
+
+    let response = agent.chat("hello").await.unwrap();
+
+    assert_eq!(response.response(), "recovered");
+    assert_eq!(server.models(), vec!["primary-model"; 3]);
+}
+
+#[tokio::test]
+async fn test_honours_retry_after() {
+    let server = StubServer::start(vec![
+        StubResponse::rate_limited("1"),
+        StubResponse::completion("after waiting"),
+    ])
+    .await;
+    let mut agent = agent_for(&server, fast_retries(1), vec![]);
+
+    let started = Instant::now();
+    let response = agent.chat("hello").await.unwrap();
+
+    assert_eq!(response.response(), "after waiting");
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(server.models().len(), 2);
+}
+
+#[tokio::test]
+async fn test_falls_back_to_next_model() {
+    let server = StubServer::start(vec![
+        StubResponse::error(503),
+        StubResponse::error(503),
+        StubResponse::rate_limited("0"),
+        StubResponse::completion("from the fallback"),
+    ])
+    .await;
+    let mut agent = agent_for(
+        &server,
+        fast_retries(1),
+        vec!["backup-model", "last-resort-model"],
+    );
+
+    let response = agent.chat("hello").await.unwrap();
+
+    assert_eq!(response.response(), "from the fallback");
+    assert_eq!(
+        server.models(),
+        vec![
+            "primary-model",
+            "primary-model",
+            "backup-model",
+            "backup-model"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_client_errors_are_not_retried() {
+    let server = StubServer::start(vec![
+        StubResponse::error(401),
+        StubResponse::completion("unreachable"),
+    ])
+    .await;
+    let mut agent = agent_for(&server, fast_retries(3), vec!["backup-model"]);
+
+    let error = agent.chat("hello").await.unwrap_err();
+    assert!(!error.is::<RetriesExhausted>());
+    assert_eq!(server.models(), vec!["primary-model"]);
+}
+
+#[tokio::test]
+async fn test_unknown_model_falls_back_without_retrying() {
+    let mut model_not_found = StubResponse::error(400);
+    model_not_found.body =
+        r#"{"error":{"code":"model_not_found","message":"The model does not exist"}}"#.to_string();
+    let server = StubServer::start(vec![
+        StubResponse::error(404),
+        model_not_found,
+        StubResponse::completion("from the last resort"),
+    ])
+    .await;
+    let mut agent = agent_for(
+        &server,
+        fast_retries(3),
+        vec!["backup-model", "last-resort-model"],
+    );
+
+    let response = agent.chat("hello").await.unwrap();
+
+    assert_eq!(response.response(), "from the last resort");
+    assert_eq!(
+        server.models(),
+        vec!["primary-model", "backup-model", "last-resort-model"]
+    );
+}
+
+#[tokio::test]
+async fn test_bad_request_mentioning_the_model_is_not_a_fallback() {
+    let mut bad_request = StubResponse::error(400);
+    bad_request.body =
+        r#"{"error":{"code":"invalid_request_error","message":"max_tokens is too large for this model"}}"#
+            .to_string();
+    let server =
+        StubServer::start(vec![bad_request, StubResponse::completion("unreachable")]).await;
+    let mut agent = agent_for(&server, fast_retries(3), vec!["backup-model"]);
+
+    let error = agent.chat("hello").await.unwrap_err();
+
+    assert!(error.to_string().contains("max_tokens is too large"));
+    assert_eq!(server.models(), vec!["primary-model"]);
+}
+
+#[tokio::test]
+async fn test_usage_is_recorded_under_the_fallback_model() {
+    let temp_dir = TempDir::new().unwrap();
+    let server = StubServer::start(vec![
+        StubResponse::error(404),
+        StubResponse::completion("from the backup"),
+        StubResponse::completion("from the primary"),
+    ])
+    .await;
+    let context = ContextManager::new(temp_dir.path(), "fallback".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(
+        Provider::OpenAICompatible,
+        "test-key".to_string(),
+        "primary-model".to_string(),
+    )
+    .with_api_base(server.base_url.clone())
+    .with_retry_policy(fast_retries(1))
+    .with_fallback_models(vec!["backup-model".to_string()])
+    .with_context(context)
+    .with_tool_progress(false)
+    .build()
+    .unwrap();
+
+    agent.chat("hello").await.unwrap();
+    // Each turn reports the model that answered it, not the one of an earlier turn
+    agent.chat("hello again").await.unwrap();
+
+    let usage = agent.session_usage().unwrap();
+    assert_eq!(usage.turns.len(), 2);
+    assert_eq!(usage.turns[0].model, "backup-model");
+    assert_eq!(usage.turns[1].model, "primary-model");
+    assert_eq!(usage.total.input_tokens, 20);
+}
+
+#[tokio::test]
+async fn test_gives_up_after_all_models_fail() {
+    let server = StubServer::start(vec![]).await;
+    let mut agent = agent_for(&server, fast_retries(1), vec!["backup-model"]);
+
+    let error = agent.chat("hello").await.unwrap_err();
+    let exhausted = error.downcast_ref::<RetriesExhausted>().unwrap();
+    assert_eq!(exhausted.attempts, 4);
+    assert_eq!(exhausted.models, 2);
+    assert!(exhausted.message.contains("try again later"));
+    assert_eq!(
+        server.models(),
+        vec![
+            "primary-model",
+            "primary-model",
+            "backup-model",
+            "backup-model"
+        ]
+    );
+}
+
+#[test]
+fn test_parse_retry_after() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(parse_retry_after(&headers), None);
+
+    headers.insert("retry-after", HeaderValue::from_static("7"));
+    assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(7)));
+
+    headers.insert(
+        "retry-after",
+        HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+    );
+    assert_eq!(parse_retry_after(&headers), Some(Duration::ZERO));
+}
+
+#[test]
+fn test_backoff_is_exponential_and_capped() {
+    let policy = RetryPolicy::default();
+    assert_eq!(policy.backoff(0), Duration::from_secs(1));
+    assert_eq!(policy.backoff(1), Duration::from_secs(2));
+    assert_eq!(policy.backoff(2), Duration::from_secs(4));
+    assert_eq!(policy.backoff(10), Duration::from_secs(30));
+}
+
+#[test]
+fn test_lua_config_retry_and_fallbacks() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.lua");
+    fs::write(
+        &config_path,
+        r#"
+kota.setup({
+  model = "gpt-4o",
+  api_key = "test-key",
+  retry = { max_retries = 5, initial_backoff_ms = 250, max_backoff_ms = 10000 },
+  fallback_models = { "gpt-4o-mini", "gpt-3.5-turbo", "o1", "o1-mini", "gpt-4", "gpt-4-turbo" },
+})
+"#,
+    )
+    .unwrap();
+
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+
+    assert_eq!(config.retry.max_retries, 5);
+    assert_eq!(config.retry.initial_backoff, Duration::from_millis(250));
+    assert_eq!(config.retry.max_backoff, Duration::from_secs(10));
+    assert_eq!(
+        config.fallback_models,
+        vec![
+            "gpt-4o-mini",
+            "gpt-3.5-turbo",
+            "o1",
+            "o1-mini",
+            "gpt-4",
+            "gpt-4-turbo"
+        ]
+    );
+}
@@ -0,0 +1,204 @@
+use kota::kota_cli::KotaCli;
+use kota::kota_code::agent::mock::MockScript;
+use kota::kota_code::context::ContextManager;
+use kota::kota_code::store::{FileStore, MemoryStore, SessionConflict, SessionStore, SqliteStore};
+use kota::{AgentBuilder, KotaConfig, Provider};
+use rig::completion::Message;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Two handles on the same sessions, as two kota processes would open them
+fn store_pairs(dir: &TempDir) -> Vec<(Arc<dyn SessionStore>, Arc<dyn SessionStore>)> {
+    let files = dir.path().join("files");
+    let database = dir.path().join("sessions.db");
+    let memory: Arc<dyn SessionStore> = Arc::new(MemoryStore::new());
+    vec![
+        (
+            Arc::new(FileStore::new(&files).unwrap()),
+            Arc::new(FileStore::new(&files).unwrap()),
+        ),
+        (
+            Arc::new(SqliteStore::open(&database).unwrap()),
+            Arc::new(SqliteStore::open(&database).unwrap()),
+        ),
+        (memory.clone(), memory),
+    ]
+}
+
+fn open(store: &Arc<dyn SessionStore>, session_id: &str) -> ContextManager {
+    let mut context = ContextManager::from_store(store.clone(), session_id.to_string());
+    context.load().unwrap();
+    context
+}
+
+#[test]
+fn test_save_detects_changes_by_another_process() {
+    let temp_dir = TempDir::new().unwrap();
+    for (first, second) in store_pairs(&temp_dir) {
+        let mut context = ContextManager::from_store(first.clone(), "shared".to_string());
+        context.add_message(Message::user("Start"));
+        context.save().unwrap();
+
+        let mut mine = open(&first, "shared");
+        let mut theirs = open(&second, "shared");
+        assert!(!mine.changed_in_store().unwrap());
+
+        theirs.add_message(Message::user("Their turn"));
+        theirs.save().unwrap();
+        assert!(mine.changed_in_store().unwrap());
+
+        mine.add_message(Message::user("My turn"));
+        let error = mine.save().unwrap_err();
+        let conflict = error.downcast_ref::<SessionConflict>().unwrap();
+        assert_eq!(conflict.session_id, "shared");
+        assert_eq!(conflict.expected, Some(1));
+        assert_eq!(conflict.found, Some(2));
+        assert_eq!(
+            open(&second, "shared").get_messages().len(),
+            2,
+            "{:?}",
+            first
+        );
+
+        // 覆盖之后轮到另一个进程发现冲突
+        mine.force_save().unwrap();
+        assert_eq!(
+            open(&second, "shared").get_messages()[1],
+            Message::user("My turn")
+        );
+        mine.save().unwrap();
+        assert!(theirs.save().unwrap_err().is::<SessionConflict>());
+    }
+}
+
+#[test]
+fn test_save_detects_created_and_deleted_sessions() {
+    let temp_dir = TempDir::new().unwrap();
+    for (first, second) in store_pairs(&temp_dir) {
+        let mut mine = ContextManager::from_store(first.clone(), "new".to_string());
+        let mut theirs = ContextManager::from_store(second.clone(), "new".to_string());
+        theirs.save().unwrap();
+        let error = mine.save().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<SessionConflict>().unwrap().expected,
+            None
+        );
+
+        let mut mine = open(&first, "new");
+        assert!(theirs.delete_session().unwrap());
+        let error = mine.save().unwrap_err();
+        assert_eq!(error.downcast_ref::<SessionConflict>().unwrap().found, None);
+        mine.force_save().unwrap();
+        assert!(second.exists("new").unwrap());
+    }
+}
+
+#[test]
+fn test_concurrent_writers_never_lose_a_turn() {
+    let temp_dir = TempDir::new().unwrap();
+    for (first, second) in store_pairs(&temp_dir) {
+        let handles: Vec<_> = (0..8)
+            .map(|n| {
+                let store = if n % 2 == 0 {
+                    first.clone()
+                } else {
+                    second.clone()
+                };
+                std::thread::spawn(move || loop {
+                    // 冲突时重新加载再写入, 与 CLI 中重新加载会话一样
+                    let mut context = open(&store, "busy");
+                    context.add_message(Message::user(format!("turn {}", n)));
+                    match context.save() {
+                        Ok(()) => break,
+                        Err(e) if e.is::<SessionConflict>() => continue,
+                        Err(e) => panic!("{}", e),
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let context = open(&first, "busy");
+        assert_eq!(context.get_messages().len(), 8, "{:?}", first);
+        assert_eq!(first.revision("busy").unwrap(), Some(8));
+    }
+}
+
+#[test]
+fn test_file_store_writes_atomically() {
+    let temp_dir = TempDir::new().unwrap();
+    let store: Arc<dyn SessionStore> = Arc::new(FileStore::new(temp_dir.path()).unwrap());
+    let mut context = ContextManager::from_store(store.clone(), "main".to_string());
+    context.add_message(Message::user("Hello"));
+    context.save().unwrap();
+    context.save().unwrap();
+
+    // 只剩会话文件和锁文件, 没有残留的临时文件
+    let mut files: Vec<String> = std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    assert_eq!(files, vec![".main.lock", "main.json"]);
+    assert_eq!(store.revision("main").unwrap(), Some(2));
+    assert_eq!(store.list().unwrap().len(), 1);
+}
+
+#[test]
+fn test_sessions_without_revision_start_at_zero() {
+    let temp_dir = TempDir::new().unwrap();
+    let legacy = serde_json::json!({
+        "version": 2,
+        "metadata": {
+            "session_id": "legacy",
+            "created_at": "2024-01-01T00:00:00+00:00",
+            "last_updated": "2024-01-01T00:00:00+00:00",
+            "message_count": 0
+        },
+        "messages": []
+    });
+    std::fs::write(
+        temp_dir.path().join("legacy.json"),
+        serde_json::to_string(&legacy).unwrap(),
+    )
+    .unwrap();
+
+    let store: Arc<dyn SessionStore> = Arc::new(FileStore::new(temp_dir.path()).unwrap());
+    assert_eq!(store.revision("legacy").unwrap(), Some(0));
+    let mut context = open(&store, "legacy");
+    context.save().unwrap();
+    assert_eq!(store.revision("legacy").unwrap(), Some(1));
+}
+
+#[tokio::test]
+async fn test_load_keeps_the_session_when_overwrite_is_declined() {
+    let store: Arc<dyn SessionStore> = Arc::new(MemoryStore::new());
+    let mut context = ContextManager::from_store(store.clone(), "shared".to_string());
+    context.add_message(Message::user("Start"));
+    context.save().unwrap();
+
+    let mut mine = open(&store, "shared");
+    mine.add_message(Message::user("My turn"));
+    let mut theirs = open(&store, "shared");
+    theirs.add_message(Message::user("Their turn"));
+    theirs.save().unwrap();
+
+    let agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(Vec::new()))
+        .with_context(mine)
+        .build()
+        .unwrap();
+    let mut cli = KotaCli::from_agent(agent, &KotaConfig::default()).with_input(&b"n\n"[..]);
+
+    cli.handle_command("/load other").await.unwrap();
+
+    let context = cli.agent_instance.context().unwrap();
+    assert_eq!(context.session_id(), "shared");
+    assert_eq!(
+        context.get_messages(),
+        &[Message::user("Start"), Message::user("My turn")]
+    );
+    assert!(!store.exists("other").unwrap());
+}
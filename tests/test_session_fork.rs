@@ -0,0 +1,105 @@
+use kota::kota_code::context::{session_tree, ContextManager};
+use rig::completion::message::{AssistantContent, ToolResultContent, UserContent};
+use rig::completion::Message;
+use rig::OneOrMany;
+use tempfile::TempDir;
+
+/// request, tool call, tool result, answer, request, answer
+fn session(dir: &TempDir) -> ContextManager {
+    let mut context = ContextManager::new(dir.path(), "main".to_string()).unwrap();
+    context.add_message(Message::user("Read the config"));
+    context.add_message(Message::Assistant {
+        id: None,
+        content: OneOrMany::one(AssistantContent::tool_call(
+            "call_1",
+            "read_file",
+            serde_json::json!({"path": "config.lua"}),
+        )),
+    });
+    context.add_message(Message::User {
+        content: OneOrMany::one(UserContent::tool_result(
+            "call_1",
+            OneOrMany::one(ToolResultContent::text("kota.setup({})")),
+        )),
+    });
+    context.add_message(Message::assistant("The config is empty"));
+    context.add_message(Message::user("Rewrite it in TOML"));
+    context.add_message(Message::assistant("Done, config.toml replaces it"));
+    context.add_tag("config");
+    context.save().unwrap();
+    context
+}
+
+#[test]
+fn test_fork_copies_history_up_to_message() {
+    let temp_dir = TempDir::new().unwrap();
+    let context = session(&temp_dir);
+
+    let fork = context.fork(3, context.next_fork_id()).unwrap();
+    assert_eq!(fork.session_id(), "main-fork-1");
+    assert_eq!(fork.get_messages(), &context.get_messages()[..4]);
+    assert_eq!(fork.parent_session_id(), Some("main"));
+    assert_eq!(fork.forked_at(), Some(4));
+    assert_eq!(fork.tags(), ["config"]);
+    assert_eq!(context.get_messages().len(), 6);
+
+    // A fork at a tool call keeps the results of the call
+    let fork = context.fork(1, context.next_fork_id()).unwrap();
+    assert_eq!(fork.session_id(), "main-fork-2");
+    assert_eq!(fork.get_messages().len(), 3);
+
+    let mut loaded = ContextManager::new(temp_dir.path(), "main-fork-1".to_string()).unwrap();
+    assert!(loaded.load().unwrap());
+    assert_eq!(loaded.get_messages(), &context.get_messages()[..4]);
+    assert_eq!(loaded.parent_session_id(), Some("main"));
+    assert_eq!(loaded.forked_at(), Some(4));
+}
+
+#[test]
+fn test_fork_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    let context = session(&temp_dir);
+
+    let err = context.fork(6, "other".to_string()).unwrap_err();
+    assert!(err.to_string().contains("the session has 6 messages"));
+    let err = context.fork(0, "main".to_string()).unwrap_err();
+    assert!(err.to_string().contains("already exists"));
+    assert_eq!(context.list_sessions().unwrap().len(), 1);
+}
+
+#[test]
+fn test_session_tree() {
+    let temp_dir = TempDir::new().unwrap();
+    let context = session(&temp_dir);
+    let fork = context.fork(0, "retry".to_string()).unwrap();
+    fork.fork(0, "retry-again".to_string()).unwrap();
+    context.fork(3, "alternative".to_string()).unwrap();
+    ContextManager::new(temp_dir.path(), "main-task-1".to_string())
+        .unwrap()
+        .with_parent_session("main".to_string())
+        .save()
+        .unwrap();
+    ContextManager::new(temp_dir.path(), "orphan".to_string())
+        .unwrap()
+        .with_parent_session("deleted".to_string())
+        .save()
+        .unwrap();
+
+    let mut sessions = context.list_sessions().unwrap();
+    sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+    let tree: Vec<(usize, String)> = session_tree(sessions)
+        .into_iter()
+        .map(|(depth, session)| (depth, session.session_id))
+        .collect();
+    assert_eq!(
+        tree,
+        vec![
+            (0, "main".to_string()),
+            (1, "alternative".to_string()),
+            (1, "main-task-1".to_string()),
+            (1, "retry".to_string()),
+            (2, "retry-again".to_string()),
+            (0, "orphan".to_string()),
+        ]
+    );
+}
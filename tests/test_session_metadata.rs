@@ -0,0 +1,127 @@
+use chrono::{Duration, TimeZone, Utc};
+use kota::kota_code::context::ContextManager;
+use kota::kota_code::search::{parse_since, SessionFilter};
+use kota::kota_code::usage::TokenUsage;
+use rig::completion::Message;
+use tempfile::TempDir;
+
+fn usage(input_tokens: u64, output_tokens: u64) -> TokenUsage {
+    TokenUsage {
+        input_tokens,
+        output_tokens,
+        cached_input_tokens: 0,
+    }
+}
+
+#[test]
+fn test_metadata_survives_saves() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path().join("repo");
+    std::fs::create_dir_all(repo.join(".git")).unwrap();
+    std::fs::write(repo.join(".git/HEAD"), "ref: refs/heads/feature/login\n").unwrap();
+
+    let mut context = ContextManager::new(temp_dir.path(), "meta".to_string())
+        .unwrap()
+        .with_working_dir(&repo);
+    context.add_message(Message::user(format!(
+        "Fix the login\n   form {}",
+        "x".repeat(100)
+    )));
+    context.add_message(Message::assistant("Done"));
+    context.record_usage("gpt-4o", usage(100, 20));
+    context.set_active_skills(vec!["rust".to_string()]);
+    assert!(context.add_tag("bug"));
+    assert!(!context.add_tag("bug"));
+    context.save().unwrap();
+    let created_at = context.created_at().to_string();
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    context.save().unwrap();
+
+    let sessions = context.list_sessions().unwrap();
+    let session = &sessions[0];
+    assert_eq!(session.created_at, created_at);
+    assert!(session.last_updated > session.created_at);
+    let title = session.title.as_deref().unwrap();
+    assert!(title.starts_with("Fix the login form xxx"));
+    assert!(title.ends_with('…'));
+    assert_eq!(title.chars().count(), 61);
+    assert_eq!(session.model.as_deref(), Some("gpt-4o"));
+    assert_eq!(session.total_tokens(), 120);
+    assert_eq!(session.active_skills, vec!["rust"]);
+    assert_eq!(session.working_dir.as_deref(), Some(repo.as_path()));
+    assert_eq!(session.git_branch.as_deref(), Some("feature/login"));
+    assert_eq!(session.tags, vec!["bug"]);
+
+    let mut loaded = ContextManager::new(temp_dir.path(), "meta".to_string()).unwrap();
+    assert!(loaded.load().unwrap());
+    loaded.set_title("Login fixes");
+    assert!(loaded.remove_tag("bug"));
+    loaded.save().unwrap();
+    let session = &loaded.list_sessions().unwrap()[0];
+    assert_eq!(session.created_at, created_at);
+    assert_eq!(session.title.as_deref(), Some("Login fixes"));
+    assert_eq!(session.working_dir.as_deref(), Some(repo.as_path()));
+    assert!(session.tags.is_empty());
+}
+
+#[test]
+fn test_filter_sessions() {
+    let temp_dir = TempDir::new().unwrap();
+    for (session_id, model, tags) in [
+        ("a", "gpt-4o", vec!["bug", "ui"]),
+        ("b", "claude-sonnet", vec!["bug"]),
+        ("c", "gpt-4o-mini", vec![]),
+    ] {
+        let mut context = ContextManager::new(temp_dir.path(), session_id.to_string()).unwrap();
+        context.add_message(Message::user("hello"));
+        context.record_usage(model, usage(1, 1));
+        for tag in tags {
+            context.add_tag(tag);
+        }
+        context.save().unwrap();
+    }
+    let context = ContextManager::new(temp_dir.path(), "current".to_string()).unwrap();
+    let ids = |args: &str| -> Vec<String> {
+        let mut ids: Vec<String> = context
+            .find_sessions(&SessionFilter::parse(args).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|session| session.session_id)
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    assert_eq!(ids(""), vec!["a", "b", "c"]);
+    assert_eq!(ids("--tag bug"), vec!["a", "b"]);
+    assert_eq!(ids("--tag bug --tag ui"), vec!["a"]);
+    assert_eq!(ids("--model GPT"), vec!["a", "c"]);
+    assert_eq!(ids("--since 1h --tag bug --model claude"), vec!["b"]);
+    assert!(ids("--since 2999-01-01").is_empty());
+    assert!(ids("--branch main").is_empty());
+}
+
+#[test]
+fn test_parse_filter_arguments() {
+    let now = Utc.with_ymd_and_hms(2024, 5, 10, 12, 0, 0).unwrap();
+    assert_eq!(parse_since("7d", now).unwrap(), now - Duration::days(7));
+    assert_eq!(parse_since("12h", now).unwrap(), now - Duration::hours(12));
+    assert_eq!(parse_since("2w", now).unwrap(), now - Duration::weeks(2));
+    assert_eq!(
+        parse_since("2024-05-01", now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()
+    );
+    assert_eq!(
+        parse_since("2024-05-01T08:00:00+02:00", now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 5, 1, 6, 0, 0).unwrap()
+    );
+    assert!(parse_since("yesterday", now).is_err());
+    assert!(parse_since("7", now).is_err());
+    assert!(parse_since("999999999999d", now).is_err());
+    assert!(parse_since("9999999999999w", now).is_err());
+
+    assert!(SessionFilter::parse("--tag").is_err());
+    assert!(SessionFilter::parse("--color red").is_err());
+    assert!(SessionFilter::parse("").unwrap().is_empty());
+}
@@ -0,0 +1,116 @@
+use kota::kota_code::context::ContextManager;
+use rig::completion::message::{ToolResultContent, UserContent};
+use rig::completion::Message;
+use rig::OneOrMany;
+use tempfile::TempDir;
+
+fn save_session(dir: &TempDir, session_id: &str, messages: Vec<Message>) {
+    let mut context = ContextManager::new(dir.path(), session_id.to_string()).unwrap();
+    for message in messages {
+        context.add_message(message);
+    }
+    context.save().unwrap();
+}
+
+fn sessions() -> TempDir {
+    let temp_dir = TempDir::new().unwrap();
+    save_session(
+        &temp_dir,
+        "parser-fix",
+        vec![
+            Message::user("The Parser crashes on nested lists"),
+            Message::assistant("I fixed the parser: nested lists now recurse properly."),
+            Message::user("Add a parser test too"),
+            Message::assistant("Done."),
+        ],
+    );
+    save_session(
+        &temp_dir,
+        "readme",
+        vec![
+            Message::user("Mention the parser in the README"),
+            Message::assistant("Updated the README."),
+        ],
+    );
+    save_session(
+        &temp_dir,
+        "tools",
+        vec![
+            Message::user("Run the tests"),
+            Message::User {
+                content: OneOrMany::one(UserContent::tool_result(
+                    "call_1",
+                    OneOrMany::one(ToolResultContent::text("test result: FAILED in lexer.rs")),
+                )),
+            },
+        ],
+    );
+    temp_dir
+}
+
+#[test]
+fn test_search_ranks_sessions_by_matches() {
+    let temp_dir = sessions();
+    let context = ContextManager::new(temp_dir.path(), "current".to_string()).unwrap();
+
+    let results = context.search_sessions("parser").unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.session_id.as_str()).collect();
+    assert_eq!(ids, vec!["parser-fix", "readme"]);
+
+    let best = &results[0];
+    assert_eq!(best.matching_messages, 3);
+    // Equal scores keep the history order
+    assert_eq!(best.hits[0].index, 0);
+    assert_eq!(best.hits[0].role, "user");
+    assert_eq!(best.hits[0].snippet, "The Parser crashes on nested lists");
+}
+
+#[test]
+fn test_search_prefers_messages_with_every_term() {
+    let temp_dir = sessions();
+    let context = ContextManager::new(temp_dir.path(), "current".to_string()).unwrap();
+
+    let results = context.search_sessions("PARSER nested").unwrap();
+    assert_eq!(results[0].session_id, "parser-fix");
+    let hits = &results[0].hits;
+    let indexes: Vec<usize> = hits.iter().map(|hit| hit.index).collect();
+    assert_eq!(indexes, vec![0, 1, 2]);
+    assert!(hits[1].score > hits[2].score);
+
+    assert!(context.search_sessions("   ").unwrap().is_empty());
+    assert!(context.search_sessions("tokenizer").unwrap().is_empty());
+}
+
+#[test]
+fn test_search_tool_results_and_snippets() {
+    let temp_dir = sessions();
+    save_session(
+        &temp_dir,
+        "long",
+        vec![Message::user(format!(
+            "{} the lexer\nbreaks on unicode {}",
+            "a".repeat(100),
+            "b".repeat(100)
+        ))],
+    );
+    let context = ContextManager::new(temp_dir.path(), "current".to_string()).unwrap();
+
+    let results = context.search_sessions("lexer").unwrap();
+    assert_eq!(results.len(), 2);
+    let tool_hit = results
+        .iter()
+        .find(|r| r.session_id == "tools")
+        .map(|r| &r.hits[0])
+        .unwrap();
+    assert_eq!(tool_hit.index, 1);
+    assert_eq!(tool_hit.role, "tool");
+
+    let long_hit = &results
+        .iter()
+        .find(|r| r.session_id == "long")
+        .unwrap()
+        .hits[0];
+    assert!(long_hit.snippet.starts_with('…'));
+    assert!(long_hit.snippet.ends_with('…'));
+    assert!(long_hit.snippet.contains("the lexer breaks on unicode"));
+}
@@ -0,0 +1,186 @@
+use kota::kota_code::agent::mock::{MockScript, MockTurn};
+use kota::kota_code::context::ContextManager;
+use kota::kota_code::store::{
+    FileStore, MemoryStore, SessionStore, SqliteStore, StoreConfig, DEFAULT_SQLITE_PATH,
+};
+use kota::{AgentBuilder, KotaConfig, Provider};
+use rig::completion::message::{AssistantContent, ToolResultContent, UserContent};
+use rig::completion::Message;
+use rig::OneOrMany;
+use serde_json::json;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn stores(dir: &TempDir) -> Vec<Arc<dyn SessionStore>> {
+    vec![
+        Arc::new(FileStore::new(dir.path().join("files")).unwrap()),
+        Arc::new(SqliteStore::open(dir.path().join("db/sessions.db")).unwrap()),
+        Arc::new(MemoryStore::new()),
+    ]
+}
+
+fn messages() -> Vec<Message> {
+    vec![
+        Message::user("Why does the 100% coverage_report fail?"),
+        Message::Assistant {
+            id: None,
+            content: OneOrMany::one(AssistantContent::tool_call(
+                "call_1",
+                "exec_cmd",
+                json!({ "command": "cargo test" }),
+            )),
+        },
+        Message::User {
+            content: OneOrMany::one(UserContent::tool_result(
+                "call_1",
+                OneOrMany::one(ToolResultContent::text("thread panicked in Parser::parse")),
+            )),
+        },
+        Message::assistant("The parser panics on empty input."),
+    ]
+}
+
+#[test]
+fn test_stores_round_trip_sessions() {
+    let temp_dir = TempDir::new().unwrap();
+    for store in stores(&temp_dir) {
+        let mut context = ContextManager::from_store(store.clone(), "main".to_string());
+        for message in messages() {
+            context.add_message(message);
+        }
+        context.add_tag("parser");
+        context.save().unwrap();
+        // Saving again replaces the session
+        context.save().unwrap();
+
+        let mut loaded = ContextManager::from_store(store.clone(), "main".to_string());
+        assert!(loaded.load().unwrap(), "{:?}", store);
+        assert_eq!(loaded.get_messages(), messages().as_slice());
+        assert_eq!(loaded.tags(), ["parser"]);
+        assert_eq!(loaded.created_at(), context.created_at());
+
+        let fork = context.fork(0, context.next_fork_id()).unwrap();
+        assert!(store.exists("main-fork-1").unwrap());
+        assert_eq!(context.next_fork_id(), "main-fork-2");
+
+        let sessions = context.list_sessions().unwrap();
+        let ids: Vec<&str> = sessions.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["main-fork-1", "main"], "{:?}", store);
+
+        assert!(fork.delete_session().unwrap());
+        assert!(!fork.delete_session().unwrap());
+        assert!(!store.exists("main-fork-1").unwrap());
+        assert!(store.load("main-fork-1").unwrap().is_none());
+        assert_eq!(context.list_sessions().unwrap().len(), 1);
+    }
+}
+
+#[test]
+fn test_stores_search_messages() {
+    let temp_dir = TempDir::new().unwrap();
+    for store in stores(&temp_dir) {
+        let mut context = ContextManager::from_store(store.clone(), "main".to_string());
+        for message in messages() {
+            context.add_message(message);
+        }
+        context.save().unwrap();
+        let mut other = ContextManager::from_store(store.clone(), "other".to_string());
+        other.add_message(Message::user("Write the changelog"));
+        other.save().unwrap();
+
+        let results = context.search_sessions("parser").unwrap();
+        assert_eq!(results.len(), 1, "{:?}", store);
+        assert_eq!(results[0].session_id, "main");
+        assert_eq!(results[0].matching_messages, 2);
+        assert_eq!(results[0].hits[0].role, "tool");
+
+        // LIKE wildcards in the query are matched literally
+        assert_eq!(context.search_sessions("100%").unwrap().len(), 1);
+        assert_eq!(context.search_sessions("coverage_").unwrap().len(), 1);
+        assert!(context.search_sessions("100_").unwrap().is_empty());
+        assert!(!context.search_sessions("%").unwrap().is_empty());
+        assert!(context.search_sessions("lexer").unwrap().is_empty());
+    }
+}
+
+#[test]
+fn test_sqlite_store_is_shared_between_connections() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("sessions.db");
+    let first: Arc<dyn SessionStore> = Arc::new(SqliteStore::open(&path).unwrap());
+    let second: Arc<dyn SessionStore> = Arc::new(SqliteStore::open(&path).unwrap());
+
+    let handles: Vec<_> = (0..8)
+        .map(|n| {
+            let store = if n % 2 == 0 {
+                first.clone()
+            } else {
+                second.clone()
+            };
+            std::thread::spawn(move || {
+                let mut context = ContextManager::from_store(store, format!("worker-{}", n));
+                context.add_message(Message::user(format!("task {}", n)));
+                context.save().unwrap();
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(first.list().unwrap().len(), 8);
+    assert!(second.exists("worker-3").unwrap());
+}
+
+#[tokio::test]
+async fn test_builder_uses_session_store() {
+    let store = Arc::new(MemoryStore::new());
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![MockTurn {
+            text: Some("Hello".to_string()),
+            ..MockTurn::default()
+        }]))
+        .with_session_store(store.clone())
+        .build()
+        .unwrap();
+    agent.chat("hi").await.unwrap();
+
+    let session_id = agent.context().unwrap().session_id().to_string();
+    let saved = store.load(&session_id).unwrap().unwrap();
+    assert_eq!(saved.messages.len(), 2);
+}
+
+#[test]
+fn test_lua_config_session_store() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.lua");
+    let config = |lua: &str| {
+        std::fs::write(&config_path, lua).unwrap();
+        KotaConfig::from_lua_file(&config_path)
+    };
+
+    assert_eq!(
+        config("kota.setup({})").unwrap().session_store,
+        StoreConfig::default()
+    );
+    assert_eq!(
+        config(r#"kota.setup({ session_store = "sqlite" })"#)
+            .unwrap()
+            .session_store,
+        StoreConfig::Sqlite(PathBuf::from(DEFAULT_SQLITE_PATH))
+    );
+    assert_eq!(
+        config(r#"kota.setup({ session_store = { backend = "sqlite", path = "/tmp/kota.db" } })"#)
+            .unwrap()
+            .session_store,
+        StoreConfig::Sqlite(PathBuf::from("/tmp/kota.db"))
+    );
+    assert_eq!(
+        config(r#"kota.setup({ session_store = "memory" })"#)
+            .unwrap()
+            .session_store,
+        StoreConfig::Memory
+    );
+    assert!(config(r#"kota.setup({ session_store = "redis" })"#).is_err());
+}
@@ -0,0 +1,190 @@
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn};
+use kota::kota_code::runtime::CancelSignal;
+use kota::kota_code::tools::SkillScriptTool;
+use kota::{AgentBuilder, AgentType, ProjectMemory, Provider, Skill, SkillManager};
+use rig::tool::ToolDyn;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Creates a `bundle` skill with a reference, a template, a script and a Lua tool
+fn bundle_skill(dir: &Path) -> Skill {
+    fs::create_dir_all(dir.join("scripts")).unwrap();
+    fs::create_dir_all(dir.join("templates")).unwrap();
+    fs::create_dir_all(dir.join("tools")).unwrap();
+    fs::write(dir.join("SKILL.md"), "---\nname: bundle\n---\n").unwrap();
+    fs::write(dir.join("REFERENCE.md"), "Use snake_case").unwrap();
+    fs::write(dir.join("templates/report.md"), "# Report").unwrap();
+    fs::write(
+        dir.join("scripts/greet.sh"),
+        "#!/usr/bin/env bash\n# Greet someone by name\necho \"hello $1 from $(basename \"$KOTA_SKILL_DIR\")\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("tools/init.lua"),
+        r#"
+kota.register_tool({
+    name = "bundle_shout",
+    description = "Upper-case a text",
+    parameters = { type = "object", properties = { text = { type = "string" } } },
+    entry = function(args) return { text = string.upper(args.text) } end
+})
+"#,
+    )
+    .unwrap();
+
+    Skill {
+        name: "bundle".to_string(),
+        description: "A skill with resources".to_string(),
+        instructions: "Follow the reference".to_string(),
+        dir: Some(dir.to_path_buf()),
+        ..Skill::default()
+    }
+}
+
+fn tool_call(name: &str, arguments: serde_json::Value, expect: &str) -> MockTurn {
+    MockTurn {
+        tool_calls: vec![MockToolCall {
+            id: None,
+            name: name.to_string(),
+            arguments,
+            expect_result: Some(expect.to_string()),
+        }],
+        ..MockTurn::default()
+    }
+}
+
+fn text(text: &str) -> MockTurn {
+    MockTurn {
+        text: Some(text.to_string()),
+        ..MockTurn::default()
+    }
+}
+
+#[test]
+fn test_skill_resources_and_scripts() {
+    let temp_dir = TempDir::new().unwrap();
+    let skill = bundle_skill(&temp_dir.path().join("bundle"));
+
+    assert_eq!(
+        skill.resources(),
+        vec!["REFERENCE.md", "scripts/greet.sh", "templates/report.md"]
+    );
+    assert_eq!(
+        skill.read_resource("templates/report.md").unwrap(),
+        "# Report"
+    );
+    assert!(skill.read_resource("../bundle/REFERENCE.md").is_err());
+    let err = skill.read_resource("MISSING.md").unwrap_err();
+    assert!(err.to_string().contains("available: REFERENCE.md"));
+
+    let scripts = skill.scripts();
+    assert_eq!(scripts.len(), 1);
+    assert_eq!(scripts[0].tool_name, "bundle_greet");
+    assert_eq!(scripts[0].description, "Greet someone by name");
+    assert!(skill.tools_file().is_some());
+
+    // Skills without a directory have no bundle
+    assert!(Skill::default().resources().is_empty());
+    assert!(Skill::default().scripts().is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_active_skill_registers_its_tools() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut skills = SkillManager::new();
+    skills.add_skill(bundle_skill(&temp_dir.path().join("bundle")));
+    skills.activate_skill("bundle").unwrap();
+
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![
+            tool_call(
+                "read_skill_resource",
+                json!({ "skill": "bundle", "path": "REFERENCE.md" }),
+                "Use snake_case",
+            ),
+            tool_call(
+                "bundle_greet",
+                json!({ "args": ["kota"] }),
+                "hello kota from bundle",
+            ),
+            tool_call("bundle_shout", json!({ "text": "hi" }), "HI"),
+            text("done"),
+            text("plain"),
+        ]))
+        .with_memory(ProjectMemory::default())
+        .with_sub_agents(false)
+        .with_tool_progress(false)
+        .with_skill_manager(skills)
+        .build()
+        .unwrap();
+
+    agent.chat("use the bundle").await.unwrap();
+    assert_eq!(agent.skill_tool_names(), ["bundle_shout", "bundle_greet"]);
+
+    agent
+        .skill_manager_mut()
+        .unwrap()
+        .deactivate_skill("bundle")
+        .unwrap();
+    agent.chat("again").await.unwrap();
+    assert!(agent.skill_tool_names().is_empty());
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    let requests = inner.model.requests();
+    let tool_names =
+        |i: usize| -> Vec<String> { requests[i].tools.iter().map(|t| t.name.clone()).collect() };
+    assert!(tool_names(0).contains(&"bundle_greet".to_string()));
+    assert!(tool_names(0).contains(&"bundle_shout".to_string()));
+    assert!(tool_names(0).contains(&"read_skill_resource".to_string()));
+    assert!(requests[0]
+        .preamble
+        .as_deref()
+        .unwrap()
+        .contains("REFERENCE.md, templates/report.md"));
+
+    assert!(!tool_names(4).contains(&"bundle_greet".to_string()));
+    assert!(!tool_names(4).contains(&"bundle_shout".to_string()));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_skill_script_stdin_never_blocks() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("pipes");
+    fs::create_dir_all(dir.join("scripts")).unwrap();
+    // Fills the stdout pipe before reading any input
+    fs::write(
+        dir.join("scripts/count.sh"),
+        "head -c 200000 /dev/zero | tr '\\0' x\necho\nwc -c\n",
+    )
+    .unwrap();
+    fs::write(dir.join("scripts/ignore.sh"), "echo ignored\n").unwrap();
+    let skill = Skill {
+        name: "pipes".to_string(),
+        dir: Some(dir.clone()),
+        ..Skill::default()
+    };
+
+    let input = "y".repeat(300_000);
+    for script in skill.scripts() {
+        let tool = SkillScriptTool::new(script, dir.clone(), CancelSignal::new());
+        let args = json!({ "stdin": input }).to_string();
+        let output = tokio::time::timeout(Duration::from_secs(10), tool.call(args))
+            .await
+            .expect("script deadlocked")
+            .unwrap();
+        let output: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(output["success"], true, "{}", output);
+        let stdout = output["stdout"].as_str().unwrap().trim_end();
+        match tool.name().as_str() {
+            "pipes_count" => assert!(stdout.ends_with("300000"), "{}", stdout),
+            _ => assert_eq!(stdout, "ignored"),
+        }
+    }
+}
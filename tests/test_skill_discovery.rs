@@ -0,0 +1,179 @@
+use kota::kota_code::agent::mock::{MockScript, MockTurn};
+use kota::kota_code::SkillScope;
+use kota::{AgentBuilder, AgentType, KotaConfig, ProjectMemory, Provider, SkillManager};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn write_skill(dir: &Path, name: &str, description: &str) {
+    fs::create_dir_all(dir.join(name)).unwrap();
+    fs::write(
+        dir.join(name).join("SKILL.md"),
+        format!(
+            "---\nname: {}\ndescription: {}\n---\n\n{} instructions",
+            name, description, description
+        ),
+    )
+    .unwrap();
+}
+
+/// Creates `global`, `team` and `project` skill directories sharing the `dup` skill
+fn skill_dirs() -> TempDir {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    write_skill(&root.join("global"), "shared", "from global");
+    write_skill(&root.join("global"), "dup", "global dup");
+    write_skill(&root.join("team"), "team", "from team");
+    write_skill(&root.join("team"), "dup", "team dup");
+    write_skill(&root.join("project"), "dup", "project dup");
+    temp_dir
+}
+
+fn discover(root: &Path) -> SkillManager {
+    SkillManager::discover(root.join("project"), Some(root.join("global")))
+        .with_skill_dirs(vec![root.join("team")])
+}
+
+#[test]
+fn test_skill_precedence() {
+    let temp_dir = skill_dirs();
+    let manager = discover(temp_dir.path());
+
+    let scopes: Vec<SkillScope> = manager.skill_dirs().iter().map(|d| d.scope).collect();
+    assert_eq!(
+        scopes,
+        vec![SkillScope::Global, SkillScope::Config, SkillScope::Project]
+    );
+
+    let dup = manager.get_skill("dup").unwrap();
+    assert_eq!(dup.description, "project dup");
+    assert_eq!(dup.scope, Some(SkillScope::Project));
+    assert_eq!(
+        manager.get_skill("shared").unwrap().scope,
+        Some(SkillScope::Global)
+    );
+    assert_eq!(
+        manager.get_skill("team").unwrap().scope,
+        Some(SkillScope::Config)
+    );
+    assert_eq!(manager.list_skills().len(), 3);
+}
+
+#[test]
+fn test_missing_dirs_are_not_created() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+
+    let mut manager = SkillManager::discover(&project, None);
+    assert!(manager.list_skills().is_empty());
+    assert!(!project.exists());
+
+    // Creating a skill is the only thing that writes to the project
+    manager
+        .create_skill("notes", "Take notes: short and precise", "Write notes")
+        .unwrap();
+    let notes = manager.get_skill("notes").unwrap();
+    assert_eq!(notes.description, "Take notes: short and precise");
+    assert_eq!(notes.scope, Some(SkillScope::Project));
+    assert!(project.join("notes/SKILL.md").is_file());
+}
+
+#[test]
+fn test_remove_skill_by_scope() {
+    let temp_dir = skill_dirs();
+    let root = temp_dir.path();
+    let mut manager = discover(root);
+
+    let err = manager.remove_skill("shared").unwrap_err();
+    assert!(err.to_string().contains("is a global skill"));
+    assert!(root.join("global/shared").exists());
+
+    // Removing the project skill uncovers the one it overrode
+    manager.remove_skill("dup").unwrap();
+    assert!(!root.join("project/dup").exists());
+    assert_eq!(manager.get_skill("dup").unwrap().description, "team dup");
+}
+
+#[test]
+fn test_reload_if_changed() {
+    let temp_dir = skill_dirs();
+    let root = temp_dir.path();
+    let mut manager = discover(root);
+    manager.activate_skill("team").unwrap();
+    assert!(!manager.reload_if_changed());
+
+    write_skill(&root.join("team"), "team", "edited team skill");
+    write_skill(&root.join("global"), "fresh", "new skill");
+    fs::remove_dir_all(root.join("global/shared")).unwrap();
+    assert!(manager.reload_if_changed());
+
+    assert_eq!(
+        manager.get_skill("team").unwrap().description,
+        "edited team skill"
+    );
+    assert!(manager.get_skill("fresh").is_some());
+    assert!(manager.get_skill("shared").is_none());
+    assert!(manager.is_active("team"));
+    assert!(!manager.reload_if_changed());
+}
+
+#[tokio::test]
+async fn test_edited_skill_applies_on_next_turn() {
+    let temp_dir = skill_dirs();
+    let root = temp_dir.path();
+    let mut skills = discover(root);
+    skills.activate_skill("team").unwrap();
+
+    let turns = (0..2)
+        .map(|_| MockTurn {
+            text: Some("ok".to_string()),
+            ..MockTurn::default()
+        })
+        .collect();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(turns))
+        .with_preamble("Base prompt")
+        .with_memory(ProjectMemory::default())
+        .with_sub_agents(false)
+        .with_tool_progress(false)
+        .with_skill_manager(skills)
+        .build()
+        .unwrap();
+
+    agent.chat("first").await.unwrap();
+    write_skill(&root.join("team"), "team", "rewritten");
+    agent.chat("second").await.unwrap();
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    let requests = inner.model.requests();
+    assert!(requests[0]
+        .preamble
+        .as_deref()
+        .unwrap()
+        .contains("from team instructions"));
+    assert!(requests[1]
+        .preamble
+        .as_deref()
+        .unwrap()
+        .contains("rewritten instructions"));
+}
+
+#[test]
+fn test_lua_config_skill_dirs() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.lua");
+    fs::write(
+        &config_path,
+        r#"kota.setup({ skill_dirs = { "/opt/team-skills", "~/shared-skills" } })"#,
+    )
+    .unwrap();
+
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+    assert_eq!(config.skill_dirs.len(), 2);
+    assert_eq!(config.skill_dirs[0], Path::new("/opt/team-skills"));
+    if let Some(home) = std::env::var_os("HOME") {
+        assert_eq!(config.skill_dirs[1], Path::new(&home).join("shared-skills"));
+    }
+}
@@ -0,0 +1,124 @@
+use kota::kota_code::agent::mock::{MockScript, MockTurn};
+use kota::{AgentBuilder, AgentType, ProjectMemory, Provider, Skill, SkillManager};
+
+#[test]
+fn test_parse_yaml_frontmatter() {
+    let skill = Skill::parse(
+        r#"---
+name: reviewer
+description: >
+  Review code for bugs
+  and style issues
+version: 1.2
+dependencies:
+  - rust-expert
+  - style
+triggers: "review this, code review"
+allowed-tools: [read_file, grep_find]
+disallowed_tools: exec_cmd
+model: gpt-4o
+temperature: 0.2
+license: MIT
+---
+
+# Reviewer
+
+Check the diff.
+
+---
+
+Report findings: severity, file and line.
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(skill.name, "reviewer");
+    assert_eq!(skill.description, "Review code for bugs and style issues\n");
+    assert_eq!(skill.version.as_deref(), Some("1.2"));
+    assert_eq!(skill.dependencies, vec!["rust-expert", "style"]);
+    assert_eq!(skill.triggers, vec!["review this", "code review"]);
+    assert_eq!(skill.allowed_tools, vec!["read_file", "grep_find"]);
+    assert_eq!(skill.disallowed_tools, vec!["exec_cmd"]);
+    assert_eq!(skill.model.as_deref(), Some("gpt-4o"));
+    assert_eq!(skill.temperature, Some(0.2));
+    // `---` inside the body is kept
+    assert!(skill.instructions.starts_with("# Reviewer"));
+    assert!(skill
+        .instructions
+        .contains("---\n\nReport findings: severity"));
+}
+
+#[test]
+fn test_parse_invalid_frontmatter() {
+    let err = Skill::parse("name: x\n---\nbody").unwrap_err();
+    assert!(err.to_string().contains("must start with"));
+
+    let err = Skill::parse("---\nname: x\nbody").unwrap_err();
+    assert!(err.to_string().contains("not closed"));
+
+    let err = Skill::parse("---\ndescription: no name\n---\n").unwrap_err();
+    assert!(err.to_string().contains("has no name"));
+
+    let err = Skill::parse("---\nname: x\ntemperature: hot\n---\n").unwrap_err();
+    assert!(err.to_string().contains("Invalid SKILL.md frontmatter"));
+}
+
+#[tokio::test]
+async fn test_active_skill_restricts_tools_and_model() {
+    let mut skills = SkillManager::new();
+    skills.add_skill(Skill {
+        name: "test-reader".to_string(),
+        description: "Read only".to_string(),
+        allowed_tools: vec![
+            "read_file".to_string(),
+            "grep_find".to_string(),
+            "write_file".to_string(),
+        ],
+        model: Some("mock-large".to_string()),
+        ..Skill::default()
+    });
+    skills.add_skill(Skill {
+        name: "test-no-write".to_string(),
+        description: "Never write".to_string(),
+        disallowed_tools: vec!["write_file".to_string()],
+        ..Skill::default()
+    });
+    skills.activate_skill("test-reader").unwrap();
+    skills.activate_skill("test-no-write").unwrap();
+
+    let turns = (0..2)
+        .map(|_| MockTurn {
+            text: Some("ok".to_string()),
+            ..MockTurn::default()
+        })
+        .collect();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(turns))
+        .with_memory(ProjectMemory::default())
+        .with_sub_agents(false)
+        .with_tool_progress(false)
+        .with_skill_manager(skills)
+        .build()
+        .unwrap();
+
+    assert_eq!(agent.effective_model(), "mock-large");
+    agent.chat("look around").await.unwrap();
+    assert!(agent
+        .disabled_tool_names()
+        .contains(&"exec_cmd".to_string()));
+
+    agent.skill_manager_mut().unwrap().deactivate_all_skills();
+    agent.chat("now anything").await.unwrap();
+    assert!(agent.disabled_tool_names().is_empty());
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    let requests = inner.model.requests();
+    let mut restricted: Vec<String> = requests[0].tools.iter().map(|t| t.name.clone()).collect();
+    restricted.sort();
+    assert_eq!(restricted, vec!["grep_find", "read_file"]);
+    assert!(requests[1].tools.len() > 2);
+    assert!(requests[1].tools.iter().any(|t| t.name == "write_file"));
+    assert_eq!(inner.model.request_models(), vec!["mock-large", "mock"]);
+}
@@ -0,0 +1,286 @@
+use kota::kota_code::agent::mock::{MockScript, MockTurn};
+use kota::kota_code::agent::AgentEvent;
+use kota::kota_code::AutoSelect;
+use kota::{AgentBuilder, AgentType, KotaConfig, ProjectMemory, Provider, Skill, SkillManager};
+use std::fs;
+use tempfile::TempDir;
+
+fn skill(name: &str, dependencies: &[&str]) -> Skill {
+    Skill {
+        name: name.to_string(),
+        description: format!("{} description", name),
+        instructions: format!("{} instructions", name),
+        dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+        ..Skill::default()
+    }
+}
+
+fn manager() -> SkillManager {
+    let mut manager = SkillManager::new();
+    manager.add_skill(skill("test-style", &[]));
+    manager.add_skill(skill("test-rust", &["test-style"]));
+    manager.add_skill(skill("test-review", &["test-rust", "test-style"]));
+    manager.add_skill(skill("test-docs", &[]));
+    manager
+}
+
+fn active(manager: &SkillManager) -> Vec<&str> {
+    manager
+        .get_active_skills()
+        .into_iter()
+        .map(|s| s.name.as_str())
+        .collect()
+}
+
+#[test]
+fn test_activate_with_dependencies() {
+    let mut manager = manager();
+
+    let activated = manager.activate_skill("test-review").unwrap();
+    assert_eq!(activated, vec!["test-style", "test-rust", "test-review"]);
+    assert_eq!(
+        active(&manager),
+        vec!["test-style", "test-rust", "test-review"]
+    );
+
+    // Only newly activated skills are reported
+    assert_eq!(
+        manager.activate_skill("test-rust").unwrap(),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        manager.activate_skill("test-docs").unwrap(),
+        vec!["test-docs"]
+    );
+
+    // A dependency stays active while another skill requires it
+    let err = manager.deactivate_skill("test-style").unwrap_err();
+    assert!(err.to_string().contains("required by 'test-review'"));
+
+    manager.deactivate_skill("test-review").unwrap();
+    assert_eq!(
+        active(&manager),
+        vec!["test-style", "test-rust", "test-docs"]
+    );
+    manager.deactivate_skill("test-rust").unwrap();
+    assert_eq!(active(&manager), vec!["test-docs"]);
+    assert!(manager.deactivate_skill("test-rust").is_err());
+
+    manager.deactivate_all_skills();
+    assert!(manager.get_active_skills().is_empty());
+}
+
+#[test]
+fn test_invalid_dependencies() {
+    let mut manager = manager();
+    manager.add_skill(skill("test-broken", &["test-missing"]));
+    manager.add_skill(skill("test-a", &["test-b"]));
+    manager.add_skill(skill("test-b", &["test-a"]));
+
+    let err = manager.activate_skill("test-broken").unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("'test-missing' required by 'test-broken' not found"));
+    let err = manager.activate_skill("test-a").unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("Circular skill dependency: test-a -> test-b -> test-a"));
+    assert!(manager.get_active_skills().is_empty());
+}
+
+#[tokio::test]
+async fn test_active_skills_in_system_preamble() {
+    let mut skills = manager();
+    skills.activate_skill("test-rust").unwrap();
+    skills.activate_skill("test-docs").unwrap();
+
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![
+            MockTurn {
+                text: Some("first".to_string()),
+                ..MockTurn::default()
+            },
+            MockTurn {
+                text: Some("second".to_string()),
+                ..MockTurn::default()
+            },
+        ]))
+        .with_preamble("Base prompt")
+        .with_memory(ProjectMemory::default())
+        .with_sub_agents(false)
+        .with_tool_progress(false)
+        .with_skill_manager(skills)
+        .build()
+        .unwrap();
+
+    agent.chat("hello").await.unwrap();
+    agent
+        .skill_manager_mut()
+        .unwrap()
+        .deactivate_skill("test-rust")
+        .unwrap();
+    agent.chat("again").await.unwrap();
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    let requests = inner.model.requests();
+    let first = requests[0].preamble.as_deref().unwrap();
+    assert!(first.starts_with("Base prompt"));
+    let style = first.find("test-style instructions").unwrap();
+    let rust = first.find("test-rust instructions").unwrap();
+    let docs = first.find("test-docs instructions").unwrap();
+    assert!(style < rust && rust < docs);
+    // Skills are no longer sent as a user message
+    assert_eq!(requests[0].chat_history.len(), 1);
+
+    let second = requests[1].preamble.as_deref().unwrap();
+    assert!(!second.contains("test-rust instructions"));
+    assert!(!second.contains("test-style instructions"));
+    assert!(second.contains("test-docs instructions"));
+}
+
+fn ranked_manager() -> SkillManager {
+    let mut manager = SkillManager::new();
+    manager.add_skill(Skill {
+        name: "test-profiler".to_string(),
+        description: "Profile slow programs and find performance bottlenecks".to_string(),
+        triggers: vec!["flame graph".to_string()],
+        ..Skill::default()
+    });
+    manager.add_skill(Skill {
+        name: "test-migrations".to_string(),
+        description: "Write database migrations and schema changes".to_string(),
+        instructions: "Always write a down migration".to_string(),
+        ..Skill::default()
+    });
+    manager
+}
+
+#[test]
+fn test_rank_skills() {
+    let manager = ranked_manager();
+
+    let ranked = manager.rank_skills("Why is the importer so slow? Find the bottleneck");
+    assert_eq!(ranked[0].name, "test-profiler");
+    assert!(ranked[0].keywords.contains(&"slow".to_string()));
+    assert!(ranked[0].keywords.contains(&"bottleneck".to_string()));
+    assert!(ranked[0].reason().contains("keywords"));
+
+    let ranked = manager.rank_skills("Render a Flame Graph of the server");
+    assert_eq!(ranked[0].name, "test-profiler");
+    assert_eq!(ranked[0].triggers, vec!["flame graph"]);
+    assert!(ranked[0].reason().contains("trigger \"flame graph\""));
+
+    // Triggers match whole words only
+    assert!(manager
+        .rank_skills("the flame graphics")
+        .iter()
+        .all(|m| m.triggers.is_empty()));
+}
+
+#[test]
+fn test_select_for_turn() {
+    let mut manager = ranked_manager();
+    let prompt = "Add a migration for the new database schema";
+
+    // Selection is opt-in
+    assert!(manager.select_for_turn(prompt).is_empty());
+
+    manager.set_auto_select(Some(AutoSelect {
+        max_skills: 1,
+        min_score: 1.0,
+    }));
+    let selected = manager.select_for_turn(prompt);
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].name, "test-migrations");
+    assert!(manager.is_active("test-migrations"));
+
+    manager.clear_turn_skills();
+    assert!(!manager.is_active("test-migrations"));
+
+    // Skills that are already active are not selected again
+    manager.activate_skill("test-migrations").unwrap();
+    assert!(manager
+        .select_for_turn(prompt)
+        .iter()
+        .all(|m| m.name != "test-migrations"));
+}
+
+#[tokio::test]
+async fn test_auto_selected_skills_last_one_turn() {
+    // Only the best match, the project's own skills are loaded as well
+    let skills = ranked_manager().with_auto_select(Some(AutoSelect {
+        max_skills: 1,
+        min_score: 1.0,
+    }));
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(vec![
+            MockTurn {
+                text: Some("done".to_string()),
+                ..MockTurn::default()
+            },
+            MockTurn {
+                text: Some("hi".to_string()),
+                ..MockTurn::default()
+            },
+        ]))
+        .with_preamble("Base prompt")
+        .with_memory(ProjectMemory::default())
+        .with_sub_agents(false)
+        .with_tool_progress(false)
+        .with_skill_manager(skills)
+        .build()
+        .unwrap();
+
+    let mut selected = Vec::new();
+    agent
+        .chat_with_events("Write the database migrations for users", |event| {
+            if let AgentEvent::SkillsSelected { skills } = event {
+                selected.extend(skills.iter().map(|m| m.name.clone()));
+            }
+        })
+        .await
+        .unwrap();
+    assert_eq!(selected, vec!["test-migrations"]);
+    assert!(!agent.skill_manager().unwrap().is_active("test-migrations"));
+
+    agent.chat("hello there").await.unwrap();
+
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    let requests = inner.model.requests();
+    assert!(requests[0]
+        .preamble
+        .as_deref()
+        .unwrap()
+        .contains("Always write a down migration"));
+    assert_eq!(requests[1].preamble.as_deref(), Some("Base prompt"));
+}
+
+#[test]
+fn test_lua_config_auto_skills() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.lua");
+
+    fs::write(&config_path, "kota.setup({ auto_skills = true })").unwrap();
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+    assert_eq!(config.auto_skills, Some(AutoSelect::default()));
+
+    fs::write(
+        &config_path,
+        "kota.setup({ auto_skills = { max_skills = 3, min_score = 2.5 } })",
+    )
+    .unwrap();
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+    assert_eq!(
+        config.auto_skills,
+        Some(AutoSelect {
+            max_skills: 3,
+            min_score: 2.5,
+        })
+    );
+
+    assert_eq!(KotaConfig::default().auto_skills, None);
+}
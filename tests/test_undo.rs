@@ -0,0 +1,271 @@
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn};
+use kota::kota_code::agent::UndoIncomplete;
+use kota::kota_code::file_history::{FileHistory, FileState};
+use kota::kota_code::store::{MemoryStore, SessionConflict, SessionStore};
+use kota::{AgentBuilder, AgentType, ContextManager, ProjectMemory, Provider};
+use rig::completion::Message;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn call(name: &str, arguments: serde_json::Value) -> MockToolCall {
+    MockToolCall {
+        id: None,
+        name: name.to_string(),
+        arguments,
+        expect_result: None,
+    }
+}
+
+fn tools(calls: Vec<MockToolCall>) -> MockTurn {
+    MockTurn {
+        tool_calls: calls,
+        ..MockTurn::default()
+    }
+}
+
+fn text(text: &str) -> MockTurn {
+    MockTurn {
+        text: Some(text.to_string()),
+        ..MockTurn::default()
+    }
+}
+
+fn path(dir: &TempDir, name: &str) -> String {
+    dir.path().join(name).to_string_lossy().to_string()
+}
+
+#[tokio::test]
+async fn test_undo_restores_files_of_the_last_turn() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("notes.txt"), "old notes").unwrap();
+    fs::write(temp_dir.path().join("gone.txt"), "keep me").unwrap();
+
+    let script = MockScript::new(vec![
+        tools(vec![call(
+            "write_file",
+            json!({ "file_path": path(&temp_dir, "notes.txt"), "content": "first" }),
+        )]),
+        text("Updated the notes"),
+        tools(vec![
+            call(
+                "write_file",
+                json!({ "file_path": path(&temp_dir, "notes.txt"), "content": "second" }),
+            ),
+            call(
+                "write_file",
+                json!({ "file_path": path(&temp_dir, "out/new/file.txt"), "content": "new" }),
+            ),
+            call(
+                "delete_file",
+                json!({ "file_path": path(&temp_dir, "gone.txt") }),
+            ),
+            call(
+                "make_dir",
+                json!({ "dir_path": path(&temp_dir, "build/cache") }),
+            ),
+        ]),
+        text("Reorganized everything"),
+    ]);
+    let context =
+        ContextManager::new(temp_dir.path().join("sessions"), "undo".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .with_context(context)
+        .build()
+        .unwrap();
+
+    agent.chat("update the notes").await.unwrap();
+    agent.chat("reorganize").await.unwrap();
+    assert!(!temp_dir.path().join("gone.txt").exists());
+    assert_eq!(agent.context().unwrap().get_messages().len(), 11);
+
+    let undo = agent.undo().unwrap().unwrap();
+    assert_eq!(undo.request.as_deref(), Some("reorganize"));
+    assert_eq!(undo.messages, 7);
+    assert!(undo.files.failed.is_empty());
+    assert_eq!(undo.files.restored.len(), 2);
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("notes.txt")).unwrap(),
+        "first"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("gone.txt")).unwrap(),
+        "keep me"
+    );
+    assert!(!temp_dir.path().join("out").exists());
+    assert!(!temp_dir.path().join("build").exists());
+
+    // The undone turn is gone from the saved session too
+    let mut saved =
+        ContextManager::new(temp_dir.path().join("sessions"), "undo".to_string()).unwrap();
+    assert!(saved.load().unwrap());
+    assert_eq!(saved.get_messages().len(), 4);
+
+    let undo = agent.undo().unwrap().unwrap();
+    assert_eq!(undo.request.as_deref(), Some("update the notes"));
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("notes.txt")).unwrap(),
+        "old notes"
+    );
+    assert!(agent.context().unwrap().get_messages().is_empty());
+    assert!(agent.undo().unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_undo_reports_restored_files_when_the_session_is_not_saved() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("notes.txt"), "old notes").unwrap();
+    let store: Arc<dyn SessionStore> = Arc::new(MemoryStore::new());
+
+    let script = MockScript::new(vec![
+        tools(vec![call(
+            "write_file",
+            json!({ "file_path": path(&temp_dir, "notes.txt"), "content": "new notes" }),
+        )]),
+        text("Updated the notes"),
+    ]);
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .with_context(ContextManager::from_store(
+            store.clone(),
+            "undo".to_string(),
+        ))
+        .build()
+        .unwrap();
+    agent.chat("update the notes").await.unwrap();
+
+    // Another process saves the session in the meantime
+    let mut theirs = ContextManager::from_store(store, "undo".to_string());
+    theirs.load().unwrap();
+    theirs.add_message(Message::user("Their turn"));
+    theirs.save().unwrap();
+
+    let error = agent.undo().unwrap_err();
+    let incomplete = error.downcast::<UndoIncomplete>().unwrap();
+    assert!(incomplete.error.is::<SessionConflict>());
+    assert_eq!(incomplete.undo.request.as_deref(), Some("update the notes"));
+    assert_eq!(
+        incomplete.undo.files.restored,
+        vec![temp_dir.path().join("notes.txt")]
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("notes.txt")).unwrap(),
+        "old notes"
+    );
+}
+
+#[tokio::test]
+async fn test_undo_restores_memory_written_by_remember() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join("KOTA.md"), "Use spaces").unwrap();
+    let memory = ProjectMemory::discover(&project, None).unwrap();
+
+    let script = MockScript::new(vec![
+        tools(vec![call(
+            "remember",
+            json!({ "fact": "The CI runs on nightly" }),
+        )]),
+        text("Noted"),
+    ]);
+    let context =
+        ContextManager::new(temp_dir.path().join("sessions"), "undo".to_string()).unwrap();
+    let mut agent = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(script)
+        .with_context(context)
+        .with_memory(memory)
+        .with_sub_agents(false)
+        .with_tool_progress(false)
+        .build()
+        .unwrap();
+
+    agent.chat("Remember that CI uses nightly").await.unwrap();
+    agent.reload_memory().unwrap();
+    assert!(fs::read_to_string(project.join("KOTA.md"))
+        .unwrap()
+        .contains("nightly"));
+
+    let undo = agent.undo().unwrap().unwrap();
+    assert_eq!(undo.files.restored, vec![project.join("KOTA.md")]);
+    assert_eq!(
+        fs::read_to_string(project.join("KOTA.md")).unwrap(),
+        "Use spaces"
+    );
+    let AgentType::Mock(inner) = &agent.agent else {
+        panic!("expected the mock provider");
+    };
+    assert!(!inner.preamble.as_deref().unwrap().contains("nightly"));
+}
+
+#[test]
+fn test_file_history_snapshots() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = temp_dir.path().join("a.txt");
+    fs::write(&file, "before").unwrap();
+    let history = FileHistory::new();
+
+    // Nothing is recorded outside of a turn
+    history.record(&file);
+    assert!(history.undo("s").is_none());
+
+    history.begin_turn("s");
+    let nested = history.nested();
+    nested.begin_turn("child");
+    nested.record(&file);
+    fs::write(&file, "changed").unwrap();
+    history.record(&file);
+    fs::write(&file, "changed twice").unwrap();
+    let dir = temp_dir.path().join("new/dir");
+    history.record(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    // A file the tools did not create keeps its directory
+    fs::write(temp_dir.path().join("new/other.txt"), "").unwrap();
+    assert_eq!(history.turns("s"), 1);
+    assert_eq!(history.turns("child"), 0);
+
+    let (turn, restore) = history.undo("s").unwrap();
+    let states: Vec<(&Path, &FileState)> = turn
+        .snapshots
+        .iter()
+        .map(|snapshot| (snapshot.path.as_path(), &snapshot.before))
+        .collect();
+    assert_eq!(
+        states,
+        vec![
+            (file.as_path(), &FileState::File(b"before".to_vec())),
+            (temp_dir.path().join("new").as_path(), &FileState::Missing),
+            (dir.as_path(), &FileState::Missing),
+        ]
+    );
+    assert_eq!(fs::read_to_string(&file).unwrap(), "before");
+    assert_eq!(restore.restored, vec![file.clone()]);
+    assert_eq!(restore.removed, vec![dir.clone()]);
+    assert_eq!(restore.failed.len(), 1);
+    assert_eq!(restore.failed[0].0, temp_dir.path().join("new"));
+}
+
+#[test]
+fn test_context_undo_last_turn() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut context = ContextManager::new(temp_dir.path(), "undo".to_string()).unwrap();
+    assert!(context.undo_last_turn().is_empty());
+
+    context.add_message(Message::user("first"));
+    context.add_message(Message::assistant("one"));
+    context.add_message(Message::user("second"));
+    context.add_message(Message::assistant("two"));
+    context.pin_message(3);
+
+    let removed = context.undo_last_turn();
+    assert_eq!(removed.len(), 2);
+    assert_eq!(context.get_messages().len(), 2);
+    assert!(!context.is_pinned(3));
+    assert!(context.is_pinned(0));
+
+    assert_eq!(context.undo_last_turn().len(), 2);
+    assert!(context.get_messages().is_empty());
+}
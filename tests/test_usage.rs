@@ -0,0 +1,247 @@
+use kota::kota_code::agent::mock::{MockScript, MockToolCall, MockTurn, MockUsage};
+use kota::{
+    AgentBuilder, AgentInstance, Budget, BudgetExceeded, BudgetStatus, ContextManager, KotaConfig,
+    ModelPrice, PriceTable, Provider, TokenUsage,
+};
+use serde_json::json;
+use std::fs;
+use tempfile::TempDir;
+
+fn answer(text: &str, input_tokens: u64, output_tokens: u64, cached: u64) -> MockTurn {
+    MockTurn {
+        text: Some(text.to_string()),
+        usage: Some(MockUsage {
+            input_tokens,
+            output_tokens,
+            cached_input_tokens: cached,
+        }),
+        ..MockTurn::default()
+    }
+}
+
+fn prices() -> PriceTable {
+    let mut prices = PriceTable::default();
+    prices.insert(
+        "mock",
+        ModelPrice {
+            input: 2.0,
+            output: 10.0,
+            cached_input: Some(1.0),
+        },
+    );
+    prices
+}
+
+fn mock_agent(temp_dir: &TempDir, turns: Vec<MockTurn>, budget: Option<Budget>) -> AgentInstance {
+    let context = ContextManager::new(temp_dir.path(), "usage-session".to_string()).unwrap();
+    let mut builder = AgentBuilder::for_provider(Provider::Mock, String::new(), "mock".to_string())
+        .with_mock_script(MockScript::new(turns))
+        .with_context(context)
+        .with_price_table(prices())
+        .with_tool_progress(false);
+    if let Some(budget) = budget {
+        builder = builder.with_budget(budget);
+    }
+    builder.build().unwrap()
+}
+
+#[tokio::test]
+async fn test_usage_is_tracked_per_turn_and_persisted() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut agent = mock_agent(
+        &temp_dir,
+        vec![answer("one", 1000, 100, 400), answer("two", 2000, 200, 0)],
+        None,
+    );
+
+    agent.chat("first").await.unwrap();
+    agent.chat("second").await.unwrap();
+
+    let usage = agent.session_usage().unwrap();
+    assert_eq!(usage.turns.len(), 2);
+    assert_eq!(usage.turns[0].model, "mock");
+    assert_eq!(
+        usage.total,
+        TokenUsage {
+            input_tokens: 3000,
+            output_tokens: 300,
+            cached_input_tokens: 400,
+        }
+    );
+
+    // 600 uncached * $2 + 400 cached * $1 + 100 out * $10, then 2000 * $2 + 200 * $10
+    let cost = agent.session_cost().unwrap();
+    assert!((cost - 0.0086).abs() < 1e-9, "cost was {}", cost);
+
+    let mut reloaded = ContextManager::new(temp_dir.path(), "usage-session".to_string()).unwrap();
+    reloaded.load().unwrap();
+    assert_eq!(reloaded.usage(), usage);
+
+    let sessions = reloaded.list_sessions().unwrap();
+    assert_eq!(sessions[0].usage.total.total_tokens(), 3300);
+}
+
+#[tokio::test]
+async fn test_usage_of_failed_turn_is_recorded() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut agent = mock_agent(
+        &temp_dir,
+        vec![MockTurn {
+            tool_calls: vec![MockToolCall {
+                id: None,
+                name: "list_dir".to_string(),
+                arguments: json!({ "dir_path": temp_dir.path().to_string_lossy() }),
+                expect_result: None,
+            }],
+            ..answer("Looking around", 1000, 100, 0)
+        }],
+        None,
+    );
+
+    // The script has no answer for the second completion call, so the turn fails
+    assert!(agent.chat("list the files").await.is_err());
+
+    let usage = agent.session_usage().unwrap();
+    assert_eq!(usage.turns.len(), 1);
+    assert_eq!(usage.turns[0].model, "mock");
+    assert_eq!(usage.total.input_tokens, 1000);
+    assert_eq!(usage.total.output_tokens, 100);
+}
+
+#[tokio::test]
+async fn test_usage_of_tool_only_calls_is_recorded() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut agent = mock_agent(
+        &temp_dir,
+        vec![
+            MockTurn {
+                tool_calls: vec![MockToolCall {
+                    id: None,
+                    name: "list_dir".to_string(),
+                    arguments: json!({ "dir_path": temp_dir.path().to_string_lossy() }),
+                    expect_result: None,
+                }],
+                usage: Some(MockUsage {
+                    input_tokens: 1000,
+                    output_tokens: 100,
+                    cached_input_tokens: 0,
+                }),
+                ..MockTurn::default()
+            },
+            answer("Nothing here", 1200, 50, 0),
+        ],
+        None,
+    );
+
+    agent.chat("list the files").await.unwrap();
+
+    // The completion call without text sends no usage event of its own
+    let usage = agent.session_usage().unwrap();
+    assert_eq!(usage.turns.len(), 1);
+    assert_eq!(usage.total.input_tokens, 2200);
+    assert_eq!(usage.total.output_tokens, 150);
+}
+
+#[tokio::test]
+async fn test_budget_warns_then_refuses_turns() {
+    let temp_dir = TempDir::new().unwrap();
+    let budget = Budget {
+        max_tokens: Some(1000),
+        ..Budget::default()
+    };
+    let mut agent = mock_agent(
+        &temp_dir,
+        vec![
+            answer("one", 800, 50, 0),
+            answer("two", 300, 50, 0),
+            answer("unreachable", 10, 10, 0),
+        ],
+        Some(budget),
+    );
+
+    agent.chat("first").await.unwrap();
+    assert!(matches!(
+        agent.budget_status(),
+        BudgetStatus::Warning { .. }
+    ));
+
+    // The budget is checked before a turn starts, so this one still runs
+    agent.chat("second").await.unwrap();
+    assert!(matches!(
+        agent.budget_status(),
+        BudgetStatus::Exceeded { .. }
+    ));
+
+    let err = agent.chat("third").await.unwrap_err();
+    assert!(err.is::<BudgetExceeded>());
+    let messages = agent.context().unwrap().get_messages().len();
+    assert_eq!(messages, 4);
+}
+
+#[test]
+fn test_price_table_and_cost_budget() {
+    let prices = prices();
+    let usage = TokenUsage {
+        input_tokens: 1_000_000,
+        output_tokens: 0,
+        cached_input_tokens: 0,
+    };
+    assert_eq!(prices.cost("mock", &usage), Some(2.0));
+    assert_eq!(prices.cost("unknown-model", &usage), None);
+
+    let mut session = kota::SessionUsage::default();
+    session.record("mock", usage);
+    let budget = Budget {
+        max_cost: Some(4.0),
+        ..Budget::default()
+    };
+    assert_eq!(budget.status(&session, &prices), BudgetStatus::Ok);
+    let remaining = budget.remaining(&session, &prices);
+    assert_eq!(remaining.max_cost, Some(2.0));
+    assert_eq!(remaining.max_tokens, None);
+    session.record("mock", usage);
+    assert_eq!(budget.remaining(&session, &prices).max_cost, Some(0.0));
+    assert!(matches!(
+        budget.status(&session, &prices),
+        BudgetStatus::Exceeded { .. }
+    ));
+    assert_eq!(
+        Budget::default().status(&session, &prices),
+        BudgetStatus::Ok
+    );
+}
+
+#[test]
+fn test_lua_config_prices_and_budget() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.lua");
+    fs::write(
+        &config_path,
+        r#"
+kota.setup({
+  model = "gpt-4o",
+  api_key = "test-key",
+  prices = {
+    ["gpt-4o"] = { input = 2.5, output = 10, cached_input = 1.25 },
+  },
+  budget = { max_cost = 5, warn_at = 0.9 },
+})
+"#,
+    )
+    .unwrap();
+
+    let config = KotaConfig::from_lua_file(&config_path).unwrap();
+
+    assert_eq!(
+        config.prices.get("gpt-4o"),
+        Some(&ModelPrice {
+            input: 2.5,
+            output: 10.0,
+            cached_input: Some(1.25),
+        })
+    );
+    let budget = config.budget.unwrap();
+    assert_eq!(budget.max_cost, Some(5.0));
+    assert_eq!(budget.max_tokens, None);
+    assert_eq!(budget.warn_at, 0.9);
+}